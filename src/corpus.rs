@@ -0,0 +1,246 @@
+// corpus.rs - Regression suites for selectors
+//
+// A `Corpus` holds named documents and test cases, each a selector with its expected
+// output. Running it evaluates every case against its document (or against every
+// document when the case names none) and reports each outcome instead of stopping at
+// the first failure, so a whole suite can be checked and summarised in one go.
+
+use std::fmt;
+
+use super::{Document, InterpreterError, SelectionResult};
+
+/// Expected (or actual) output of a selector
+#[derive(Debug, Clone, PartialEq)]
+pub enum Output {
+    /// Texts in order
+    Texts(Vec<String>),
+
+    /// Number of results, for selectors returning nodes
+    Count(usize),
+}
+
+/// A selector together with the output it should produce
+#[derive(Debug, Clone, PartialEq)]
+pub struct Case {
+    /// Name used in the report
+    pub name: String,
+
+    /// Document to run against, every document of the corpus if `None`
+    pub document: Option<String>,
+
+    /// Selector under test
+    pub selector: String,
+
+    /// Expected output
+    pub expected: Output,
+}
+
+/// Outcome of one case on one document
+#[derive(Debug, Clone)]
+pub enum Outcome {
+    /// The selector produced the expected output
+    Passed,
+
+    /// The selector produced something else
+    Failed { actual: Output },
+
+    /// The selector could not be parsed or evaluated
+    Error(InterpreterError),
+
+    /// The case names a document the corpus does not have
+    UnknownDocument,
+}
+
+/// Result of one case on one document
+#[derive(Debug, Clone)]
+pub struct CaseResult {
+    /// Name of the case
+    pub case: String,
+
+    /// Name of the document
+    pub document: String,
+
+    /// Selector under test
+    pub selector: String,
+
+    /// Expected output
+    pub expected: Output,
+
+    /// What happened
+    pub outcome: Outcome,
+}
+
+/// Results of a corpus run, in the order the cases were added
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub results: Vec<CaseResult>,
+}
+
+/// Named documents and the cases to run against them
+#[derive(Debug, Clone, Default)]
+pub struct Corpus {
+    documents: Vec<(String, Document)>,
+    cases: Vec<Case>,
+}
+
+impl Case {
+    /// Expect the selector to produce exactly these texts.
+    pub fn texts<S: Into<String>>(
+        name: &str,
+        selector: &str,
+        expected: impl IntoIterator<Item = S>,
+    ) -> Self {
+        Case {
+            name: name.to_string(),
+            document: None,
+            selector: selector.to_string(),
+            expected: Output::Texts(expected.into_iter().map(Into::into).collect()),
+        }
+    }
+
+    /// Expect the selector to produce this many results.
+    pub fn count(name: &str, selector: &str, expected: usize) -> Self {
+        Case {
+            name: name.to_string(),
+            document: None,
+            selector: selector.to_string(),
+            expected: Output::Count(expected),
+        }
+    }
+
+    /// Run the case against a single named document only.
+    pub fn on(mut self, document: &str) -> Self {
+        self.document = Some(document.to_string());
+        self
+    }
+}
+
+impl Corpus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a document under a name that cases can refer to.
+    pub fn document(mut self, name: &str, document: Document) -> Self {
+        self.documents.push((name.to_string(), document));
+        self
+    }
+
+    /// Add a case.
+    pub fn case(mut self, case: Case) -> Self {
+        self.cases.push(case);
+        self
+    }
+
+    /// Evaluate every case and collect the outcomes.
+    pub fn run(&self) -> Report {
+        let mut results = Vec::new();
+
+        for case in &self.cases {
+            let documents: Vec<&(String, Document)> = match &case.document {
+                Some(name) => self.documents.iter().filter(|(n, _)| n == name).collect(),
+                None => self.documents.iter().collect(),
+            };
+
+            if documents.is_empty() {
+                results.push(CaseResult::new(
+                    case,
+                    case.document.clone().unwrap_or_default(),
+                    Outcome::UnknownDocument,
+                ));
+                continue;
+            }
+
+            for (name, document) in documents {
+                let outcome = match document.query().query(&case.selector).result() {
+                    Ok(result) => compare(&case.expected, &result),
+                    Err(e) => Outcome::Error(e),
+                };
+                results.push(CaseResult::new(case, name.clone(), outcome));
+            }
+        }
+
+        Report { results }
+    }
+}
+
+impl CaseResult {
+    fn new(case: &Case, document: String, outcome: Outcome) -> Self {
+        CaseResult {
+            case: case.name.clone(),
+            document,
+            selector: case.selector.clone(),
+            expected: case.expected.clone(),
+            outcome,
+        }
+    }
+
+    /// Check if the case passed
+    pub fn passed(&self) -> bool {
+        matches!(self.outcome, Outcome::Passed)
+    }
+}
+
+impl Report {
+    /// Number of passed results
+    pub fn passed(&self) -> usize {
+        self.results.iter().filter(|r| r.passed()).count()
+    }
+
+    /// Results that did not pass
+    pub fn failures(&self) -> impl Iterator<Item = &CaseResult> {
+        self.results.iter().filter(|r| !r.passed())
+    }
+
+    /// Check if every case passed
+    pub fn is_success(&self) -> bool {
+        self.results.iter().all(CaseResult::passed)
+    }
+}
+
+/// Compare a result with the expected output. Text expectations need a text result;
+/// count expectations accept either kind.
+fn compare(expected: &Output, result: &SelectionResult) -> Outcome {
+    let actual = match (expected, result) {
+        (Output::Texts(_), SelectionResult::Texts(texts, _)) => Output::Texts(texts.clone()),
+        _ => Output::Count(result.count()),
+    };
+
+    if &actual == expected {
+        Outcome::Passed
+    } else {
+        Outcome::Failed { actual }
+    }
+}
+
+impl fmt::Display for Output {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Output::Texts(texts) => write!(f, "{:?}", texts),
+            Output::Count(count) => write!(f, "{} result(s)", count),
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for result in self.failures() {
+            write!(f, "FAIL {} [{}] `{}`: ", result.case, result.document, result.selector)?;
+            match &result.outcome {
+                Outcome::Failed { actual } => {
+                    writeln!(f, "expected {}, got {}", result.expected, actual)?
+                }
+                Outcome::Error(e) => writeln!(f, "{}", e)?,
+                Outcome::UnknownDocument => writeln!(f, "no such document")?,
+                Outcome::Passed => writeln!(f)?,
+            }
+        }
+
+        write!(
+            f,
+            "{} passed, {} failed",
+            self.passed(),
+            self.results.len() - self.passed()
+        )
+    }
+}
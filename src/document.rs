@@ -0,0 +1,210 @@
+// document.rs - Parsed documents shared between queries
+//
+// A `Document` owns nothing but a handle to the immutable, `Arc`-shared tree, so it is
+// cheap to clone and can back any number of `Query` views, each with its own options
+// and cache, on any thread.
+
+use std::fs::File;
+use std::io::Read;
+use std::any::Any;
+use std::path::Path;
+
+use super::extract::page_kind::{self, PageClass};
+use super::interpreter::ext::Extensions;
+use super::interpreter::{charset, html, rewrite, serialize};
+use super::metadata::{self, Metadata};
+use super::{HtmlWarning, InterpreterResult, NodeHandle, Query, SerializeOptions};
+
+/// A parsed HTML (or XML) document
+#[derive(Debug, Clone)]
+pub struct Document {
+    /// Document root
+    root: NodeHandle,
+
+    /// User data, see `set_ext`
+    ext: Extensions,
+}
+
+impl Document {
+    /// Parse an HTML document.
+    pub fn parse(html: &str) -> InterpreterResult<Self> {
+        Ok(Document {
+            root: html::parse_html(html)?,
+            ext: Extensions::default(),
+        })
+    }
+
+    /// Parse an HTML document read incrementally from a reader, decoded as UTF-8.
+    pub fn from_reader<R: Read>(mut reader: R) -> InterpreterResult<Self> {
+        Ok(Document {
+            root: html::parse_html_from_reader(&mut reader)?,
+            ext: Extensions::default(),
+        })
+    }
+
+    /// Parse an HTML file.
+    pub fn from_file(path: impl AsRef<Path>) -> InterpreterResult<Self> {
+        Self::from_reader(File::open(path)?)
+    }
+
+    /// Parse raw bytes, detecting the character encoding from a byte order mark or
+    /// `<meta charset>` declaration.
+    pub fn from_bytes(bytes: &[u8]) -> InterpreterResult<Self> {
+        Self::parse(&charset::decode(bytes))
+    }
+
+    /// Parse an XML document.
+    #[cfg(feature = "xml")]
+    pub fn parse_xml(xml: &str) -> InterpreterResult<Self> {
+        Ok(Document {
+            root: html::parse_xml(xml)?,
+            ext: Extensions::default(),
+        })
+    }
+
+    /// Wrap the root of an already parsed tree.
+    pub fn from_root(root: NodeHandle) -> Self {
+        Document {
+            root,
+            ext: Extensions::default(),
+        }
+    }
+
+    /// Document root node
+    pub fn root(&self) -> &NodeHandle {
+        &self.root
+    }
+
+    /// Attach a value, such as the URL the document was fetched from, replacing any
+    /// earlier value of the same type. Queries created over the document can read it
+    /// with `Query::get_ext`.
+    pub fn set_ext<T: Any + Send + Sync>(mut self, value: T) -> Self {
+        self.ext.insert(value);
+        self
+    }
+
+    /// The attached value of type `T`
+    pub fn get_ext<T: Any + Send + Sync>(&self) -> Option<&T> {
+        self.ext.get()
+    }
+
+    pub(crate) fn ext(&self) -> &Extensions {
+        &self.ext
+    }
+
+    pub(crate) fn with_ext(mut self, ext: Extensions) -> Self {
+        self.ext = ext;
+        self
+    }
+
+    /// Errors the parser recovered from while building the document.
+    pub fn warnings(&self) -> &[HtmlWarning] {
+        self.root.dom().warnings()
+    }
+
+    /// Fail with the parser's messages if it had to recover from malformed markup.
+    pub fn ensure_well_formed(&self) -> InterpreterResult<()> {
+        self.root.dom().ensure_well_formed()
+    }
+
+    /// Title, description, canonical URL, OpenGraph and Twitter card properties.
+    pub fn metadata(&self) -> Metadata {
+        metadata::extract(&self.root)
+    }
+
+    /// Guess whether the page is regular content or an error page, login wall, captcha
+    /// or near-empty placeholder, to skip extraction on pages that have nothing to offer.
+    pub fn page_class(&self) -> PageClass {
+        page_kind::classify(&self.root)
+    }
+
+    /// Serialize the document back to HTML.
+    pub fn to_html(&self, options: &SerializeOptions) -> String {
+        serialize::to_html(&self.root, options)
+    }
+
+    /// Copy the document with the URLs in `href`, `src` and `srcset` attributes and in
+    /// `url()` references of `style` attributes replaced by the result of `rewrite`.
+    pub fn rewrite_urls<F: FnMut(&str) -> String>(&self, rewrite: F) -> Document {
+        Document {
+            root: rewrite::rewrite_urls(&self.root, rewrite),
+            ext: self.ext.clone(),
+        }
+    }
+
+    /// Create a query over this document with default options and an empty cache.
+    pub fn query(&self) -> Query {
+        Query::on(self)
+    }
+
+    /// Traverse every node of the document without recursion, yielding an `Enter` event
+    /// before a node's descendants and a `Leave` event after them, together with the
+    /// node's depth (0 for the root). `Enter` events alone give pre-order, `Leave`
+    /// events alone post-order.
+    pub fn walk(&self) -> Walk {
+        Walk::new(&self.root)
+    }
+}
+
+/// Traversal event reported by `Document::walk`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WalkEvent {
+    /// Before the node's descendants
+    Enter,
+
+    /// After the node's descendants
+    Leave,
+}
+
+/// Depth-first traversal of a subtree, see `Document::walk`
+#[derive(Debug, Clone)]
+pub struct Walk {
+    root: NodeHandle,
+
+    /// Index of the next node to enter
+    next: usize,
+
+    /// Indices of the entered nodes that have not been left yet
+    open: Vec<usize>,
+}
+
+impl Walk {
+    /// Traverse the subtree of `root`, including `root` itself
+    pub fn new(root: &NodeHandle) -> Self {
+        Walk {
+            root: root.clone(),
+            next: root.index(),
+            open: Vec::new(),
+        }
+    }
+
+    fn handle(&self, index: usize) -> NodeHandle {
+        NodeHandle::new(self.root.dom().clone(), index)
+    }
+}
+
+impl Iterator for Walk {
+    type Item = (NodeHandle, usize, WalkEvent);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let dom = self.root.dom();
+
+        // Nodes are stored in pre-order, so a node is left once the next index falls
+        // outside its subtree
+        if let Some(&top) = self.open.last()
+            && dom.node(top).subtree_end <= self.next
+        {
+            self.open.pop();
+            return Some((self.handle(top), self.open.len(), WalkEvent::Leave));
+        }
+
+        if self.next >= self.root.node().subtree_end {
+            return None;
+        }
+
+        let index = self.next;
+        self.next += 1;
+        self.open.push(index);
+        Some((self.handle(index), self.open.len() - 1, WalkEvent::Enter))
+    }
+}
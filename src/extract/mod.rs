@@ -0,0 +1,9 @@
+// extract/mod.rs - Extractors for common page structures
+//
+// Helpers that read well-known conventions out of a parsed document directly, without
+// going through the selector language.
+
+#[cfg(feature = "structured-data")]
+pub mod structured_data;
+
+pub mod page_kind;
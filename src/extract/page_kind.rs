@@ -0,0 +1,268 @@
+// extract/page_kind.rs - Detection of pages not worth extracting from
+//
+// Crawls fetch many pages that are served with a success status but carry no content:
+// "not found" pages, login walls, captcha challenges and near-empty placeholders. These
+// are recognised from phrases in the title and visible text and from a few telltale
+// elements. The checks are heuristics for English pages; the signals that decided the
+// classification are reported so callers can judge or log them.
+
+use std::fmt;
+
+use crate::interpreter::dom::NodeData;
+use crate::interpreter::html;
+use crate::metadata;
+use crate::{NodeHandle, Walk, WalkEvent};
+
+/// Visible words below which a page counts as boilerplate
+const MIN_CONTENT_WORDS: usize = 30;
+
+/// Visible words above which phrases in the body text alone do not mark a captcha,
+/// error or login page, since articles can mention them
+const MAX_SHORT_PAGE_WORDS: usize = 400;
+
+const CAPTCHA_PHRASES: &[&str] = &[
+    "captcha",
+    "are you a robot",
+    "not a robot",
+    "verify you are human",
+    "verify that you are human",
+    "unusual traffic",
+    "checking your browser",
+    "checking if the site connection is secure",
+    "just a moment...",
+];
+
+/// Classes, ids and script URLs of captcha widgets
+const CAPTCHA_MARKERS: &[&str] = &[
+    "g-recaptcha",
+    "h-captcha",
+    "cf-turnstile",
+    "cf-challenge",
+    "recaptcha/api.js",
+    "hcaptcha.com",
+    "challenges.cloudflare.com",
+];
+
+const ERROR_TITLE_PHRASES: &[&str] = &[
+    "404",
+    "not found",
+    "page not found",
+    "403 forbidden",
+    "access denied",
+    "500 internal server error",
+    "service unavailable",
+    "page unavailable",
+    "no longer available",
+];
+
+const ERROR_TEXT_PHRASES: &[&str] = &[
+    "page not found",
+    "page you requested",
+    "page you are looking for",
+    "page you were looking for",
+    "could not be found",
+    "couldn't be found",
+    "does not exist",
+    "doesn't exist",
+    "no longer available",
+    "has been removed",
+    "404 error",
+];
+
+const LOGIN_PHRASES: &[&str] = &[
+    "log in",
+    "login",
+    "sign in",
+    "signin",
+    "log on",
+    "please log in to continue",
+    "you must be logged in",
+];
+
+const BOILERPLATE_PHRASES: &[&str] = &[
+    "domain is for sale",
+    "buy this domain",
+    "coming soon",
+    "under construction",
+    "enable javascript",
+    "javascript is disabled",
+    "javascript is required",
+];
+
+/// What a page appears to be
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageKind {
+    /// A page with regular content
+    Content,
+
+    /// A "not found" or other error page
+    Error,
+
+    /// A page asking to log in before showing content
+    Login,
+
+    /// A captcha or bot check
+    Captcha,
+
+    /// A placeholder with little or no content, such as a parked domain or a page that
+    /// needs JavaScript
+    Boilerplate,
+}
+
+/// Classification of a page and the signals it is based on
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageClass {
+    pub kind: PageKind,
+
+    /// Human-readable reasons, e.g. `title contains "not found"`
+    pub signals: Vec<String>,
+}
+
+impl PageClass {
+    /// Check whether the page looks like regular content
+    pub fn is_content(&self) -> bool {
+        self.kind == PageKind::Content
+    }
+}
+
+/// Classify the document `root` belongs to
+pub fn classify(root: &NodeHandle) -> PageClass {
+    let root = root.root();
+    let title = metadata::extract(&root).title.unwrap_or_default().to_lowercase();
+    let text = visible_text(&root).to_lowercase();
+    let words = text.split_whitespace().count();
+    let short = words <= MAX_SHORT_PAGE_WORDS;
+
+    let mut signals = Vec::new();
+
+    // Captcha pages are checked first, as they often also have little text
+    find_phrases("title", &title, CAPTCHA_PHRASES, &mut signals);
+    if short {
+        find_phrases("text", &text, CAPTCHA_PHRASES, &mut signals);
+    }
+    find_captcha_widgets(&root, &mut signals);
+    if !signals.is_empty() {
+        return PageClass {
+            kind: PageKind::Captcha,
+            signals,
+        };
+    }
+
+    find_phrases("title", &title, ERROR_TITLE_PHRASES, &mut signals);
+    if short {
+        find_phrases("text", &text, ERROR_TEXT_PHRASES, &mut signals);
+    }
+    if !signals.is_empty() {
+        return PageClass {
+            kind: PageKind::Error,
+            signals,
+        };
+    }
+
+    if has_password_field(&root) {
+        let mut login_signals = vec!["password field".to_string()];
+        find_phrases("title", &title, LOGIN_PHRASES, &mut login_signals);
+        if login_signals.len() > 1 || short {
+            return PageClass {
+                kind: PageKind::Login,
+                signals: login_signals,
+            };
+        }
+    }
+
+    find_phrases("text", &text, BOILERPLATE_PHRASES, &mut signals);
+    if words < MIN_CONTENT_WORDS {
+        signals.push(format!("only {} visible words", words));
+    }
+    if short && !signals.is_empty() {
+        return PageClass {
+            kind: PageKind::Boilerplate,
+            signals,
+        };
+    }
+
+    PageClass {
+        kind: PageKind::Content,
+        signals: Vec::new(),
+    }
+}
+
+/// Text of the visible text nodes, separated by spaces
+fn visible_text(root: &NodeHandle) -> String {
+    let mut text = String::new();
+    for (node, _, event) in Walk::new(root) {
+        if event != WalkEvent::Enter {
+            continue;
+        }
+        if let NodeData::Text { contents } = node.data()
+            && node.parent().is_some_and(|parent| html::is_visible(&parent))
+        {
+            text.push_str(contents);
+            text.push(' ');
+        }
+    }
+    text
+}
+
+fn find_phrases(place: &str, text: &str, phrases: &[&str], signals: &mut Vec<String>) {
+    for phrase in phrases {
+        if contains_phrase(text, phrase) {
+            signals.push(format!("{} contains \"{}\"", place, phrase));
+        }
+    }
+}
+
+/// Check whether `phrase` occurs in `text` as whole words
+fn contains_phrase(text: &str, phrase: &str) -> bool {
+    text.match_indices(phrase).any(|(start, _)| {
+        let before = text[..start].chars().next_back();
+        let after = text[start + phrase.len()..].chars().next();
+        !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+    })
+}
+
+fn find_captcha_widgets(root: &NodeHandle, signals: &mut Vec<String>) {
+    for (node, _, event) in Walk::new(root) {
+        if event != WalkEvent::Enter {
+            continue;
+        }
+        for attr in node.attrs() {
+            if !matches!(attr.name.as_str(), "class" | "id" | "src") {
+                continue;
+            }
+            let value = attr.value.to_lowercase();
+            if let Some(marker) = CAPTCHA_MARKERS.iter().find(|m| value.contains(*m)) {
+                let signal = format!("element with {} \"{}\"", attr.name, marker);
+                if !signals.contains(&signal) {
+                    signals.push(signal);
+                }
+            }
+        }
+    }
+}
+
+fn has_password_field(root: &NodeHandle) -> bool {
+    root.dom()
+        .elements_by_tag("input", root.index())
+        .iter()
+        .map(|index| NodeHandle::new(root.dom().clone(), *index))
+        .any(|input| {
+            input
+                .attrs()
+                .iter()
+                .any(|a| a.name == "type" && a.value.trim().eq_ignore_ascii_case("password"))
+        })
+}
+
+impl fmt::Display for PageKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            PageKind::Content => "content",
+            PageKind::Error => "error",
+            PageKind::Login => "login",
+            PageKind::Captcha => "captcha",
+            PageKind::Boilerplate => "boilerplate",
+        };
+        write!(f, "{}", name)
+    }
+}
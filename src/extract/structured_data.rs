@@ -0,0 +1,148 @@
+// extract/structured_data.rs - JSON-LD and microdata extraction
+//
+// JSON-LD blocks are parsed as they are. Microdata items are converted to JSON objects
+// in the usual shape: `@type` and `@id` from `itemtype`/`itemid`, and one key per
+// property, whose value is an array when the property occurs more than once. Nested
+// `itemscope` elements become nested objects. `itemref` is not followed.
+
+use serde_json::{Map, Value};
+
+use crate::interpreter::html;
+use crate::NodeHandle;
+
+/// Structured data found in a document
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StructuredData {
+    /// Contents of `<script type="application/ld+json">` blocks, in document order.
+    /// Blocks that are not valid JSON are skipped.
+    pub json_ld: Vec<Value>,
+
+    /// Top-level microdata items, in document order
+    pub microdata: Vec<Value>,
+}
+
+/// Extract JSON-LD blocks and microdata items within the subtree of `root`
+pub fn extract(root: &NodeHandle) -> StructuredData {
+    StructuredData {
+        json_ld: json_ld(root),
+        microdata: microdata(root),
+    }
+}
+
+/// Parse the JSON-LD blocks within the subtree of `root`
+pub fn json_ld(root: &NodeHandle) -> Vec<Value> {
+    elements(root)
+        .filter(|node| node.tag_name() == Some("script"))
+        .filter(|node| {
+            attr(node, "type").is_some_and(|t| t.trim().eq_ignore_ascii_case("application/ld+json"))
+        })
+        .filter_map(|node| html::extract_text(&node).ok())
+        .filter_map(|text| serde_json::from_str(text.trim()).ok())
+        .collect()
+}
+
+/// Convert the top-level microdata items within the subtree of `root`: elements with
+/// `itemscope` that are not themselves a property of another item
+pub fn microdata(root: &NodeHandle) -> Vec<Value> {
+    elements(root)
+        .filter(|node| attr(node, "itemscope").is_some() && attr(node, "itemprop").is_none())
+        .map(|node| item(&node))
+        .collect()
+}
+
+/// Element nodes of a subtree in document order, including `root`
+fn elements(root: &NodeHandle) -> impl Iterator<Item = NodeHandle> + '_ {
+    let dom = root.dom();
+    (root.index()..root.node().subtree_end)
+        .map(|index| NodeHandle::new(dom.clone(), index))
+        .filter(|node| node.is_element())
+}
+
+/// Convert an `itemscope` element into an object
+fn item(scope: &NodeHandle) -> Value {
+    let mut object = Map::new();
+
+    if let Some(item_type) = attr(scope, "itemtype") {
+        let types: Vec<&str> = item_type.split_whitespace().collect();
+        match types.as_slice() {
+            [] => {}
+            [single] => {
+                object.insert("@type".to_string(), Value::from(*single));
+            }
+            _ => {
+                object.insert("@type".to_string(), Value::from(types));
+            }
+        }
+    }
+    if let Some(id) = attr(scope, "itemid") {
+        object.insert("@id".to_string(), Value::from(id.trim()));
+    }
+
+    let mut properties = Vec::new();
+    collect_properties(scope, &mut properties);
+
+    for (name, value) in properties {
+        match object.get_mut(&name) {
+            Some(Value::Array(values)) => values.push(value),
+            Some(existing) => {
+                let first = existing.take();
+                *existing = Value::Array(vec![first, value]);
+            }
+            None => {
+                object.insert(name, value);
+            }
+        }
+    }
+
+    Value::Object(object)
+}
+
+/// Collect the properties of an item from the descendants of `node`, without entering
+/// nested items
+fn collect_properties(node: &NodeHandle, properties: &mut Vec<(String, Value)>) {
+    for child in node.children() {
+        if !child.is_element() {
+            continue;
+        }
+
+        if let Some(names) = attr(&child, "itemprop") {
+            let value = property_value(&child);
+            for name in names.split_whitespace() {
+                properties.push((name.to_string(), value.clone()));
+            }
+        }
+
+        if attr(&child, "itemscope").is_none() {
+            collect_properties(&child, properties);
+        }
+    }
+}
+
+/// Value of a property element, following the microdata rules for each tag
+fn property_value(node: &NodeHandle) -> Value {
+    if attr(node, "itemscope").is_some() {
+        return item(node);
+    }
+
+    let from_attr = |name: &str| Value::from(attr(node, name).unwrap_or_default().trim());
+
+    match node.tag_name().unwrap_or_default() {
+        "meta" => from_attr("content"),
+        "audio" | "embed" | "iframe" | "img" | "source" | "track" | "video" => from_attr("src"),
+        "a" | "area" | "link" => from_attr("href"),
+        "object" => from_attr("data"),
+        "data" | "meter" => from_attr("value"),
+        "time" if attr(node, "datetime").is_some() => from_attr("datetime"),
+        _ => {
+            let text = html::extract_text(node).unwrap_or_default();
+            Value::from(text.split_whitespace().collect::<Vec<_>>().join(" "))
+        }
+    }
+}
+
+fn attr<'a>(node: &'a NodeHandle, name: &str) -> Option<&'a str> {
+    node.attrs()
+        .iter()
+        .find(|a| a.name == name)
+        .map(|a| a.value.as_str())
+}
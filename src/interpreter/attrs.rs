@@ -0,0 +1,255 @@
+// interpreter/attrs.rs - Recovery of duplicate attributes
+//
+// The HTML tokenizer keeps only the first occurrence of an attribute and silently drops
+// the rest, so duplicates never reach the tree. This module rescans the raw source for
+// start tags with repeated attribute names and records what was dropped, so it can be
+// attached to the matching elements afterwards.
+
+use html5ever::tendril::StrTendril;
+use html5ever::tokenizer::{
+    BufferQueue, StartTag, Token, TokenSink, TokenSinkResult, Tokenizer, TokenizerOpts,
+};
+
+use super::dom::Attribute;
+
+/// A start tag whose attribute list contained duplicates
+#[derive(Debug, Clone, PartialEq)]
+pub struct DuplicateAttrs {
+    /// Tag name, lowercased
+    pub tag: String,
+
+    /// Attributes the tokenizer kept, in source order, with entities decoded
+    pub kept: Vec<Attribute>,
+
+    /// Attributes dropped as duplicates, in source order, with entities decoded
+    pub dropped: Vec<Attribute>,
+}
+
+/// Elements whose content is raw text, where `<` does not start a tag
+const RAW_TEXT_ELEMENTS: &[&str] = &[
+    "script", "style", "textarea", "title", "xmp", "iframe", "noembed", "noframes",
+];
+
+/// Find every start tag in the source that repeats an attribute name
+pub fn scan_duplicates(html: &str) -> Vec<DuplicateAttrs> {
+    let bytes = html.as_bytes();
+    let mut result = Vec::new();
+    let mut pos = 0;
+
+    while let Some(offset) = html[pos..].find('<') {
+        pos += offset;
+        let rest = &html[pos..];
+
+        if rest.starts_with("<!--") {
+            pos = rest.find("-->").map_or(html.len(), |end| pos + end + 3);
+        } else if rest.starts_with("<!") || rest.starts_with("<?") || rest.starts_with("</") {
+            pos = rest.find('>').map_or(html.len(), |end| pos + end + 1);
+        } else if bytes.get(pos + 1).is_some_and(|b| b.is_ascii_alphabetic()) {
+            let (tag, attrs, end) = scan_start_tag(html, pos + 1);
+            pos = end;
+
+            let has_duplicates = attrs
+                .iter()
+                .enumerate()
+                .any(|(i, (name, _))| attrs[..i].iter().any(|(other, _)| other == name));
+
+            if has_duplicates {
+                let mut kept: Vec<Attribute> = Vec::new();
+                let mut dropped = Vec::new();
+                for (name, raw_value) in attrs {
+                    let attr = Attribute {
+                        value: raw_value.map(decode_value).unwrap_or_default(),
+                        name,
+                    };
+                    if kept.iter().any(|a| a.name == attr.name) {
+                        dropped.push(attr);
+                    } else {
+                        kept.push(attr);
+                    }
+                }
+                result.push(DuplicateAttrs { tag: tag.clone(), kept, dropped });
+            }
+
+            // Skip the content of raw text elements up to their end tag
+            if RAW_TEXT_ELEMENTS.contains(&tag.as_str()) {
+                let close = format!("</{}", tag);
+                pos = bytes[pos..]
+                    .windows(close.len())
+                    .position(|window| window.eq_ignore_ascii_case(close.as_bytes()))
+                    .map_or(html.len(), |end| pos + end);
+            }
+        } else {
+            pos += 1;
+        }
+    }
+
+    result
+}
+
+/// Scan a start tag beginning at its name. Returns the lowercased tag name, the
+/// attributes as lowercased names with raw (possibly quoted) values, and the position
+/// after the tag.
+fn scan_start_tag(html: &str, start: usize) -> (String, Vec<(String, Option<&str>)>, usize) {
+    let bytes = html.as_bytes();
+    let is_name_end = |b: u8| b.is_ascii_whitespace() || b == b'/' || b == b'>';
+
+    let mut pos = start;
+    while pos < bytes.len() && !is_name_end(bytes[pos]) {
+        pos += 1;
+    }
+    let tag = html[start..pos].to_ascii_lowercase();
+
+    let mut attrs = Vec::new();
+    loop {
+        while pos < bytes.len() && (bytes[pos].is_ascii_whitespace() || bytes[pos] == b'/') {
+            pos += 1;
+        }
+        if pos >= bytes.len() {
+            return (tag, attrs, pos);
+        }
+        if bytes[pos] == b'>' {
+            return (tag, attrs, pos + 1);
+        }
+
+        // An `=` at the start of a name is part of the name
+        let name_start = pos;
+        pos += 1;
+        while pos < bytes.len() && !is_name_end(bytes[pos]) && bytes[pos] != b'=' {
+            pos += 1;
+        }
+        let name = html[name_start..pos].to_ascii_lowercase();
+
+        let mut value_pos = pos;
+        while value_pos < bytes.len() && bytes[value_pos].is_ascii_whitespace() {
+            value_pos += 1;
+        }
+        if bytes.get(value_pos) != Some(&b'=') {
+            attrs.push((name, None));
+            continue;
+        }
+
+        pos = value_pos + 1;
+        while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        let value_start = pos;
+        match bytes.get(pos) {
+            Some(&quote) if quote == b'"' || quote == b'\'' => {
+                pos = html[pos + 1..]
+                    .find(quote as char)
+                    .map_or(html.len(), |end| pos + 1 + end + 1);
+            }
+            _ => {
+                while pos < bytes.len() && !bytes[pos].is_ascii_whitespace() && bytes[pos] != b'>' {
+                    pos += 1;
+                }
+            }
+        }
+        attrs.push((name, Some(&html[value_start..pos])));
+    }
+}
+
+/// Decode the character references in text or attribute values, for parsers that
+/// report them as written
+#[cfg(any(feature = "lol-html", feature = "tl"))]
+pub(crate) fn decode_entities(raw: &str) -> String {
+    if !raw.contains('&') {
+        return raw.to_string();
+    }
+
+    let mut decoded = String::with_capacity(raw.len());
+    let mut rest = raw;
+
+    while let Some(amp) = rest.find('&') {
+        decoded.push_str(&rest[..amp]);
+        rest = &rest[amp + 1..];
+
+        match decode_reference(rest) {
+            Some((text, len)) => {
+                decoded.push_str(&text);
+                rest = &rest[len..];
+            }
+            None => decoded.push('&'),
+        }
+    }
+
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Decode the reference at the start of `s` (after the `&`), returning its text and the
+/// number of bytes it spans
+#[cfg(any(feature = "lol-html", feature = "tl"))]
+fn decode_reference(s: &str) -> Option<(String, usize)> {
+    if let Some(number) = s.strip_prefix('#') {
+        let (digits, radix, prefix) = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => (hex, 16, 2),
+            None => (number, 10, 1),
+        };
+        let len = digits.find(|c: char| !c.is_digit(radix)).unwrap_or(digits.len());
+        if len == 0 {
+            return None;
+        }
+
+        let c = u32::from_str_radix(&digits[..len], radix)
+            .ok()
+            .filter(|&c| c != 0)
+            .and_then(char::from_u32)
+            .unwrap_or('\u{FFFD}');
+        let end = prefix + len + usize::from(digits[len..].starts_with(';'));
+        return Some((c.to_string(), end));
+    }
+
+    // Longest known name, with or without the `;`
+    let name_len = s
+        .find(|c: char| !c.is_ascii_alphanumeric())
+        .unwrap_or(s.len())
+        .min(32);
+    let with_semicolon = s[name_len..].starts_with(';');
+
+    (1..=name_len).rev().find_map(|len| {
+        let (key, end) = if len == name_len && with_semicolon {
+            (&s[..=len], len + 1)
+        } else {
+            (&s[..len], len)
+        };
+        // The table also holds every prefix of a name, mapped to (0, 0)
+        let &(first, second) = html5ever::data::NAMED_ENTITIES
+            .get(key)
+            .filter(|&&(first, _)| first != 0)?;
+        let text = [first, second]
+            .into_iter()
+            .filter(|&c| c != 0)
+            .filter_map(char::from_u32)
+            .collect();
+        Some((text, end))
+    })
+}
+
+/// Decode a raw attribute value the way the tokenizer would, by tokenizing it as the
+/// value of a synthetic tag
+fn decode_value(raw: &str) -> String {
+    struct ValueSink(Option<String>);
+
+    impl TokenSink for ValueSink {
+        type Handle = ();
+
+        fn process_token(&mut self, token: Token, _line_number: u64) -> TokenSinkResult<()> {
+            if let Token::TagToken(tag) = token
+                && tag.kind == StartTag
+                && let Some(attr) = tag.attrs.first()
+            {
+                self.0 = Some(attr.value.to_string());
+            }
+            TokenSinkResult::Continue
+        }
+    }
+
+    let mut tokenizer = Tokenizer::new(ValueSink(None), TokenizerOpts::default());
+    let mut queue = BufferQueue::default();
+    queue.push_back(StrTendril::from_slice(&format!("<x v={}>", raw)));
+    let _ = tokenizer.feed(&mut queue);
+    tokenizer.end();
+
+    tokenizer.sink.0.unwrap_or_default()
+}
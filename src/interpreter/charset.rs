@@ -0,0 +1,105 @@
+// interpreter/charset.rs - Character encoding detection for byte input
+//
+// A simplified version of the HTML encoding sniffing algorithm: byte order mark first,
+// then a `<meta charset>` / `<meta http-equiv="Content-Type">` declaration near the
+// start of the document, and finally UTF-8 with a windows-1252 fallback.
+
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
+
+/// Number of leading bytes searched for a meta declaration
+const PRESCAN_LIMIT: usize = 1024;
+
+/// Detect the encoding of an HTML document
+pub fn sniff(bytes: &[u8]) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        return encoding;
+    }
+
+    if let Some(encoding) = meta_charset(&bytes[..bytes.len().min(PRESCAN_LIMIT)]) {
+        return encoding;
+    }
+
+    if std::str::from_utf8(bytes).is_ok() {
+        UTF_8
+    } else {
+        WINDOWS_1252
+    }
+}
+
+/// Decode an HTML document to a string using the sniffed encoding
+pub fn decode(bytes: &[u8]) -> String {
+    // `decode` strips a matching BOM and replaces malformed sequences
+    let (text, _, _) = sniff(bytes).decode(bytes);
+    text.into_owned()
+}
+
+/// Find the encoding declared by a meta tag
+fn meta_charset(head: &[u8]) -> Option<&'static Encoding> {
+    let label = meta_charset_label(head)?;
+    let encoding = Encoding::for_label(label)?;
+
+    // A document that could be read as ASCII-compatible text is not UTF-16
+    if encoding == encoding_rs::UTF_16LE || encoding == encoding_rs::UTF_16BE {
+        Some(UTF_8)
+    } else {
+        Some(encoding)
+    }
+}
+
+/// Find the first `charset=<label>` inside a `<meta>` tag, ignoring case. Equivalent to
+/// `<meta\b[^>]*?charset\s*=\s*["']?\s*([a-z0-9_\-:.]+)`, scanned by hand since
+/// the pattern engine is configurable and may not support byte input.
+fn meta_charset_label(head: &[u8]) -> Option<&[u8]> {
+    let lower = head.to_ascii_lowercase();
+    let mut from = 0;
+
+    while let Some(offset) = find(&lower[from..], b"<meta") {
+        let start = from + offset + b"<meta".len();
+        from = start;
+
+        // `<metadata>` is not a meta tag
+        if lower.get(start).is_some_and(|b| b.is_ascii_alphanumeric() || *b == b'_') {
+            continue;
+        }
+        let end = lower[start..]
+            .iter()
+            .position(|b| *b == b'>')
+            .map_or(lower.len(), |p| start + p);
+
+        let mut search = start;
+        while let Some(offset) = find(&lower[search..end], b"charset") {
+            let mut i = search + offset + b"charset".len();
+            search = i;
+
+            let skip_whitespace = |i: &mut usize| {
+                while *i < end && lower[*i].is_ascii_whitespace() {
+                    *i += 1;
+                }
+            };
+            skip_whitespace(&mut i);
+            if lower.get(i) != Some(&b'=') {
+                continue;
+            }
+            i += 1;
+            skip_whitespace(&mut i);
+            if i < end && (lower[i] == b'"' || lower[i] == b'\'') {
+                i += 1;
+            }
+            skip_whitespace(&mut i);
+
+            let label_start = i;
+            while i < end && (lower[i].is_ascii_alphanumeric() || b"_-:.".contains(&lower[i])) {
+                i += 1;
+            }
+            if i > label_start {
+                return Some(&head[label_start..i]);
+            }
+        }
+    }
+
+    None
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
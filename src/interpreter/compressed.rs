@@ -0,0 +1,53 @@
+// interpreter/compressed.rs - Decompression of stored documents
+//
+// Archived pages are usually kept gzip or brotli compressed. They are decompressed in
+// full before parsing, so the character encoding is then detected from the bytes like
+// for any other input.
+
+use std::io::Read;
+use std::path::Path;
+
+use super::{InterpreterError, InterpreterResult};
+
+/// Decompress gzip data, including files of several concatenated members
+#[cfg(feature = "gzip")]
+pub fn gunzip(bytes: &[u8]) -> InterpreterResult<Vec<u8>> {
+    let mut out = Vec::new();
+    flate2::read::MultiGzDecoder::new(bytes)
+        .read_to_end(&mut out)
+        .map_err(|e| InterpreterError::execution_error(format!("Invalid gzip data: {}", e)))?;
+    Ok(out)
+}
+
+/// Decompress brotli data
+#[cfg(feature = "brotli")]
+pub fn unbrotli(bytes: &[u8]) -> InterpreterResult<Vec<u8>> {
+    let mut out = Vec::new();
+    brotli_decompressor::Decompressor::new(bytes, 64 * 1024)
+        .read_to_end(&mut out)
+        .map_err(|e| InterpreterError::execution_error(format!("Invalid brotli data: {}", e)))?;
+    Ok(out)
+}
+
+/// Read a file and decompress it according to its extension: `.gz` for gzip, `.br`
+/// for brotli. Other files are returned as they are.
+pub fn read_file(path: &Path) -> InterpreterResult<Vec<u8>> {
+    let mut bytes = Vec::new();
+    std::fs::File::open(path)?.read_to_end(&mut bytes)?;
+
+    match path.extension().and_then(|ext| ext.to_str()) {
+        #[cfg(feature = "gzip")]
+        Some("gz") => gunzip(&bytes),
+        #[cfg(not(feature = "gzip"))]
+        Some("gz") => Err(InterpreterError::execution_error(
+            "Reading gzip files needs the `gzip` feature",
+        )),
+        #[cfg(feature = "brotli")]
+        Some("br") => unbrotli(&bytes),
+        #[cfg(not(feature = "brotli"))]
+        Some("br") => Err(InterpreterError::execution_error(
+            "Reading brotli files needs the `brotli` feature",
+        )),
+        _ => Ok(bytes),
+    }
+}
@@ -0,0 +1,174 @@
+// interpreter/datetime.rs - Minimal calendar arithmetic for date functions
+//
+// Only what the text functions need: parsing ISO 8601 reference times,
+// shifting them by seconds or calendar months, and formatting them back.
+
+/// Largest year shifted by seconds, the limit of ISO 8601's six-digit expanded years.
+/// Day counts of wider years could overflow.
+const MAX_YEAR: i64 = 999_999;
+
+/// A calendar date and time with the UTC offset kept as written
+#[derive(Debug, Clone, PartialEq)]
+pub struct DateTime {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+
+    /// Whether the time part was present in the source
+    pub has_time: bool,
+
+    /// UTC offset suffix as written, e.g. `Z` or `+08:00`
+    pub offset: String,
+}
+
+impl DateTime {
+    /// Parse `YYYY-MM-DD`, `YYYY-MM-DDTHH:MM[:SS][Z|±HH:MM]` (a space may replace `T`)
+    pub fn parse_iso(input: &str) -> Option<Self> {
+        let input = input.trim();
+        let (date, rest) = match input.find(['T', ' ']) {
+            Some(pos) => (&input[..pos], Some(&input[pos + 1..])),
+            None => (input, None),
+        };
+
+        let mut parts = date.splitn(3, '-');
+        let year = parts.next()?.parse::<i64>().ok()?;
+        let month = parts.next()?.parse::<u32>().ok()?;
+        let day = parts.next()?.parse::<u32>().ok()?;
+        if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+            return None;
+        }
+
+        let mut datetime = DateTime {
+            year,
+            month,
+            day,
+            hour: 0,
+            minute: 0,
+            second: 0,
+            has_time: false,
+            offset: String::new(),
+        };
+
+        if let Some(rest) = rest {
+            let offset_pos = rest.find(['Z', '+', '-']).unwrap_or(rest.len());
+            let (time, offset) = rest.split_at(offset_pos);
+
+            let mut parts = time.split(':');
+            datetime.hour = parts.next()?.parse().ok()?;
+            datetime.minute = parts.next()?.parse().ok()?;
+            datetime.second = match parts.next() {
+                // Fractional seconds are dropped
+                Some(sec) => sec.split('.').next()?.parse().ok()?,
+                None => 0,
+            };
+            if datetime.hour > 23 || datetime.minute > 59 || datetime.second > 60 {
+                return None;
+            }
+
+            datetime.has_time = true;
+            datetime.offset = offset.to_string();
+        }
+
+        Some(datetime)
+    }
+
+    /// Shift by a number of seconds, or `None` if the result is out of range
+    pub fn add_seconds(&self, seconds: i64) -> Option<Self> {
+        if self.year.abs() > MAX_YEAR {
+            return None;
+        }
+        let total = days_from_civil(self.year, self.month, self.day)
+            .checked_mul(86_400)?
+            .checked_add((self.hour * 3600 + self.minute * 60 + self.second) as i64)?
+            .checked_add(seconds)?;
+
+        let days = total.div_euclid(86_400);
+        let secs = total.rem_euclid(86_400);
+        let (year, month, day) = civil_from_days(days);
+
+        Some(DateTime {
+            year,
+            month,
+            day,
+            hour: (secs / 3600) as u32,
+            minute: (secs % 3600 / 60) as u32,
+            second: (secs % 60) as u32,
+            has_time: self.has_time,
+            offset: self.offset.clone(),
+        })
+    }
+
+    /// Shift by calendar months, clamping the day to the length of the target month, or
+    /// `None` if the result is out of range
+    pub fn add_months(&self, months: i64) -> Option<Self> {
+        let index = self
+            .year
+            .checked_mul(12)?
+            .checked_add(self.month as i64 - 1)?
+            .checked_add(months)?;
+        let year = index.div_euclid(12);
+        let month = (index.rem_euclid(12) + 1) as u32;
+
+        Some(DateTime {
+            year,
+            month,
+            day: self.day.min(days_in_month(year, month)),
+            ..self.clone()
+        })
+    }
+
+    /// Format in the same ISO 8601 shape the value was parsed from
+    pub fn to_iso(&self) -> String {
+        if self.has_time {
+            format!(
+                "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}{}",
+                self.year, self.month, self.day, self.hour, self.minute, self.second, self.offset
+            )
+        } else {
+            format!("{:04}-{:02}-{:02}", self.year, self.month, self.day)
+        }
+    }
+}
+
+fn is_leap_year(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+/// Number of days in the given month
+pub fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        _ => 0,
+    }
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date
+pub fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let m = month as i64;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Proleptic Gregorian date for a number of days since 1970-01-01
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
@@ -0,0 +1,483 @@
+// interpreter/dom.rs - Thread-safe document tree
+//
+// html5ever's RcDom is built out of `Rc` and `RefCell`, so it can never leave the thread
+// that parsed it. After parsing, the tree is copied into this flat arena of plain data,
+// which can be shared behind an `Arc` and queried from any number of threads.
+
+use super::attrs::DuplicateAttrs;
+#[cfg(feature = "tl")]
+use super::attrs::decode_entities;
+use super::{InterpreterError, InterpreterResult};
+use markup5ever_rcdom::{Handle as Html5Handle, NodeData as Html5NodeData};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Next unassigned node ID. Every document reserves a contiguous block of IDs when it is
+/// built, so node IDs never repeat across documents within the process.
+static NEXT_NODE_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Element attribute
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attribute {
+    /// Attribute name (local name)
+    pub name: String,
+
+    /// Attribute value
+    pub value: String,
+}
+
+/// Recoverable error reported by the parser
+#[derive(Debug, Clone, PartialEq)]
+pub struct HtmlWarning {
+    /// Parser message
+    pub message: String,
+}
+
+impl fmt::Display for HtmlWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Node content
+#[derive(Debug, Clone, PartialEq)]
+pub enum NodeData {
+    /// Document root
+    Document,
+
+    /// Doctype declaration
+    Doctype {
+        name: String,
+        public_id: String,
+        system_id: String,
+    },
+
+    /// Text content
+    Text { contents: String },
+
+    /// Comment
+    Comment { contents: String },
+
+    /// Element with tag name (local name) and attributes in source order
+    Element { name: String, attrs: Vec<Attribute> },
+
+    /// Processing instruction
+    ProcessingInstruction { target: String, contents: String },
+}
+
+/// A node stored in the arena
+#[derive(Debug, Clone)]
+pub struct Node {
+    /// Parent node index, `None` for the document root
+    pub parent: Option<usize>,
+
+    /// Child node indices in document order
+    pub children: Vec<usize>,
+
+    /// End (exclusive) of this node's subtree: descendants occupy `index + 1..subtree_end`
+    pub subtree_end: usize,
+
+    /// Node content
+    pub data: NodeData,
+}
+
+/// Arena holding every node of a parsed document.
+///
+/// Nodes are stored in document (pre-)order and the root is always at index 0.
+#[derive(Debug, Clone)]
+pub struct Dom {
+    nodes: Vec<Node>,
+
+    /// ID of the root node; node IDs are `id_base + index`
+    id_base: usize,
+
+    /// Element indices by tag name, in document order
+    tag_index: HashMap<String, Vec<usize>>,
+
+    /// Element indices by class name, in document order
+    class_index: HashMap<String, Vec<usize>>,
+
+    /// Element indices by id attribute, in document order
+    id_index: HashMap<String, Vec<usize>>,
+
+    /// Errors the parser recovered from
+    warnings: Vec<HtmlWarning>,
+
+    /// Attributes the tokenizer dropped as duplicates, by element index. Only recorded
+    /// when requested at parse time.
+    duplicate_attrs: HashMap<usize, Vec<Attribute>>,
+}
+
+impl Dom {
+    /// Copy an html5ever (or xml5ever) tree into a new arena
+    pub(crate) fn from_html5(root: &Html5Handle) -> Self {
+        let mut dom = Dom {
+            nodes: Vec::new(),
+            id_base: 0,
+            tag_index: HashMap::new(),
+            class_index: HashMap::new(),
+            id_index: HashMap::new(),
+            warnings: Vec::new(),
+            duplicate_attrs: HashMap::new(),
+        };
+        dom.copy_node(root, None);
+        dom.id_base = NEXT_NODE_ID.fetch_add(dom.nodes.len(), Ordering::Relaxed);
+        dom.build_indexes();
+        dom
+    }
+
+    /// Copy a tree parsed by `tl` into a new arena. `tl` keeps text and attribute values
+    /// as written, so character references are decoded here, except in raw text elements.
+    #[cfg(feature = "tl")]
+    pub(crate) fn from_tl(vdom: &tl::VDom) -> Self {
+        let mut dom = Dom {
+            nodes: Vec::new(),
+            id_base: 0,
+            tag_index: HashMap::new(),
+            class_index: HashMap::new(),
+            id_index: HashMap::new(),
+            warnings: Vec::new(),
+            duplicate_attrs: HashMap::new(),
+        };
+        dom.nodes.push(Node {
+            parent: None,
+            children: Vec::new(),
+            subtree_end: 1,
+            data: NodeData::Document,
+        });
+        for child in vdom.children() {
+            if let Some(index) = dom.copy_tl_node(*child, vdom.parser(), 0, false) {
+                dom.nodes[0].children.push(index);
+            }
+        }
+        dom.nodes[0].subtree_end = dom.nodes.len();
+
+        dom.id_base = NEXT_NODE_ID.fetch_add(dom.nodes.len(), Ordering::Relaxed);
+        dom.build_indexes();
+        dom
+    }
+
+    /// Attach the errors reported while parsing the document
+    pub(crate) fn with_warnings<I, S>(mut self, messages: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.warnings = messages
+            .into_iter()
+            .map(|message| HtmlWarning {
+                message: message.into(),
+            })
+            .collect();
+        self
+    }
+
+    /// Attach duplicate attributes recovered from the source. Each entry is matched to
+    /// the next element, in document order, with the same tag name and kept attributes;
+    /// entries without a match (e.g. attributes merged into `<html>` or `<body>`) are
+    /// ignored.
+    pub(crate) fn with_duplicate_attrs(mut self, entries: Vec<DuplicateAttrs>) -> Self {
+        let mut entries = entries.into_iter().peekable();
+
+        for (index, node) in self.nodes.iter().enumerate() {
+            let Some(entry) = entries.peek() else {
+                break;
+            };
+
+            if let NodeData::Element { name, attrs } = &node.data
+                && *name == entry.tag
+                && *attrs == entry.kept
+                && let Some(entry) = entries.next()
+            {
+                self.duplicate_attrs.insert(index, entry.dropped);
+            }
+        }
+
+        self
+    }
+
+    /// Copy the document, passing every element attribute through `f` together with the
+    /// element's tag name. The copy is a new document with node IDs of its own.
+    pub(crate) fn map_attributes<F>(&self, mut f: F) -> Self
+    where
+        F: FnMut(&str, &mut Attribute),
+    {
+        let mut dom = self.clone();
+        for node in &mut dom.nodes {
+            if let NodeData::Element { name, attrs } = &mut node.data {
+                for attr in attrs {
+                    f(name, attr);
+                }
+            }
+        }
+
+        dom.id_base = NEXT_NODE_ID.fetch_add(dom.nodes.len(), Ordering::Relaxed);
+        dom.tag_index.clear();
+        dom.class_index.clear();
+        dom.id_index.clear();
+        dom.build_indexes();
+        dom
+    }
+
+    /// Build the tag, class and id lookup tables
+    fn build_indexes(&mut self) {
+        for (index, node) in self.nodes.iter().enumerate() {
+            if let NodeData::Element { name, attrs } = &node.data {
+                self.tag_index.entry(name.clone()).or_default().push(index);
+
+                for attr in attrs {
+                    match attr.name.as_str() {
+                        "class" => {
+                            for class in attr.value.split_whitespace() {
+                                let entries = self.class_index.entry(class.to_string()).or_default();
+                                // The same class may be repeated within one attribute
+                                if entries.last() != Some(&index) {
+                                    entries.push(index);
+                                }
+                            }
+                        }
+                        "id" => self.id_index.entry(attr.value.clone()).or_default().push(index),
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    /// Recursively copy a node and its children, returning the new node index
+    fn copy_node(&mut self, handle: &Html5Handle, parent: Option<usize>) -> usize {
+        let data = match &handle.data {
+            Html5NodeData::Document => NodeData::Document,
+            Html5NodeData::Doctype {
+                name,
+                public_id,
+                system_id,
+            } => NodeData::Doctype {
+                name: name.to_string(),
+                public_id: public_id.to_string(),
+                system_id: system_id.to_string(),
+            },
+            Html5NodeData::Text { contents } => NodeData::Text {
+                contents: contents.borrow().to_string(),
+            },
+            Html5NodeData::Comment { contents } => NodeData::Comment {
+                contents: contents.to_string(),
+            },
+            Html5NodeData::Element { name, attrs, .. } => NodeData::Element {
+                name: name.local.to_string(),
+                attrs: attrs
+                    .borrow()
+                    .iter()
+                    .map(|attr| Attribute {
+                        name: attr.name.local.to_string(),
+                        value: attr.value.to_string(),
+                    })
+                    .collect(),
+            },
+            Html5NodeData::ProcessingInstruction { target, contents } => {
+                NodeData::ProcessingInstruction {
+                    target: target.to_string(),
+                    contents: contents.to_string(),
+                }
+            }
+        };
+
+        let index = self.nodes.len();
+        self.nodes.push(Node {
+            parent,
+            children: Vec::new(),
+            subtree_end: index + 1,
+            data,
+        });
+
+        for child in handle.children.borrow().iter() {
+            let child_index = self.copy_node(child, Some(index));
+            self.nodes[index].children.push(child_index);
+        }
+
+        self.nodes[index].subtree_end = self.nodes.len();
+
+        index
+    }
+
+    /// Recursively copy a `tl` node and its children, returning the new node index
+    #[cfg(feature = "tl")]
+    fn copy_tl_node(
+        &mut self,
+        handle: tl::NodeHandle,
+        parser: &tl::Parser,
+        parent: usize,
+        raw_text: bool,
+    ) -> Option<usize> {
+        let node = handle.get(parser)?;
+
+        let (data, children) = match node {
+            tl::Node::Tag(tag) => {
+                let name = tag.name().as_utf8_str().to_ascii_lowercase();
+                let mut attrs: Vec<Attribute> = tag
+                    .attributes()
+                    .iter()
+                    .map(|(name, value)| Attribute {
+                        name: name.to_ascii_lowercase(),
+                        value: value.map(|v| decode_entities(&v)).unwrap_or_default(),
+                    })
+                    .collect();
+                // `tl` keeps attributes in a map, so restore the order of the source
+                let order = tl_attribute_order(&tag.raw().as_utf8_str());
+                attrs.sort_by_key(|a| order.iter().position(|n| *n == a.name));
+                let children = tag.children().top().as_slice().to_vec();
+                (NodeData::Element { name, attrs }, children)
+            }
+            tl::Node::Raw(text) => {
+                let text = text.as_utf8_str();
+                let contents = if raw_text {
+                    text.into_owned()
+                } else {
+                    decode_entities(&text)
+                };
+                (NodeData::Text { contents }, Vec::new())
+            }
+            tl::Node::Comment(comment) => {
+                let comment = comment.as_utf8_str();
+                let contents = comment
+                    .strip_prefix("<!--")
+                    .map(|c| c.strip_suffix("-->").unwrap_or(c))
+                    .unwrap_or(&comment)
+                    .to_string();
+                (NodeData::Comment { contents }, Vec::new())
+            }
+        };
+
+        let raw_text = matches!(
+            &data,
+            NodeData::Element { name, .. } if name == "script" || name == "style"
+        );
+
+        let index = self.nodes.len();
+        self.nodes.push(Node {
+            parent: Some(parent),
+            children: Vec::new(),
+            subtree_end: index + 1,
+            data,
+        });
+
+        for child in children {
+            if let Some(child_index) = self.copy_tl_node(child, parser, index, raw_text) {
+                self.nodes[index].children.push(child_index);
+            }
+        }
+
+        self.nodes[index].subtree_end = self.nodes.len();
+
+        Some(index)
+    }
+
+    /// Index of the document root
+    pub fn root(&self) -> usize {
+        0
+    }
+
+    /// Stable ID of the node at the given index
+    pub fn node_id(&self, index: usize) -> usize {
+        self.id_base + index
+    }
+
+    /// Get a node by index
+    pub fn node(&self, index: usize) -> &Node {
+        &self.nodes[index]
+    }
+
+    /// Elements with the given tag name within the subtree of `scope` (inclusive)
+    pub fn elements_by_tag(&self, tag_name: &str, scope: usize) -> &[usize] {
+        self.lookup(&self.tag_index, tag_name, scope)
+    }
+
+    /// Elements with the given class name within the subtree of `scope` (inclusive)
+    pub fn elements_by_class(&self, class_name: &str, scope: usize) -> &[usize] {
+        self.lookup(&self.class_index, class_name, scope)
+    }
+
+    /// Elements with the given id within the subtree of `scope` (inclusive)
+    pub fn elements_by_id(&self, id: &str, scope: usize) -> &[usize] {
+        self.lookup(&self.id_index, id, scope)
+    }
+
+    /// Look up a key and narrow the sorted entries down to the subtree of `scope`
+    fn lookup<'a>(&self, table: &'a HashMap<String, Vec<usize>>, key: &str, scope: usize) -> &'a [usize] {
+        let Some(entries) = table.get(key) else {
+            return &[];
+        };
+
+        let end = self.nodes[scope].subtree_end;
+        let lo = entries.partition_point(|&index| index < scope);
+        let hi = entries.partition_point(|&index| index < end);
+        &entries[lo..hi]
+    }
+
+    /// Errors the parser recovered from, in the order they were reported
+    pub fn warnings(&self) -> &[HtmlWarning] {
+        &self.warnings
+    }
+
+    /// Attributes dropped as duplicates from the element at the given index
+    pub fn duplicate_attrs(&self, index: usize) -> &[Attribute] {
+        self.duplicate_attrs.get(&index).map_or(&[], |attrs| attrs.as_slice())
+    }
+
+    /// Fail with the parser's messages if it had to recover from malformed markup
+    pub fn ensure_well_formed(&self) -> InterpreterResult<()> {
+        if !self.warnings.is_empty() {
+            let messages: Vec<String> = self.warnings.iter().map(|w| w.to_string()).collect();
+            return Err(InterpreterError::html_parse_error(messages.join("; ")));
+        }
+
+        Ok(())
+    }
+
+    /// Number of nodes in the document
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Check if the document has no nodes
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+}
+
+/// Lowercased attribute names of the start tag at the beginning of `raw`, in order
+#[cfg(feature = "tl")]
+fn tl_attribute_order(raw: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let is_space = |c: char| c.is_ascii_whitespace() || c == '/';
+
+    // Skip `<` and the tag name
+    let mut rest = raw.get(1..).unwrap_or_default();
+    rest = rest.trim_start_matches(|c: char| !is_space(c) && c != '>');
+
+    loop {
+        rest = rest.trim_start_matches(is_space);
+        if rest.is_empty() || rest.starts_with('>') {
+            return names;
+        }
+
+        let name_len = rest
+            .find(|c: char| is_space(c) || c == '=' || c == '>')
+            .unwrap_or(rest.len())
+            .max(1);
+        names.push(rest[..name_len].to_ascii_lowercase());
+        rest = rest[name_len..].trim_start_matches(|c: char| c.is_ascii_whitespace());
+
+        let Some(value) = rest.strip_prefix('=') else {
+            continue;
+        };
+        let value = value.trim_start_matches(|c: char| c.is_ascii_whitespace());
+        rest = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => value[1..]
+                .find(quote)
+                .map_or("", |end| &value[end + 2..]),
+            _ => value.trim_start_matches(|c: char| !c.is_ascii_whitespace() && c != '>'),
+        };
+    }
+}
@@ -3,11 +3,15 @@
 // This module is responsible for executing element selectors, including class, id, tag and attr selectors.
 // It relies on the DOM manipulation functionality provided by html.rs to implement precise selection of HTML elements.
 
-use super::error::InterpreterResult;
+use std::collections::{HashMap, HashSet};
+
+use super::dom::NodeData;
+use super::error::{InterpreterError, InterpreterResult};
 use super::result::{NodeHandle, SelectionResult};
+use super::html::ValueMatcher;
 use super::{Interpreter, html};
-use crate::parser::ElementNode;
-use regex;
+use crate::parser::{ElementNode, Node};
+use crate::pattern::Pattern;
 
 /// Apply element selector
 pub fn apply_element_selector(
@@ -26,6 +30,31 @@ pub fn apply_element_selector(
             let value_ref = attr_value.as_ref().map(|s| s.as_str());
             select_by_attr(nodes, attr_name, value_ref, *is_regex)?
         },
+        ElementNode::Lang(lang, is_regex) => select_by_lang(nodes, lang, *is_regex)?,
+        ElementNode::Role(role, is_regex) => select_by_role(nodes, role, *is_regex)?,
+        ElementNode::Not(expr) => select_not(it, nodes, expr)?,
+        ElementNode::Has(expr) => select_has(it, nodes, expr)?,
+        ElementNode::Next(filter) => select_sibling(it, nodes, filter.as_deref(), true)?,
+        ElementNode::Prev(filter) => select_sibling(it, nodes, filter.as_deref(), false)?,
+        ElementNode::Child(filter) => select_children(it, nodes, filter.as_deref())?,
+        ElementNode::Parent(filter) => select_ancestors(it, nodes, filter.as_deref(), Ancestry::Parent)?,
+        ElementNode::Ancestors(filter) => select_ancestors(it, nodes, filter.as_deref(), Ancestry::All)?,
+        ElementNode::Closest(filter) => select_ancestors(it, nodes, Some(filter), Ancestry::Closest)?,
+        ElementNode::FirstChild => select_by_position(nodes, |index, _| index == 0),
+        ElementNode::LastChild => select_by_position(nodes, |index, count| index + 1 == count),
+        ElementNode::NthChild(n) => select_by_position(nodes, |index, _| index + 1 == *n),
+        ElementNode::Empty => nodes
+            .iter()
+            .filter(|node| node.is_element() && node.node().children.is_empty())
+            .cloned()
+            .collect(),
+        ElementNode::Leaf => nodes
+            .iter()
+            .filter(|node| node.is_element() && !node.children().iter().any(NodeHandle::is_element))
+            .cloned()
+            .collect(),
+        ElementNode::TextNodes => nodes.iter().flat_map(html::find_text_nodes).collect(),
+        ElementNode::Depth(inner, depth) => select_within_depth(nodes, inner, *depth)?,
     };
 
     // Wrap result as SelectionResult
@@ -85,6 +114,23 @@ fn select_by_tag(
     Ok(result)
 }
 
+/// Select elements by ARIA role
+fn select_by_role(
+    current_selection: &Vec<NodeHandle>,
+    role: &str,
+    is_regex: bool,
+) -> InterpreterResult<Vec<NodeHandle>> {
+    let mut result = Vec::new();
+
+    // Apply role selector to each currently selected node
+    for node in current_selection {
+        let mut matches = html::find_by_role(node, role, is_regex)?;
+        result.append(&mut matches);
+    }
+
+    Ok(result)
+}
+
 /// Select elements by attribute name
 fn select_by_attr(
     current_selection: &Vec<NodeHandle>,
@@ -92,55 +138,378 @@ fn select_by_attr(
     attr_value: Option<&str>,
     is_regex: bool,
 ) -> InterpreterResult<Vec<NodeHandle>> {
+    // Special case `attr value "name"`: any attribute with the value will do
+    let name = if attr_name.is_empty() && attr_value.is_some() {
+        None
+    } else {
+        Some(ValueMatcher::new(attr_name, is_regex)?)
+    };
+    let value = attr_value.map(|value| ValueMatcher::new(value, is_regex)).transpose()?;
+
     let mut result = Vec::new();
+    for node in current_selection {
+        result.extend(html::find_by_attr(node, name.as_ref(), value.as_ref()));
+    }
 
-    // Handle special case: attr value "name"
-    if attr_name.is_empty() && attr_value.is_some() {
-        // Search all attributes by attribute value
-        for node in current_selection {
-            // Get all attributes
-            let attributes = html::get_all_attributes(node)?;
-            
-            let target_value = attr_value.unwrap();
-            
-            // Check if any attribute value matches the target value
-            let mut found = false;
-            for (_, val) in attributes {
-                let is_match = if is_regex {
-                    // Regex matching
-                    match regex::Regex::new(target_value) {
-                        Ok(re) => re.is_match(&val),
-                        Err(_) => false,
-                    }
-                } else {
-                    // Exact matching
-                    val == target_value
-                };
-                
-                if is_match {
-                    found = true;
-                    break;
-                }
+    Ok(result)
+}
+
+/// Keep elements whose language, inherited from the nearest `lang` attribute, matches.
+/// A plain value matches the language tag or any of its subtags (`en` matches `en-US`).
+fn select_by_lang(
+    current_selection: &Vec<NodeHandle>,
+    lang: &str,
+    is_regex: bool,
+) -> InterpreterResult<Vec<NodeHandle>> {
+    let re = if is_regex {
+        Some(Pattern::new(lang)?)
+    } else {
+        None
+    };
+
+    let mut result = Vec::new();
+
+    for node in current_selection {
+        if !node.is_element() {
+            continue;
+        }
+
+        let Some(node_lang) = html::get_lang(node) else {
+            continue;
+        };
+
+        let is_match = match &re {
+            Some(re) => re.is_match(&node_lang),
+            None => {
+                let node_lang = node_lang.to_ascii_lowercase();
+                let lang = lang.to_ascii_lowercase();
+                node_lang == lang || node_lang.starts_with(&format!("{}-", lang))
             }
-            
-            if found {
-                result.push(node.clone());
+        };
+
+        if is_match {
+            result.push(node.clone());
+        }
+    }
+
+    Ok(result)
+}
+
+/// Keep elements that are not among the matches of the expression evaluated from them
+fn select_not(
+    it: &Interpreter,
+    current_selection: &Vec<NodeHandle>,
+    expr: &Node,
+) -> InterpreterResult<Vec<NodeHandle>> {
+    let mut result = Vec::new();
+
+    for node in current_selection {
+        if !relational_matches(it, node, expr)?.contains(node) {
+            result.push(node.clone());
+        }
+    }
+
+    Ok(result)
+}
+
+/// Keep elements where the expression evaluated from them matches a descendant
+fn select_has(
+    it: &Interpreter,
+    current_selection: &Vec<NodeHandle>,
+    expr: &Node,
+) -> InterpreterResult<Vec<NodeHandle>> {
+    let mut result = Vec::new();
+
+    for node in current_selection {
+        if relational_matches(it, node, expr)?.iter().any(|m| m != node) {
+            result.push(node.clone());
+        }
+    }
+
+    Ok(result)
+}
+
+/// Move each element to its nearest sibling element in the given direction that matches
+/// the filter, if any. Elements without such a sibling are dropped.
+fn select_sibling(
+    it: &Interpreter,
+    current_selection: &Vec<NodeHandle>,
+    filter: Option<&ElementNode>,
+    forward: bool,
+) -> InterpreterResult<Vec<NodeHandle>> {
+    let filter = filter.map(|filter| Node::element(filter.clone()));
+    let mut result = Vec::new();
+
+    for node in current_selection {
+        let Some(parent) = node.node().parent else {
+            continue;
+        };
+        // Child indices are in document order, so the node is found by binary search and
+        // only the siblings actually visited become handles
+        let siblings = &node.dom().node(parent).children;
+        let Ok(position) = siblings.binary_search(&node.index()) else {
+            continue;
+        };
+        let candidates: Box<dyn Iterator<Item = &usize>> = if forward {
+            Box::new(siblings[position + 1..].iter())
+        } else {
+            Box::new(siblings[..position].iter().rev())
+        };
+
+        for &index in candidates {
+            let sibling = NodeHandle::new(node.dom().clone(), index);
+            if !sibling.is_element() {
+                continue;
             }
-            
-            // Recursively process child nodes
-            let children = html::get_children(node)?;
-            for child in children {
-                let mut child_results = select_by_attr(&vec![child], attr_name, attr_value, is_regex)?;
-                result.append(&mut child_results);
+            let is_match = match &filter {
+                Some(filter) => relational_matches(it, &sibling, filter)?.contains(&sibling),
+                None => true,
+            };
+            if is_match {
+                result.push(sibling);
+                break;
             }
         }
-    } else {
-        // Regular case: search by attribute name and optional attribute value
-        for node in current_selection {
-            let mut matches = html::find_by_attr(node, attr_name, attr_value, is_regex)?;
-            result.append(&mut matches);
+    }
+
+    Ok(result)
+}
+
+/// Move each element to its child elements, keeping those that match the filter, if any
+fn select_children(
+    it: &Interpreter,
+    current_selection: &Vec<NodeHandle>,
+    filter: Option<&ElementNode>,
+) -> InterpreterResult<Vec<NodeHandle>> {
+    let filter = filter.map(|filter| Node::element(filter.clone()));
+    let mut result = Vec::new();
+
+    for node in current_selection {
+        for child in node.children().into_iter().filter(NodeHandle::is_element) {
+            let is_match = match &filter {
+                Some(filter) => relational_matches(it, &child, filter)?.contains(&child),
+                None => true,
+            };
+            if is_match {
+                result.push(child);
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+/// Keep elements whose position among the element children of their parent passes the
+/// check, which receives the position, counting from 0, and the number of element
+/// children
+fn select_by_position(
+    current_selection: &[NodeHandle],
+    check: impl Fn(usize, usize) -> bool,
+) -> Vec<NodeHandle> {
+    // Element children of each parent, keyed by its node ID, as document-ordered
+    // indices, so that siblings share one list and each position is a binary search
+    let mut element_children: HashMap<usize, Vec<usize>> = HashMap::new();
+
+    current_selection
+        .iter()
+        .filter(|node| {
+            let Some(parent) = node.node().parent else {
+                return false;
+            };
+            let dom = node.dom();
+            let siblings = element_children.entry(dom.node_id(parent)).or_insert_with(|| {
+                dom.node(parent)
+                    .children
+                    .iter()
+                    .copied()
+                    .filter(|&child| matches!(dom.node(child).data, NodeData::Element { .. }))
+                    .collect()
+            });
+            siblings
+                .binary_search(&node.index())
+                .is_ok_and(|index| check(index, siblings.len()))
+        })
+        .cloned()
+        .collect()
+}
+
+/// Which elements up the tree an upward step moves to
+#[derive(Clone, Copy, PartialEq)]
+enum Ancestry {
+    /// The parent
+    Parent,
+
+    /// Every ancestor
+    All,
+
+    /// The first match among the element itself and its ancestors
+    Closest,
+}
+
+/// Move each element up the tree, keeping the elements that match the filter, if any.
+/// An element reached from several current elements is kept once, at its first
+/// occurrence.
+fn select_ancestors(
+    it: &Interpreter,
+    current_selection: &Vec<NodeHandle>,
+    filter: Option<&ElementNode>,
+    ancestry: Ancestry,
+) -> InterpreterResult<Vec<NodeHandle>> {
+    let filter = filter.map(|filter| Node::element(filter.clone()));
+    let mut result = Vec::new();
+    let mut seen = HashSet::new();
+
+    for node in current_selection {
+        let mut current = if ancestry == Ancestry::Closest {
+            Some(node.clone())
+        } else {
+            node.parent()
+        };
+
+        while let Some(candidate) = current.filter(NodeHandle::is_element) {
+            let is_match = match &filter {
+                Some(filter) => relational_matches(it, &candidate, filter)?.contains(&candidate),
+                None => true,
+            };
+            if is_match && seen.insert(candidate.clone()) {
+                result.push(candidate.clone());
+            }
+            if ancestry == Ancestry::Parent || (ancestry == Ancestry::Closest && is_match) {
+                break;
+            }
+            current = candidate.parent();
+        }
+    }
+
+    Ok(result)
+}
+
+/// Apply a searching selector to each element on its own, visiting only the elements at
+/// most `max_depth` levels below it
+fn select_within_depth(
+    current_selection: &[NodeHandle],
+    selector: &ElementNode,
+    max_depth: usize,
+) -> InterpreterResult<Vec<NodeHandle>> {
+    let test = element_test(selector)?;
+    let mut result = Vec::new();
+
+    for node in current_selection {
+        let dom = node.dom();
+
+        // Plain names are still answered from the document's indexes, which only leaves
+        // a climb of at most `max_depth` parents per match
+        let indexed = match selector {
+            ElementNode::Tag(name, false) => Some(dom.elements_by_tag(name, node.index())),
+            ElementNode::Class(name, false) => Some(dom.elements_by_class(name, node.index())),
+            ElementNode::Id(id, false) => Some(dom.elements_by_id(id, node.index())),
+            _ => None,
+        };
+        if let Some(indices) = indexed {
+            for &index in indices {
+                if is_within_depth(node, index, max_depth) {
+                    result.push(NodeHandle::new(dom.clone(), index));
+                }
+            }
+            continue;
+        }
+
+        // Walk down in document order, never below `max_depth`
+        let mut pending = vec![(node.index(), 0)];
+        while let Some((index, depth)) = pending.pop() {
+            let current = NodeHandle::new(dom.clone(), index);
+            if depth < max_depth {
+                let children = &current.node().children;
+                pending.extend(children.iter().rev().map(|&child| (child, depth + 1)));
+            }
+            if test(&current) {
+                result.push(current);
+            }
         }
     }
 
     Ok(result)
-}
\ No newline at end of file
+}
+
+/// Check whether the node at `index` is at most `max_depth` levels below `ancestor`,
+/// climbing no further than that
+fn is_within_depth(ancestor: &NodeHandle, index: usize, max_depth: usize) -> bool {
+    let dom = ancestor.dom();
+    let mut current = Some(index);
+    for _ in 0..=max_depth {
+        match current {
+            Some(index) if index == ancestor.index() => return true,
+            Some(index) => current = dom.node(index).parent,
+            None => return false,
+        }
+    }
+    false
+}
+
+/// A searching selector applied to a single element
+type ElementTest<'a> = Box<dyn Fn(&NodeHandle) -> bool + 'a>;
+
+/// Test a single element against a searching selector (`class`, `id`, `tag`, `attr` or
+/// `role`) the way its finder would, compiling any pattern once
+fn element_test(selector: &ElementNode) -> InterpreterResult<ElementTest<'_>> {
+    fn attr<'n>(node: &'n NodeHandle, name: &str) -> Option<&'n str> {
+        node.attrs().iter().find(|attr| attr.name == name).map(|attr| attr.value.as_str())
+    }
+
+    Ok(match selector {
+        ElementNode::Class(class_name, is_regex) => {
+            let class_name = ValueMatcher::new(class_name, *is_regex)?;
+            Box::new(move |node| {
+                attr(node, "class")
+                    .is_some_and(|classes| classes.split_whitespace().any(|c| class_name.is_match(c)))
+            })
+        }
+        ElementNode::Id(id, is_regex) => {
+            let id = ValueMatcher::new(id, *is_regex)?;
+            Box::new(move |node| attr(node, "id").is_some_and(|value| id.is_match(value)))
+        }
+        ElementNode::Tag(tag_name, is_regex) => {
+            let tag_name = ValueMatcher::new(tag_name, *is_regex)?;
+            Box::new(move |node| node.tag_name().is_some_and(|name| tag_name.is_match(name)))
+        }
+        ElementNode::Role(role, is_regex) => {
+            let role = ValueMatcher::new(role, *is_regex)?;
+            Box::new(move |node| html::get_roles(node).iter().any(|r| role.is_match(r)))
+        }
+        ElementNode::Attr(attr_name, attr_value, is_regex) => {
+            let name = if attr_name.is_empty() && attr_value.is_some() {
+                None
+            } else {
+                Some(ValueMatcher::new(attr_name, *is_regex)?)
+            };
+            let value = attr_value.as_deref().map(|value| ValueMatcher::new(value, *is_regex)).transpose()?;
+            Box::new(move |node| {
+                node.attrs().iter().any(|attr| {
+                    name.as_ref().is_none_or(|name| name.is_match(&attr.name))
+                        && value.as_ref().is_none_or(|value| value.is_match(&attr.value))
+                })
+            })
+        }
+        other => {
+            return Err(InterpreterError::execution_error(format!(
+                "A maximum depth cannot follow `{}`",
+                other
+            )))
+        }
+    })
+}
+
+/// Elements the expression of a relational selector selects from a single element
+fn relational_matches(
+    it: &Interpreter,
+    node: &NodeHandle,
+    expr: &Node,
+) -> InterpreterResult<Vec<NodeHandle>> {
+    match it.evaluate(SelectionResult::with_nodes(vec![node.clone()]), expr)? {
+        SelectionResult::Nodes(nodes, _) => Ok(nodes),
+        SelectionResult::Texts(..) => Err(InterpreterError::execution_error(format!(
+            "The expression `{}` of not(...) or has(...) must select elements, not text",
+            expr
+        ))),
+    }
+}
@@ -42,6 +42,16 @@ pub enum InterpreterError {
 
     /// Result limit exceeded
     ResultLimitExceeded(usize),
+
+    /// Parallel columns of different lengths: column name, its length, expected length
+    ColumnLengthMismatch(String, usize, usize),
+
+    /// Memory budget exceeded: stage that exceeded it, bytes used, budget in bytes
+    MemoryLimitExceeded(String, usize, usize),
+
+    /// Text function could not index into a text, with strict functions enabled:
+    /// function, text, start and end byte index, reason
+    InvalidStringIndex(String, String, usize, usize, String),
 }
 
 impl fmt::Display for InterpreterError {
@@ -64,6 +74,26 @@ impl fmt::Display for InterpreterError {
             InterpreterError::ResultLimitExceeded(limit) => {
                 write!(f, "Result limit exceeded: more than {} results", limit)
             }
+            InterpreterError::ColumnLengthMismatch(column, found, expected) => write!(
+                f,
+                "Column length mismatch: column {} has {} values, expected {}",
+                column, found, expected
+            ),
+            InterpreterError::MemoryLimitExceeded(stage, used, budget) => write!(
+                f,
+                "Memory limit exceeded: intermediate results reached {} bytes at `{}`, budget is {} bytes",
+                used, stage, budget
+            ),
+            InterpreterError::InvalidStringIndex(function, text, start, end, reason) => {
+                // Long texts are shortened, keeping the start the indices refer to
+                let shown: String = text.chars().take(60).collect();
+                let ellipsis = if shown.len() < text.len() { "..." } else { "" };
+                write!(
+                    f,
+                    "Invalid string index: @{} cannot take {}..{} of {:?}{} ({} bytes): {}",
+                    function, start, end, shown, ellipsis, text.len(), reason
+                )
+            }
         }
     }
 }
@@ -71,8 +101,8 @@ impl fmt::Display for InterpreterError {
 impl Error for InterpreterError {}
 
 // Implement conversion for regex errors
-impl From<regex::Error> for InterpreterError {
-    fn from(err: regex::Error) -> Self {
+impl From<crate::pattern::PatternError> for InterpreterError {
+    fn from(err: crate::pattern::PatternError) -> Self {
         InterpreterError::InvalidRegex(format!("{}", err))
     }
 }
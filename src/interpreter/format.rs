@@ -0,0 +1,140 @@
+// interpreter/format.rs - JSON and CSV output for selection results
+//
+// Texts become a JSON array of strings or a single-column CSV. Nodes become objects
+// (or rows) with their tag name, attributes and text content, matching the shape of
+// the `serde` implementation.
+
+use super::html;
+use super::result::{NodeHandle, SelectionResult};
+use std::io::{self, Write};
+
+/// Format a result as JSON
+pub fn to_json(result: &SelectionResult) -> String {
+    let items: Vec<String> = match result {
+        SelectionResult::Texts(texts, _) => texts.iter().map(|t| json_string(t)).collect(),
+        SelectionResult::Nodes(nodes, _) => nodes.iter().map(node_json).collect(),
+    };
+
+    format!("[{}]", items.join(","))
+}
+
+/// Write a result as newline-delimited JSON, one object per item: `{"text": ...}` for
+/// texts, `{"tag", "attrs", "text"}` for nodes. Each item is serialized and written
+/// before the next, so the output is never buffered as a whole. Returns the number of
+/// lines written.
+pub fn write_ndjson<W: Write>(result: &SelectionResult, writer: &mut W) -> io::Result<usize> {
+    match result {
+        SelectionResult::Texts(texts, _) => {
+            for text in texts {
+                writeln!(writer, "{{\"text\":{}}}", json_string(text))?;
+            }
+        }
+        SelectionResult::Nodes(nodes, _) => {
+            for node in nodes {
+                writeln!(writer, "{}", node_json(node))?;
+            }
+        }
+    }
+
+    Ok(result.count())
+}
+
+/// Format a result as CSV with a header row.
+///
+/// Texts produce a single `text` column. Nodes produce `tag` and `text` columns
+/// followed by one column per attribute name, in order of first appearance.
+pub fn to_csv(result: &SelectionResult) -> String {
+    let mut rows: Vec<Vec<String>> = Vec::new();
+
+    match result {
+        SelectionResult::Texts(texts, _) => {
+            rows.push(vec!["text".to_string()]);
+            rows.extend(texts.iter().map(|t| vec![t.clone()]));
+        }
+        SelectionResult::Nodes(nodes, _) => {
+            let mut attr_names: Vec<&str> = Vec::new();
+            for node in nodes {
+                for attr in node.attrs() {
+                    if !attr_names.contains(&attr.name.as_str()) {
+                        attr_names.push(&attr.name);
+                    }
+                }
+            }
+
+            let mut header = vec!["tag".to_string(), "text".to_string()];
+            header.extend(attr_names.iter().map(|name| name.to_string()));
+            rows.push(header);
+
+            for node in nodes {
+                let mut row = vec![
+                    node.tag_name().unwrap_or_default().to_string(),
+                    html::extract_text(node).unwrap_or_default(),
+                ];
+                for name in &attr_names {
+                    let value = node.attrs().iter().find(|a| a.name == *name);
+                    row.push(value.map(|a| a.value.clone()).unwrap_or_default());
+                }
+                rows.push(row);
+            }
+        }
+    }
+
+    let mut csv = String::new();
+    for row in rows {
+        let fields: Vec<String> = row.iter().map(|f| csv_field(f)).collect();
+        csv.push_str(&fields.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+fn node_json(node: &NodeHandle) -> String {
+    let tag = match node.tag_name() {
+        Some(tag) => json_string(tag),
+        None => "null".to_string(),
+    };
+
+    let attrs: Vec<String> = node.attrs()
+        .iter()
+        .map(|a| format!("{}:{}", json_string(&a.name), json_string(&a.value)))
+        .collect();
+
+    let text = html::extract_text(node).unwrap_or_default();
+
+    format!(
+        "{{\"tag\":{},\"attrs\":{{{}}},\"text\":{}}}",
+        tag,
+        attrs.join(","),
+        json_string(&text)
+    )
+}
+
+/// Quote and escape a JSON string
+fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\u{08}' => out.push_str("\\b"),
+            '\u{0c}' => out.push_str("\\f"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Quote a CSV field if it contains a separator, quote or line break (RFC 4180)
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
@@ -1,12 +1,326 @@
+use std::borrow::Cow;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+
+use crate::pattern::Pattern;
 use crate::{FunctionNode, parser::ast::Literal};
 
-use super::{Interpreter, InterpreterError, InterpreterResult};
+use super::datetime::DateTime;
+use super::dom::NodeData;
+use super::html;
+use super::url;
+use super::{EmptyReason, Interpreter, InterpreterError, InterpreterResult, SelectionResult};
 
 pub fn apply_function(it: &mut Interpreter, node: &FunctionNode) -> InterpreterResult<()> {
-    let texts = it.result.texts_mut()?;
+    if it.result.is_nodes() {
+        return apply_node_function(it, node);
+    }
 
-    match node.name.as_str() {
-        "trim" => trim(texts),
+    if node.name == "absolute" {
+        return absolute(it, node);
+    }
+
+    if node.name == "as_html" {
+        if !node.arguments.is_empty() {
+            return Err(InterpreterError::InvalidArgument(
+                "as_html does not take arguments.".to_string(),
+            ));
+        }
+        return as_html(it);
+    }
+
+    let function = compile_text_function(node, it.options.strict_functions)?;
+    function(it.result.texts_mut()?)
+}
+
+/// Resolve the texts as URLs against the base given as argument, or else the base of
+/// the document. Texts are kept as they are when there is no absolute base.
+fn absolute(it: &mut Interpreter, node: &FunctionNode) -> InterpreterResult<()> {
+    let base = match &node.arguments[..] {
+        [] => url::document_base(&it.document, it.options.base_url.as_deref()),
+        [Literal::Str(base)] => Some(base.clone()),
+        [_] => {
+            return Err(InterpreterError::InvalidArgument(
+                "absolute expect a value of type str".to_string(),
+            ));
+        }
+        _ => {
+            return Err(InterpreterError::MissingArgument(
+                "absolute takes at most 1 argument.".to_string(),
+            ));
+        }
+    };
+
+    let Some(base) = base else {
+        return Ok(());
+    };
+    for text in it.result.texts_mut()? {
+        if let Some(resolved) = url::resolve(&base, text) {
+            *text = resolved;
+        }
+    }
+
+    Ok(())
+}
+
+/// Parse each text as HTML and continue with the `html` elements of the parsed
+/// fragments. Markup that is escaped as a whole, with `&lt;` and no `<`, is decoded
+/// first.
+fn as_html(it: &mut Interpreter) -> InterpreterResult<()> {
+    let mut nodes = Vec::new();
+    for text in it.result.texts()? {
+        let markup = if !text.contains('<') && text.contains("&lt;") {
+            Cow::Owned(html::html_to_text(text)?)
+        } else {
+            Cow::Borrowed(text.as_str())
+        };
+        let root = html::parse_html(&markup)?;
+        let element = root
+            .children()
+            .into_iter()
+            .find(|child| child.tag_name() == Some("html"))
+            .unwrap_or(root);
+        nodes.push(element);
+    }
+
+    it.result = SelectionResult::with_nodes(nodes);
+    Ok(())
+}
+
+/// Text functions that keep, drop or rewrite each text on its own. Consecutive calls of
+/// these are fused into a single pass over the texts.
+const PER_ITEM_FUNCTIONS: &[&str] = &[
+    "trim",
+    "trim_start",
+    "trim_end",
+    "collapse_ws",
+    #[cfg(feature = "base64")]
+    "base64_encode",
+    #[cfg(feature = "base64")]
+    "base64_decode",
+    #[cfg(feature = "hash")]
+    "hash",
+    #[cfg(feature = "chrono")]
+    "parse_date",
+    "replace",
+    "lowercase",
+    "uppercase",
+    "capitalize",
+    "title_case",
+    "format",
+    "contains",
+    "starts_with",
+    "ends_with",
+    "icontains",
+    "istarts_with",
+    "iends_with",
+    "iequals",
+    "in",
+    "slice",
+    "parse_price",
+    "filter_empty",
+    "to_int",
+    "to_float",
+    "numeric",
+    "extract_numbers",
+    "extract_emails",
+    "extract_urls",
+    "html_to_text",
+    "map_lookup",
+    "parse_relative_date",
+    "regex_replace",
+    "regex_match",
+    "regex_filter",
+    "regex_capture",
+];
+
+/// Check whether a function handles each text independently of the others
+pub fn is_per_item(name: &str) -> bool {
+    PER_ITEM_FUNCTIONS.contains(&name)
+}
+
+/// Apply a chain of per-item text functions, innermost first, in one pass: each text
+/// runs through the whole chain before the next one is read, instead of the texts being
+/// walked once per function.
+pub fn apply_fused(it: &mut Interpreter, chain: &[&FunctionNode]) -> InterpreterResult<()> {
+    let functions = chain
+        .iter()
+        .map(|node| compile_text_function(node, it.options.strict_functions))
+        .collect::<InterpreterResult<Vec<_>>>()?;
+
+    let texts = std::mem::take(it.result.texts_mut()?);
+    let input = texts.len();
+
+    // Number of texts that came out of each function, to report which one emptied the
+    // result as unfused evaluation would
+    let mut survivors = vec![0; functions.len()];
+    let mut result = Vec::with_capacity(texts.len());
+    let mut item = Vec::with_capacity(1);
+
+    for text in texts {
+        item.push(text);
+        for (function, survived) in functions.iter().zip(survivors.iter_mut()) {
+            function(&mut item)?;
+            if item.is_empty() {
+                break;
+            }
+            *survived += 1;
+        }
+        result.append(&mut item);
+    }
+
+    if it.empty_reason.is_none() && input > 0 && result.is_empty() {
+        let stage = survivors.iter().position(|n| *n == 0).unwrap_or(0);
+        let received = if stage == 0 { input } else { survivors[stage - 1] };
+        it.empty_reason = Some(EmptyReason {
+            stage: format!("@{}", chain[stage]),
+            input: received,
+        });
+    }
+
+    *it.result.texts_mut()? = result;
+    Ok(())
+}
+
+/// Function over the current texts, with its arguments checked and bound
+type TextFunction<'a> = Box<dyn Fn(&mut Vec<String>) -> InterpreterResult<()> + 'a>;
+
+fn text_fn<'a>(f: impl Fn(&mut Vec<String>) -> InterpreterResult<()> + 'a) -> TextFunction<'a> {
+    Box::new(f)
+}
+
+/// Check the arguments of a text function and bind them
+fn compile_text_function(node: &FunctionNode, strict: bool) -> InterpreterResult<TextFunction<'_>> {
+    let function = match node.name.as_str() {
+        "trim" => text_fn(|texts| {
+            trim(texts);
+            Ok(())
+        }),
+        "trim_start" => text_fn(|texts| {
+            texts
+                .iter_mut()
+                .for_each(|text| *text = text.trim_start().to_string());
+            Ok(())
+        }),
+        "trim_end" => text_fn(|texts| {
+            texts
+                .iter_mut()
+                .for_each(|text| *text = text.trim_end().to_string());
+            Ok(())
+        }),
+        "collapse_ws" => text_fn(|texts| {
+            collapse_ws(texts);
+            Ok(())
+        }),
+        #[cfg(feature = "chrono")]
+        "parse_date" => {
+            if node.arguments.is_empty() || node.arguments.len() > 2 {
+                return Err(InterpreterError::MissingArgument(
+                    "parse_date must include 1 or 2 arguments.".to_string(),
+                ));
+            }
+
+            let mut formats = Vec::new();
+            match &node.arguments[0] {
+                Literal::Str(v) => formats.push(v.as_str()),
+                Literal::List(list) => {
+                    for item in list {
+                        match item {
+                            Literal::Str(v) => formats.push(v.as_str()),
+                            _ => {
+                                return Err(InterpreterError::InvalidArgument(
+                                    "parse_date expect a value of type str or list<str>".to_string(),
+                                ));
+                            }
+                        }
+                    }
+                }
+                _ => {
+                    return Err(InterpreterError::InvalidArgument(
+                        "parse_date expect a value of type str or list<str>".to_string(),
+                    ));
+                }
+            };
+
+            let output = match node.arguments.get(1) {
+                Some(Literal::Str(v)) => Some(v.as_str()),
+                None => None,
+                _ => {
+                    return Err(InterpreterError::InvalidArgument(
+                        "The second parameter of parse_date expects a value of type str.".to_string(),
+                    ));
+                }
+            };
+            if let Some(output) = output {
+                check_date_format(output)?;
+            }
+
+            text_fn(move |texts| {
+                parse_date(texts, &formats, output);
+                Ok(())
+            })
+        }
+        #[cfg(feature = "hash")]
+        "hash" => {
+            let algorithm = match &node.arguments[..] {
+                [] => "sha256",
+                [Literal::Str(v)] => v.as_str(),
+                [_] => {
+                    return Err(InterpreterError::InvalidArgument(
+                        "hash expect a value of type str".to_string(),
+                    ));
+                }
+                _ => {
+                    return Err(InterpreterError::MissingArgument(
+                        "hash must include 0 or 1 argument.".to_string(),
+                    ));
+                }
+            };
+            let digest: fn(&[u8]) -> String = match algorithm {
+                "sha256" => |bytes| {
+                    use sha2::Digest;
+                    hex(&sha2::Sha256::digest(bytes))
+                },
+                "md5" => |bytes| {
+                    use md5::Digest;
+                    hex(&md5::Md5::digest(bytes))
+                },
+                "xxh64" => |bytes| format!("{:016x}", xxhash_rust::xxh64::xxh64(bytes, 0)),
+                "xxh3" | "xxhash" => |bytes| format!("{:016x}", xxhash_rust::xxh3::xxh3_64(bytes)),
+                _ => {
+                    return Err(InterpreterError::InvalidArgument(format!(
+                        "hash supports sha256, md5, xxh64 and xxh3, found {}",
+                        algorithm
+                    )));
+                }
+            };
+            text_fn(move |texts| {
+                for text in texts.iter_mut() {
+                    *text = digest(text.as_bytes());
+                }
+                Ok(())
+            })
+        }
+        #[cfg(feature = "base64")]
+        "base64_encode" | "base64_decode" => {
+            if !node.arguments.is_empty() {
+                return Err(InterpreterError::InvalidArgument(format!(
+                    "{} does not take arguments.",
+                    node.name
+                )));
+            }
+            if node.name == "base64_encode" {
+                text_fn(|texts| {
+                    base64_encode(texts);
+                    Ok(())
+                })
+            } else {
+                text_fn(|texts| {
+                    base64_decode(texts);
+                    Ok(())
+                })
+            }
+        }
         "replace" => {
             if node.arguments.len() != 2 {
                 return Err(InterpreterError::MissingArgument(
@@ -29,10 +343,27 @@ pub fn apply_function(it: &mut Interpreter, node: &FunctionNode) -> InterpreterR
                     ));
                 }
             };
-            replace(texts, value0, value1)
+            text_fn(move |texts| {
+                replace(texts, value0, value1);
+                Ok(())
+            })
         }
-        "lowercase" => lowercase(texts),
-        "uppercase" => uppercase(texts),
+        "lowercase" => text_fn(|texts| {
+            lowercase(texts);
+            Ok(())
+        }),
+        "uppercase" => text_fn(|texts| {
+            uppercase(texts);
+            Ok(())
+        }),
+        "capitalize" => text_fn(|texts| {
+            capitalize(texts);
+            Ok(())
+        }),
+        "title_case" => text_fn(|texts| {
+            title_case(texts);
+            Ok(())
+        }),
         "join" => {
             let value0 = if node.arguments.len() == 1 {
                 match &node.arguments[0] {
@@ -50,7 +381,10 @@ pub fn apply_function(it: &mut Interpreter, node: &FunctionNode) -> InterpreterR
                     "join must include 0 or 1 argument.".to_string(),
                 ));
             };
-            join(texts, value0)
+            text_fn(move |texts| {
+                join(texts, value0);
+                Ok(())
+            })
         }
         "format" => {
             let value0 = if node.arguments.len() == 1 {
@@ -67,7 +401,10 @@ pub fn apply_function(it: &mut Interpreter, node: &FunctionNode) -> InterpreterR
                     "format must include 1 argument.".to_string(),
                 ));
             };
-            format(texts, value0)
+            text_fn(move |texts| {
+                format(texts, value0);
+                Ok(())
+            })
         }
         "contains" => {
             let value0 = if node.arguments.len() == 1 {
@@ -84,7 +421,99 @@ pub fn apply_function(it: &mut Interpreter, node: &FunctionNode) -> InterpreterR
                     "contains must include 1 argument.".to_string(),
                 ));
             };
-            contains(texts, value0);
+            text_fn(move |texts| {
+                contains(texts, value0);
+                Ok(())
+            })
+        }
+        "regex_replace" => {
+            let (pattern, replacement) = match &node.arguments[..] {
+                [Literal::Str(pattern), Literal::Str(replacement)] => (pattern, replacement),
+                [_, _] => {
+                    return Err(InterpreterError::InvalidArgument(
+                        "regex_replace expect a pattern and a replacement of type str".to_string(),
+                    ));
+                }
+                _ => {
+                    return Err(InterpreterError::MissingArgument(
+                        "regex_replace must include 2 arguments.".to_string(),
+                    ));
+                }
+            };
+            let pattern = Pattern::new(pattern)?;
+            text_fn(move |texts| {
+                for text in texts.iter_mut() {
+                    if let Cow::Owned(replaced) = pattern.replace_all(text, replacement) {
+                        *text = replaced;
+                    }
+                }
+                Ok(())
+            })
+        }
+        "regex_capture" => {
+            let (pattern, group) = match &node.arguments[..] {
+                [Literal::Str(pattern)] => (pattern, 1),
+                [Literal::Str(pattern), Literal::Int(group)] if *group >= 0 => {
+                    (pattern, *group as usize)
+                }
+                [_] | [_, _] => {
+                    return Err(InterpreterError::InvalidArgument(
+                        "regex_capture expect a pattern of type str and a group number of type int"
+                            .to_string(),
+                    ));
+                }
+                _ => {
+                    return Err(InterpreterError::MissingArgument(
+                        "regex_capture must include 1 or 2 arguments.".to_string(),
+                    ));
+                }
+            };
+            let pattern = Pattern::new(pattern)?;
+            if group >= pattern.captures_len() {
+                return Err(InterpreterError::InvalidArgument(format!(
+                    "regex_capture: the pattern `{}` has no group {}",
+                    pattern.as_str(),
+                    group
+                )));
+            }
+            text_fn(move |texts| {
+                *texts = texts
+                    .iter()
+                    .filter_map(|text| pattern.capture(text, group).map(str::to_string))
+                    .collect();
+                Ok(())
+            })
+        }
+        "regex_match" | "regex_filter" => {
+            let pattern = match &node.arguments[..] {
+                [Literal::Str(pattern)] => Pattern::new(pattern)?,
+                [_] => {
+                    return Err(InterpreterError::InvalidArgument(format!(
+                        "{} expect a value of type str",
+                        node.name
+                    )));
+                }
+                _ => {
+                    return Err(InterpreterError::MissingArgument(format!(
+                        "{} must include 1 argument.",
+                        node.name
+                    )));
+                }
+            };
+            if node.name == "regex_match" {
+                text_fn(move |texts| {
+                    *texts = texts
+                        .iter()
+                        .filter_map(|text| pattern.find(text).map(str::to_string))
+                        .collect();
+                    Ok(())
+                })
+            } else {
+                text_fn(move |texts| {
+                    texts.retain(|text| pattern.is_match(text));
+                    Ok(())
+                })
+            }
         }
         "starts_with" => {
             let value0 = if node.arguments.len() == 1 {
@@ -101,7 +530,10 @@ pub fn apply_function(it: &mut Interpreter, node: &FunctionNode) -> InterpreterR
                     "starts_with must include 1 argument.".to_string(),
                 ));
             };
-            starts_with(texts, value0);
+            text_fn(move |texts| {
+                starts_with(texts, value0);
+                Ok(())
+            })
         }
         "ends_with" => {
             let value0 = if node.arguments.len() == 1 {
@@ -118,7 +550,38 @@ pub fn apply_function(it: &mut Interpreter, node: &FunctionNode) -> InterpreterR
                     "ends_with must include 1 argument.".to_string(),
                 ));
             };
-            ends_with(texts, value0);
+            text_fn(move |texts| {
+                ends_with(texts, value0);
+                Ok(())
+            })
+        }
+        "icontains" | "istarts_with" | "iends_with" | "iequals" => {
+            let value0 = match &node.arguments[..] {
+                [Literal::Str(v)] => v.to_lowercase(),
+                [_] => {
+                    return Err(InterpreterError::InvalidArgument(format!(
+                        "{} expect a value of type str",
+                        node.name
+                    )));
+                }
+                _ => {
+                    return Err(InterpreterError::MissingArgument(format!(
+                        "{} must include 1 argument.",
+                        node.name
+                    )));
+                }
+            };
+            let matches: fn(&str, &str) -> bool = match node.name.as_str() {
+                "icontains" => |text, value| text.contains(value),
+                "istarts_with" => |text, value| text.starts_with(value),
+                "iends_with" => |text, value| text.ends_with(value),
+                _ => |text, value| text == value,
+            };
+            // Texts are compared lowercased but kept with their original case
+            text_fn(move |texts| {
+                texts.retain(|text| matches(&text.to_lowercase(), &value0));
+                Ok(())
+            })
         }
         "in" => {
             let value0 = if node.arguments.len() == 1 {
@@ -149,7 +612,12 @@ pub fn apply_function(it: &mut Interpreter, node: &FunctionNode) -> InterpreterR
                 ));
             };
 
-            in_(texts, value0);
+            // Built once, as the closure runs per text when fused
+            let list: HashSet<String> = value0.into_iter().collect();
+            text_fn(move |texts| {
+                in_(texts, &list);
+                Ok(())
+            })
         }
         "slice" => {
             if node.arguments.len() != 2 {
@@ -192,108 +660,973 @@ pub fn apply_function(it: &mut Interpreter, node: &FunctionNode) -> InterpreterR
                 }
             };
 
-            slice(texts, st, ed);
+            text_fn(move |texts| slice(texts, st, ed, strict))
         }
-        _ => return Err(InterpreterError::UnknownFunction(node.name.clone())),
-    };
-
-    Ok(())
-}
-
-fn trim(texts: &mut Vec<String>) {
-    texts
-        .iter_mut()
-        .for_each(|text| *text = text.trim().to_string());
-}
-
-fn replace(texts: &mut Vec<String>, search: &str, replacement: &str) {
-    texts
-        .iter_mut()
-        .for_each(|text| *text = text.replace(search, replacement))
-}
-
-fn lowercase(texts: &mut Vec<String>) {
-    texts
-        .iter_mut()
-        .for_each(|text| *text = text.to_lowercase())
-}
-
-fn uppercase(texts: &mut Vec<String>) {
-    texts
-        .iter_mut()
-        .for_each(|text| *text = text.to_uppercase())
-}
-
-fn join(texts: &mut Vec<String>, separator: &str) {
-    *texts = vec![texts.join(separator)]
-}
-
-fn format(texts: &mut Vec<String>, format_str: &str) {
-    texts.iter_mut().for_each(|text| {
-        if format_str.contains("{}") {
-            *text = format!("{}", format_str.replacen("{}", &text, 1));
-        } else if format_str.starts_with("%") {
-            match format_str {
-                "%s" => { /* Keep as is, equivalent to {} */ }
-                "%d" | "%i" => {
-                    if let Ok(num) = text.trim().parse::<i64>() {
-                        *text = num.to_string();
+        "parse_price" => {
+            let value0 = if node.arguments.len() == 1 {
+                match &node.arguments[0] {
+                    Literal::Str(v) => Some(v.as_str()),
+                    _ => {
+                        return Err(InterpreterError::InvalidArgument(
+                            "parse_price expect a value of type str".to_string(),
+                        ));
                     }
                 }
-                "%f" => {
-                    if let Ok(num) = text.trim().parse::<f64>() {
-                        *text = num.to_string();
-                    }
+            } else if node.arguments.is_empty() {
+                None
+            } else {
+                return Err(InterpreterError::MissingArgument(
+                    "parse_price must include 0 or 1 argument.".to_string(),
+                ));
+            };
+            text_fn(move |texts| {
+                parse_price(texts, value0);
+                Ok(())
+            })
+        }
+        "to_int" | "to_float" | "numeric" => {
+            if !node.arguments.is_empty() {
+                return Err(InterpreterError::InvalidArgument(format!(
+                    "{} does not take arguments.",
+                    node.name
+                )));
+            }
+            match node.name.as_str() {
+                "to_int" => text_fn(|texts| {
+                    to_int(texts);
+                    Ok(())
+                }),
+                "to_float" => text_fn(|texts| {
+                    to_float(texts);
+                    Ok(())
+                }),
+                _ => text_fn(|texts| {
+                    texts.retain(|text| is_number(text));
+                    Ok(())
+                }),
+            }
+        }
+        "filter_empty" => {
+            if !node.arguments.is_empty() {
+                return Err(InterpreterError::InvalidArgument(
+                    "filter_empty does not take arguments.".to_string(),
+                ));
+            }
+            text_fn(|texts| {
+                texts.retain(|text| !text.trim().is_empty());
+                Ok(())
+            })
+        }
+        "default" => {
+            let placeholder = match &node.arguments[..] {
+                [Literal::Str(v)] => v,
+                [_] => {
+                    return Err(InterpreterError::InvalidArgument(
+                        "default expect a value of type str".to_string(),
+                    ));
                 }
-                "%x" => {
-                    if let Ok(num) = text.trim().parse::<i64>() {
-                        *text = format!("{:x}", num);
-                    }
+                _ => {
+                    return Err(InterpreterError::MissingArgument(
+                        "default must include 1 argument.".to_string(),
+                    ));
                 }
-                "%X" => {
-                    if let Ok(num) = text.trim().parse::<i64>() {
-                        *text = format!("{:X}", num);
-                    }
+            };
+            text_fn(move |texts| {
+                if texts.is_empty() {
+                    texts.push(placeholder.clone());
+                }
+                Ok(())
+            })
+        }
+        "reverse" | "first" | "last" => {
+            if !node.arguments.is_empty() {
+                return Err(InterpreterError::InvalidArgument(format!(
+                    "{} does not take arguments.",
+                    node.name
+                )));
+            }
+            match node.name.as_str() {
+                "reverse" => text_fn(|texts| {
+                    texts.reverse();
+                    Ok(())
+                }),
+                "first" => text_fn(|texts| {
+                    texts.truncate(1);
+                    Ok(())
+                }),
+                _ => text_fn(|texts| {
+                    let start = texts.len().saturating_sub(1);
+                    texts.drain(..start);
+                    Ok(())
+                }),
+            }
+        }
+        "limit" | "skip" => {
+            let n = match &node.arguments[..] {
+                [Literal::Int(n)] if *n >= 0 => *n as usize,
+                [_] => {
+                    return Err(InterpreterError::InvalidArgument(format!(
+                        "{} expect a value of type int, at least 0",
+                        node.name
+                    )));
                 }
                 _ => {
-                    *text = format!("{}{}", format_str, text);
+                    return Err(InterpreterError::MissingArgument(format!(
+                        "{} must include 1 argument.",
+                        node.name
+                    )));
                 }
+            };
+            if node.name == "limit" {
+                text_fn(move |texts| {
+                    texts.truncate(n);
+                    Ok(())
+                })
+            } else {
+                text_fn(move |texts| {
+                    texts.drain(..n.min(texts.len()));
+                    Ok(())
+                })
             }
-        } else {
-            *text = format!("{}{}", format_str, text);
         }
-    })
-}
-
-fn contains(texts: &mut Vec<String>, inner: &str) {
-    let mut result = Vec::new();
-
-    for text in texts.iter() {
-        if text.contains(inner) {
-            result.push(text.clone());
+        "sort" | "sort_numeric" => {
+            let descending = match &node.arguments[..] {
+                [] => false,
+                [Literal::Str(order)] if order == "asc" => false,
+                [Literal::Str(order)] if order == "desc" => true,
+                [_] => {
+                    return Err(InterpreterError::InvalidArgument(format!(
+                        "{} expects \"asc\" or \"desc\"",
+                        node.name
+                    )));
+                }
+                _ => {
+                    return Err(InterpreterError::MissingArgument(format!(
+                        "{} must include 0 or 1 argument.",
+                        node.name
+                    )));
+                }
+            };
+            if node.name == "sort" {
+                text_fn(move |texts| {
+                    texts.sort();
+                    if descending {
+                        texts.reverse();
+                    }
+                    Ok(())
+                })
+            } else {
+                text_fn(move |texts| {
+                    sort_numeric(texts, descending);
+                    Ok(())
+                })
+            }
         }
-    }
+        "count" | "sum" | "min" | "max" | "avg" => {
+            if !node.arguments.is_empty() {
+                return Err(InterpreterError::InvalidArgument(format!(
+                    "{} does not take arguments.",
+                    node.name
+                )));
+            }
+            let name = node.name.as_str();
+            text_fn(move |texts| {
+                aggregate(texts, name);
+                Ok(())
+            })
+        }
+        "extract_numbers" | "extract_emails" | "extract_urls" => {
+            if !node.arguments.is_empty() {
+                return Err(InterpreterError::InvalidArgument(format!(
+                    "{} does not take arguments.",
+                    node.name
+                )));
+            }
+            match node.name.as_str() {
+                "extract_numbers" => text_fn(|texts| {
+                    extract_numbers(texts);
+                    Ok(())
+                }),
+                "extract_emails" => {
+                    let pattern = Pattern::new(EMAIL_PATTERN)?;
+                    text_fn(move |texts| {
+                        *texts = texts
+                            .iter()
+                            .flat_map(|text| pattern.find_all(text))
+                            .map(str::to_string)
+                            .collect();
+                        Ok(())
+                    })
+                }
+                _ => {
+                    let pattern = Pattern::new(URL_PATTERN)?;
+                    text_fn(move |texts| {
+                        *texts = texts
+                            .iter()
+                            .flat_map(|text| pattern.find_all(text))
+                            .map(|url| trim_url(url).to_string())
+                            .collect();
+                        Ok(())
+                    })
+                }
+            }
+        }
+        "html_to_text" => text_fn(|texts| html_to_text(texts)),
+        "chunk" => {
+            if node.arguments.is_empty() || node.arguments.len() > 2 {
+                return Err(InterpreterError::MissingArgument(
+                    "chunk must include 1 or 2 arguments.".to_string(),
+                ));
+            };
 
-    *texts = result;
-}
+            let size = match &node.arguments[0] {
+                Literal::Int(n) => {
+                    if *n <= 0 {
+                        return Err(InterpreterError::InvalidArgument(
+                            "chunk's size must be greater than 0.".to_string(),
+                        ));
+                    };
+                    *n as usize
+                }
+                _ => {
+                    return Err(InterpreterError::InvalidArgument(
+                        "The first parameter of chunk expects a value of type int.".to_string(),
+                    ));
+                }
+            };
 
-fn starts_with(texts: &mut Vec<String>, st: &str) {
-    let mut result = Vec::new();
+            let separator = match node.arguments.get(1) {
+                Some(Literal::Str(v)) => v.as_str(),
+                None => " ",
+                _ => {
+                    return Err(InterpreterError::InvalidArgument(
+                        "The second parameter of chunk expects a value of type str.".to_string(),
+                    ));
+                }
+            };
 
-    for text in texts.iter() {
-        if text.starts_with(st) {
-            result.push(text.clone());
+            text_fn(move |texts| {
+                chunk(texts, size, separator);
+                Ok(())
+            })
         }
-    }
-
-    *texts = result;
+        "map_lookup" => {
+            if node.arguments.is_empty() || node.arguments.len() > 2 {
+                return Err(InterpreterError::MissingArgument(
+                    "map_lookup must include 1 or 2 arguments.".to_string(),
+                ));
+            };
+
+            let mut values = Vec::new();
+            match &node.arguments[0] {
+                Literal::List(list) => {
+                    for item in list {
+                        match item {
+                            Literal::Str(v) => values.push(v.as_str()),
+                            _ => {
+                                return Err(InterpreterError::InvalidArgument(
+                                    "map_lookup expect a value of type list<str>".to_string(),
+                                ));
+                            }
+                        };
+                    }
+                }
+                _ => {
+                    return Err(InterpreterError::InvalidArgument(
+                        "map_lookup expect a value of type list<str>".to_string(),
+                    ));
+                }
+            };
+            if values.len() % 2 != 0 {
+                return Err(InterpreterError::InvalidArgument(
+                    "map_lookup expects a list of key/value pairs.".to_string(),
+                ));
+            }
+
+            let default = match node.arguments.get(1) {
+                Some(Literal::Str(v)) => Some(v.as_str()),
+                None => None,
+                _ => {
+                    return Err(InterpreterError::InvalidArgument(
+                        "The second parameter of map_lookup expects a value of type str.".to_string(),
+                    ));
+                }
+            };
+
+            text_fn(move |texts| {
+                map_lookup(texts, &values, default);
+                Ok(())
+            })
+        }
+        "parse_relative_date" => {
+            let value0 = if node.arguments.len() == 1 {
+                match &node.arguments[0] {
+                    Literal::Str(v) => v,
+                    _ => {
+                        return Err(InterpreterError::InvalidArgument(
+                            "parse_relative_date expect a value of type str".to_string(),
+                        ));
+                    }
+                }
+            } else {
+                return Err(InterpreterError::MissingArgument(
+                    "parse_relative_date must include 1 argument.".to_string(),
+                ));
+            };
+            let now = DateTime::parse_iso(value0).ok_or_else(|| {
+                InterpreterError::InvalidArgument(format!(
+                    "parse_relative_date expects an ISO 8601 reference time, found {}",
+                    value0
+                ))
+            })?;
+            text_fn(move |texts| {
+                parse_relative_date(texts, &now);
+                Ok(())
+            })
+        }
+        _ => return Err(InterpreterError::UnknownFunction(node.name.clone())),
+    };
+
+    Ok(function)
 }
 
-fn ends_with(texts: &mut Vec<String>, ed: &str) {
+/// Apply a function that filters or transforms the current node set
+fn apply_node_function(it: &mut Interpreter, node: &FunctionNode) -> InterpreterResult<()> {
+    if node.name == "closest" {
+        let value0 = if node.arguments.len() == 1 {
+            match &node.arguments[0] {
+                Literal::Str(v) => v,
+                _ => {
+                    return Err(InterpreterError::InvalidArgument(
+                        "closest expect a value of type str".to_string(),
+                    ));
+                }
+            }
+        } else {
+            return Err(InterpreterError::MissingArgument(
+                "closest must include 1 argument.".to_string(),
+            ));
+        };
+        return closest(it, value0);
+    }
+
+    if node.name == "between" {
+        let mut markers = Vec::new();
+        for arg in &node.arguments {
+            match arg {
+                Literal::Str(v) => markers.push(v.as_str()),
+                _ => {
+                    return Err(InterpreterError::InvalidArgument(
+                        "between expect a value of type str".to_string(),
+                    ));
+                }
+            }
+        }
+        return match markers[..] {
+            [start] => between(it, start, start),
+            [start, end] => between(it, start, end),
+            _ => Err(InterpreterError::MissingArgument(
+                "between must include 1 or 2 arguments.".to_string(),
+            )),
+        };
+    }
+
+    if node.name == "template" {
+        let template = match &node.arguments[..] {
+            [Literal::Str(v)] => parse_template(v)?,
+            [_] => {
+                return Err(InterpreterError::InvalidArgument(
+                    "template expect a value of type str".to_string(),
+                ));
+            }
+            _ => {
+                return Err(InterpreterError::MissingArgument(
+                    "template must include 1 argument.".to_string(),
+                ));
+            }
+        };
+        return fill_template(it, &template);
+    }
+
+    let nodes = it.result.nodes_mut()?;
+
+    match node.name.as_str() {
+        "count" => {
+            if !node.arguments.is_empty() {
+                return Err(InterpreterError::InvalidArgument(
+                    "count does not take arguments.".to_string(),
+                ));
+            }
+            let count = nodes.len();
+            it.result = SelectionResult::with_texts(vec![count.to_string()]);
+        }
+        "has_class" => {
+            let class = match &node.arguments[..] {
+                [Literal::Str(v)] => v,
+                [_] => {
+                    return Err(InterpreterError::InvalidArgument(
+                        "has_class expect a value of type str".to_string(),
+                    ));
+                }
+                _ => {
+                    return Err(InterpreterError::MissingArgument(
+                        "has_class must include 1 argument.".to_string(),
+                    ));
+                }
+            };
+            nodes.retain(|n| {
+                n.attrs()
+                    .iter()
+                    .any(|a| a.name == "class" && a.value.split_whitespace().any(|c| c == class))
+            });
+        }
+        "has_attr" => {
+            let (name, value) = match &node.arguments[..] {
+                [Literal::Str(name)] => (name, None),
+                [Literal::Str(name), Literal::Str(value)] => (name, Some(value)),
+                [_] | [_, _] => {
+                    return Err(InterpreterError::InvalidArgument(
+                        "has_attr expect a name and a value of type str".to_string(),
+                    ));
+                }
+                _ => {
+                    return Err(InterpreterError::MissingArgument(
+                        "has_attr must include 1 or 2 arguments.".to_string(),
+                    ));
+                }
+            };
+            nodes.retain(|n| {
+                n.attrs()
+                    .iter()
+                    .any(|a| a.name == *name && value.is_none_or(|v| a.value == *v))
+            });
+        }
+        "child_count" => {
+            let (min, max) = match &node.arguments[..] {
+                [Literal::Int(min)] if *min >= 0 => (*min as usize, usize::MAX),
+                [Literal::Int(min), Literal::Int(max)] if *min >= 0 && max >= min => {
+                    (*min as usize, *max as usize)
+                }
+                [_] | [_, _] => {
+                    return Err(InterpreterError::InvalidArgument(
+                        "child_count expect a minimum and a maximum of type int, at least 0".to_string(),
+                    ));
+                }
+                _ => {
+                    return Err(InterpreterError::MissingArgument(
+                        "child_count must include 1 or 2 arguments.".to_string(),
+                    ));
+                }
+            };
+            nodes.retain(|n| {
+                let count = n.children().iter().filter(|c| c.is_element()).count();
+                (min..=max).contains(&count)
+            });
+        }
+        "text_contains" => {
+            let inner = match &node.arguments[..] {
+                [Literal::Str(v)] => v,
+                [_] => {
+                    return Err(InterpreterError::InvalidArgument(
+                        "text_contains expect a value of type str".to_string(),
+                    ));
+                }
+                _ => {
+                    return Err(InterpreterError::MissingArgument(
+                        "text_contains must include 1 argument.".to_string(),
+                    ));
+                }
+            };
+            let mut kept = Vec::with_capacity(nodes.len());
+            for n in nodes.drain(..) {
+                if html::extract_text_with(&n, &it.options)?.contains(inner.as_str()) {
+                    kept.push(n);
+                }
+            }
+            *nodes = kept;
+        }
+        "visible" => {
+            if !node.arguments.is_empty() {
+                return Err(InterpreterError::InvalidArgument(
+                    "visible does not take arguments.".to_string(),
+                ));
+            }
+            nodes.retain(html::is_visible);
+        }
+        _ => {
+            return Err(InterpreterError::execution_error(format!(
+                "Function {} cannot be applied to nodes",
+                node.name
+            )));
+        }
+    };
+
+    Ok(())
+}
+
+/// Piece of a `@template` string
+enum TemplatePart {
+    Literal(String),
+
+    /// `{text}`, `{href}`, `{src}` or `{name}` for any other attribute
+    Field(String),
+}
+
+/// Split a template into literal text and `{field}` placeholders. `{{` and `}}` stand
+/// for literal braces.
+fn parse_template(template: &str) -> InterpreterResult<Vec<TemplatePart>> {
+    let mut parts = Vec::new();
+    let mut literal = String::new();
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                literal.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                literal.push('}');
+            }
+            '{' => {
+                let mut field = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => field.push(c),
+                        None => {
+                            return Err(InterpreterError::InvalidArgument(format!(
+                                "template: unclosed placeholder in `{}`",
+                                template
+                            )));
+                        }
+                    }
+                }
+                let field = field.trim();
+                if field.is_empty() {
+                    return Err(InterpreterError::InvalidArgument(format!(
+                        "template: empty placeholder in `{}`",
+                        template
+                    )));
+                }
+                if !literal.is_empty() {
+                    parts.push(TemplatePart::Literal(std::mem::take(&mut literal)));
+                }
+                parts.push(TemplatePart::Field(field.to_string()));
+            }
+            c => literal.push(c),
+        }
+    }
+    if !literal.is_empty() {
+        parts.push(TemplatePart::Literal(literal));
+    }
+
+    Ok(parts)
+}
+
+/// Replace each element with the template filled in from it. Attributes the element
+/// does not have are left empty.
+fn fill_template(it: &mut Interpreter, template: &[TemplatePart]) -> InterpreterResult<()> {
+    let nodes = it.result.nodes()?;
+    let mut texts = Vec::with_capacity(nodes.len());
+
+    for node in nodes {
+        let mut text = String::new();
+        for part in template {
+            match part {
+                TemplatePart::Literal(literal) => text.push_str(literal),
+                TemplatePart::Field(field) if field == "text" => {
+                    text.push_str(&html::extract_text_with(node, &it.options)?)
+                }
+                TemplatePart::Field(name) => {
+                    if let Some(value) = html::get_attribute(node, name) {
+                        text.push_str(&value);
+                    }
+                }
+            }
+        }
+        texts.push(text);
+    }
+
+    it.result = SelectionResult::with_texts(texts);
+    Ok(())
+}
+
+/// Replace each node with its nearest ancestor-or-self matching the selector
+fn closest(it: &mut Interpreter, selector: &str) -> InterpreterResult<()> {
+    let ast = it.parse_selector(selector)?;
+
+    // Matches of the selector, evaluated once per document the current nodes belong to
+    let mut matches: HashMap<usize, HashSet<usize>> = HashMap::new();
+    let mut seen = HashSet::new();
+    let mut result = Vec::new();
+
+    for node in it.result.nodes()? {
+        let root = node.root();
+        let ids = match matches.entry(root.id()) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let found = it.evaluate(SelectionResult::with_nodes(vec![root]), &ast)?;
+                entry.insert(found.nodes()?.iter().map(|n| n.id()).collect())
+            }
+        };
+
+        let mut current = Some(node.clone());
+        while let Some(candidate) = current {
+            if ids.contains(&candidate.id()) {
+                if seen.insert(candidate.id()) {
+                    result.push(candidate);
+                }
+                break;
+            }
+            current = candidate.parent();
+        }
+    }
+
+    it.result = SelectionResult::with_nodes(result);
+
+    Ok(())
+}
+
+/// Replace each node with the siblings that follow a start marker inside it, up to
+/// (but not including) the next start or end marker, or the end of the parent
+fn between(it: &mut Interpreter, start: &str, end: &str) -> InterpreterResult<()> {
+    let start_ast = it.parse_selector(start)?;
+    let end_ast = it.parse_selector(end)?;
+
+    let mut seen = HashSet::new();
+    let mut result = Vec::new();
+
+    for node in it.result.nodes()? {
+        let context = SelectionResult::with_nodes(vec![node.clone()]);
+        let starts = it.evaluate(context.clone(), &start_ast)?;
+        let ends = it.evaluate(context, &end_ast)?;
+
+        // A section also stops at the next start marker
+        let stops: HashSet<usize> = starts
+            .nodes()?
+            .iter()
+            .chain(ends.nodes()?)
+            .map(|n| n.id())
+            .collect();
+
+        for marker in starts.nodes()? {
+            let Some(parent) = marker.parent() else {
+                continue;
+            };
+
+            let siblings = parent.children();
+            let Some(pos) = siblings.iter().position(|s| s == marker) else {
+                continue;
+            };
+
+            for sibling in &siblings[pos + 1..] {
+                if stops.contains(&sibling.id()) {
+                    break;
+                }
+                let keep = match sibling.data() {
+                    NodeData::Element { .. } => true,
+                    // Whitespace between elements is not part of a section
+                    NodeData::Text { contents } => !contents.trim().is_empty(),
+                    _ => false,
+                };
+                if keep && seen.insert(sibling.id()) {
+                    result.push(sibling.clone());
+                }
+            }
+        }
+    }
+
+    it.result = SelectionResult::with_nodes(result);
+
+    Ok(())
+}
+
+fn trim(texts: &mut Vec<String>) {
+    texts
+        .iter_mut()
+        .for_each(|text| *text = text.trim().to_string());
+}
+
+/// Parse each text with the first of `formats` that matches it and write it in the
+/// `output` format, by default ISO 8601: `2025-04-29`, `2025-04-29T12:00:00` or, with a
+/// UTC offset, `2025-04-29T12:00:00+02:00`. Texts no format matches, or lacking fields
+/// the output asks for, such as the time of a date, are left unchanged.
+#[cfg(feature = "chrono")]
+fn parse_date(texts: &mut [String], formats: &[&str], output: Option<&str>) {
+    use chrono::{DateTime, NaiveDate, NaiveDateTime};
+    use std::fmt::Write;
+
+    for text in texts.iter_mut() {
+        let input = text.trim();
+        let mut formatted = String::new();
+        for format in formats {
+            // Writing fails when the output asks for fields the input lacks, such as the
+            // time of a date; the text is then kept
+            let written = if let Ok(datetime) = DateTime::parse_from_str(input, format) {
+                write!(formatted, "{}", datetime.format(output.unwrap_or("%Y-%m-%dT%H:%M:%S%:z")))
+            } else if let Ok(datetime) = NaiveDateTime::parse_from_str(input, format) {
+                write!(formatted, "{}", datetime.format(output.unwrap_or("%Y-%m-%dT%H:%M:%S")))
+            } else if let Ok(date) = NaiveDate::parse_from_str(input, format) {
+                write!(formatted, "{}", date.format(output.unwrap_or("%Y-%m-%d")))
+            } else {
+                continue;
+            };
+            if written.is_ok() {
+                *text = formatted;
+            }
+            break;
+        }
+    }
+}
+
+/// Reject output formats chrono cannot write, which would otherwise panic when used
+#[cfg(feature = "chrono")]
+fn check_date_format(format: &str) -> InterpreterResult<()> {
+    use chrono::format::{Item, StrftimeItems};
+
+    if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+        return Err(InterpreterError::InvalidArgument(format!(
+            "parse_date cannot write the date format {}",
+            format
+        )));
+    }
+    Ok(())
+}
+
+/// Lowercase hexadecimal form of a digest
+#[cfg(feature = "hash")]
+fn hex(bytes: &[u8]) -> String {
+    use std::fmt::Write;
+
+    let mut out = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        let _ = write!(out, "{:02x}", byte);
+    }
+    out
+}
+
+/// Encode each text as standard base64 with padding
+#[cfg(feature = "base64")]
+fn base64_encode(texts: &mut [String]) {
+    use base64::Engine;
+
+    for text in texts.iter_mut() {
+        *text = base64::engine::general_purpose::STANDARD.encode(text.as_bytes());
+    }
+}
+
+/// Decode each text from base64, standard or URL-safe, with or without padding. The
+/// `data:...;base64,` prefix of data URIs and whitespace are skipped. Texts that are
+/// not valid base64 are dropped; decoded bytes that are not UTF-8 are replaced with
+/// U+FFFD.
+#[cfg(feature = "base64")]
+fn base64_decode(texts: &mut Vec<String>) {
+    use base64::Engine;
+    use base64::alphabet;
+    use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
+
+    const CONFIG: GeneralPurposeConfig =
+        GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent);
+    const STANDARD: GeneralPurpose = GeneralPurpose::new(&alphabet::STANDARD, CONFIG);
+    const URL_SAFE: GeneralPurpose = GeneralPurpose::new(&alphabet::URL_SAFE, CONFIG);
+
+    texts.retain_mut(|text| {
+        let data = match text.trim_start().strip_prefix("data:") {
+            Some(uri) => uri.split_once(";base64,").map_or("", |(_, data)| data),
+            None => text.as_str(),
+        };
+        let data: String = data.chars().filter(|c| !c.is_ascii_whitespace()).collect();
+
+        let engine = if data.contains(['-', '_']) { &URL_SAFE } else { &STANDARD };
+        match engine.decode(&data) {
+            Ok(bytes) => {
+                *text = String::from_utf8_lossy(&bytes).into_owned();
+                true
+            }
+            Err(_) => false,
+        }
+    });
+}
+
+/// Replace each run of whitespace, line breaks included, with a single space and
+/// remove whitespace at both ends
+fn collapse_ws(texts: &mut [String]) {
+    for text in texts.iter_mut() {
+        *text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    }
+}
+
+fn replace(texts: &mut Vec<String>, search: &str, replacement: &str) {
+    texts
+        .iter_mut()
+        .for_each(|text| *text = text.replace(search, replacement))
+}
+
+fn lowercase(texts: &mut Vec<String>) {
+    texts
+        .iter_mut()
+        .for_each(|text| *text = text.to_lowercase())
+}
+
+fn uppercase(texts: &mut Vec<String>) {
+    texts
+        .iter_mut()
+        .for_each(|text| *text = text.to_uppercase())
+}
+
+/// Uppercase the first letter of each text and lowercase the rest
+fn capitalize(texts: &mut [String]) {
+    for text in texts.iter_mut() {
+        let start = text.len() - text.trim_start().len();
+        *text = format!("{}{}", &text[..start], capitalize_word(&text[start..]));
+    }
+}
+
+/// Capitalize each word, words being separated by whitespace or hyphens
+fn title_case(texts: &mut [String]) {
+    for text in texts.iter_mut() {
+        let mut result = String::with_capacity(text.len());
+        let mut rest = text.as_str();
+        while let Some(start) = rest.find(|c: char| !c.is_whitespace() && c != '-') {
+            result.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let end = rest
+                .find(|c: char| c.is_whitespace() || c == '-')
+                .unwrap_or(rest.len());
+            result.push_str(&capitalize_word(&rest[..end]));
+            rest = &rest[end..];
+        }
+        result.push_str(rest);
+        *text = result;
+    }
+}
+
+fn capitalize_word(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars.as_str().to_lowercase().chars()).collect(),
+        None => String::new(),
+    }
+}
+
+fn join(texts: &mut Vec<String>, separator: &str) {
+    *texts = vec![texts.join(separator)]
+}
+
+/// Sort the texts by their first number, read like `to_float` reads them. Texts without
+/// a number come last, in their original order.
+fn sort_numeric(texts: &mut Vec<String>, descending: bool) {
+    let mut keyed: Vec<(Option<f64>, String)> = std::mem::take(texts)
+        .into_iter()
+        .map(|text| {
+            let key = extract_amount(&text).and_then(|(amount, _)| amount.parse().ok());
+            (key, text)
+        })
+        .collect();
+
+    keyed.sort_by(|(a, _), (b, _)| match (a, b) {
+        (Some(a), Some(b)) if descending => b.total_cmp(a),
+        (Some(a), Some(b)) => a.total_cmp(b),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => std::cmp::Ordering::Equal,
+    });
+
+    *texts = keyed.into_iter().map(|(_, text)| text).collect();
+}
+
+/// Reduce the texts to a single value: their number for `count`, or the sum, minimum,
+/// maximum or average of the first number in each text, read like `to_float` reads
+/// them. Texts without a number are ignored; `min`, `max` and `avg` of no numbers
+/// leave no text.
+fn aggregate(texts: &mut Vec<String>, name: &str) {
+    if name == "count" {
+        *texts = vec![texts.len().to_string()];
+        return;
+    }
+
+    let numbers: Vec<f64> = texts
+        .iter()
+        .filter_map(|text| extract_amount(text))
+        .filter_map(|(amount, _)| amount.parse().ok())
+        .collect();
+
+    let value = match name {
+        "sum" => Some(numbers.iter().sum()),
+        "min" => numbers.iter().copied().reduce(f64::min),
+        "max" => numbers.iter().copied().reduce(f64::max),
+        _ if numbers.is_empty() => None,
+        _ => Some(numbers.iter().sum::<f64>() / numbers.len() as f64),
+    };
+
+    *texts = value.map(|v: f64| v.to_string()).into_iter().collect();
+}
+
+/// Join every `size` consecutive texts into one; the last chunk may be shorter
+fn chunk(texts: &mut Vec<String>, size: usize, separator: &str) {
+    *texts = texts.chunks(size).map(|c| c.join(separator)).collect()
+}
+
+/// Replace each text with the value paired with it in `pairs` (`[key, value, ...]`).
+/// Texts without a key become `default`, or are kept when there is none.
+fn map_lookup(texts: &mut [String], pairs: &[&str], default: Option<&str>) {
+    for text in texts.iter_mut() {
+        let value = pairs
+            .chunks(2)
+            .find(|pair| pair[0] == text.as_str())
+            .map(|pair| pair[1])
+            .or(default);
+
+        if let Some(value) = value {
+            *text = value.to_string();
+        }
+    }
+}
+
+fn format(texts: &mut Vec<String>, format_str: &str) {
+    texts.iter_mut().for_each(|text| {
+        if format_str.contains("{}") {
+            *text = format!("{}", format_str.replacen("{}", &text, 1));
+        } else if format_str.starts_with("%") {
+            match format_str {
+                "%s" => { /* Keep as is, equivalent to {} */ }
+                "%d" | "%i" => {
+                    if let Ok(num) = text.trim().parse::<i64>() {
+                        *text = num.to_string();
+                    }
+                }
+                "%f" => {
+                    if let Ok(num) = text.trim().parse::<f64>() {
+                        *text = num.to_string();
+                    }
+                }
+                "%x" => {
+                    if let Ok(num) = text.trim().parse::<i64>() {
+                        *text = format!("{:x}", num);
+                    }
+                }
+                "%X" => {
+                    if let Ok(num) = text.trim().parse::<i64>() {
+                        *text = format!("{:X}", num);
+                    }
+                }
+                _ => {
+                    *text = format!("{}{}", format_str, text);
+                }
+            }
+        } else {
+            *text = format!("{}{}", format_str, text);
+        }
+    })
+}
+
+fn contains(texts: &mut Vec<String>, inner: &str) {
     let mut result = Vec::new();
 
     for text in texts.iter() {
-        if text.ends_with(ed) {
+        if text.contains(inner) {
             result.push(text.clone());
         }
     }
@@ -301,10 +1634,23 @@ fn ends_with(texts: &mut Vec<String>, ed: &str) {
     *texts = result;
 }
 
-fn in_(texts: &mut Vec<String>, list: Vec<String>) {
+fn starts_with(texts: &mut Vec<String>, st: &str) {
     let mut result = Vec::new();
+
     for text in texts.iter() {
-        if list.contains(text) {
+        if text.starts_with(st) {
+            result.push(text.clone());
+        }
+    }
+
+    *texts = result;
+}
+
+fn ends_with(texts: &mut Vec<String>, ed: &str) {
+    let mut result = Vec::new();
+
+    for text in texts.iter() {
+        if text.ends_with(ed) {
             result.push(text.clone());
         }
     }
@@ -312,7 +1658,25 @@ fn in_(texts: &mut Vec<String>, list: Vec<String>) {
     *texts = result;
 }
 
-fn slice(tests: &mut Vec<String>, st: Option<usize>, ed: Option<usize>) {
+fn in_(texts: &mut Vec<String>, list: &HashSet<String>) {
+    texts.retain(|text| list.contains(text));
+}
+
+fn slice(
+    tests: &mut Vec<String>,
+    st: Option<usize>,
+    ed: Option<usize>,
+    strict: bool,
+) -> InterpreterResult<()> {
+    if strict {
+        for text in tests.iter_mut() {
+            let start = st.unwrap_or(0);
+            let end = ed.unwrap_or(text.len());
+            *text = checked_slice("slice", text, start, end)?.to_string();
+        }
+        return Ok(());
+    }
+
     tests.iter_mut().for_each(|text| {
         let mut st = st.unwrap_or(0);
         let mut ed = ed.unwrap_or(text.len());
@@ -333,4 +1697,308 @@ fn slice(tests: &mut Vec<String>, st: Option<usize>, ed: Option<usize>) {
             *text = t.to_string();
         }
     });
+
+    Ok(())
+}
+
+/// Take bytes `start..end` of a text, or describe why that is not possible
+fn checked_slice<'a>(
+    function: &str,
+    text: &'a str,
+    start: usize,
+    end: usize,
+) -> InterpreterResult<&'a str> {
+    let reason = if start > end {
+        "start is after end".to_string()
+    } else if end > text.len() {
+        "end is past the end of the text".to_string()
+    } else if !text.is_char_boundary(start) || !text.is_char_boundary(end) {
+        let index = if text.is_char_boundary(start) { end } else { start };
+        format!("byte {} is inside a multi-byte character", index)
+    } else {
+        return Ok(&text[start..end]);
+    };
+
+    Err(InterpreterError::InvalidStringIndex(
+        function.to_string(),
+        text.to_string(),
+        start,
+        end,
+        reason,
+    ))
+}
+
+/// Currency symbols and the ISO 4217 codes they are normalized to.
+/// Multi-character symbols come first so that `R$` is not read as `$`.
+const CURRENCY_SYMBOLS: &[(&str, &str)] = &[
+    ("R$", "BRL"),
+    ("C$", "CAD"),
+    ("A$", "AUD"),
+    ("HK$", "HKD"),
+    ("zł", "PLN"),
+    ("Kč", "CZK"),
+    ("£", "GBP"),
+    ("€", "EUR"),
+    ("$", "USD"),
+    ("¥", "JPY"),
+    ("₹", "INR"),
+    ("₽", "RUB"),
+    ("₩", "KRW"),
+    ("₺", "TRY"),
+    ("₴", "UAH"),
+    ("₪", "ILS"),
+    ("฿", "THB"),
+    ("₫", "VND"),
+];
+
+/// ISO 4217 codes of circulating currencies, sorted for binary search
+const CURRENCY_CODES: &[&str] = &[
+    "AED", "AFN", "ALL", "AMD", "ANG", "AOA", "ARS", "AUD", "AWG", "AZN", "BAM", "BBD",
+    "BDT", "BGN", "BHD", "BIF", "BMD", "BND", "BOB", "BRL", "BSD", "BTN", "BWP", "BYN",
+    "BZD", "CAD", "CDF", "CHF", "CLP", "CNY", "COP", "CRC", "CUP", "CVE", "CZK", "DJF",
+    "DKK", "DOP", "DZD", "EGP", "ERN", "ETB", "EUR", "FJD", "FKP", "GBP", "GEL", "GHS",
+    "GIP", "GMD", "GNF", "GTQ", "GYD", "HKD", "HNL", "HTG", "HUF", "IDR", "ILS", "INR",
+    "IQD", "IRR", "ISK", "JMD", "JOD", "JPY", "KES", "KGS", "KHR", "KMF", "KPW", "KRW",
+    "KWD", "KYD", "KZT", "LAK", "LBP", "LKR", "LRD", "LSL", "LYD", "MAD", "MDL", "MGA",
+    "MKD", "MMK", "MNT", "MOP", "MRU", "MUR", "MVR", "MWK", "MXN", "MYR", "MZN", "NAD",
+    "NGN", "NIO", "NOK", "NPR", "NZD", "OMR", "PAB", "PEN", "PGK", "PHP", "PKR", "PLN",
+    "PYG", "QAR", "RON", "RSD", "RUB", "RWF", "SAR", "SBD", "SCR", "SDG", "SEK", "SGD",
+    "SHP", "SLE", "SOS", "SRD", "SSP", "STN", "SVC", "SYP", "SZL", "THB", "TJS", "TMT",
+    "TND", "TOP", "TRY", "TTD", "TWD", "TZS", "UAH", "UGX", "USD", "UYU", "UZS", "VES",
+    "VND", "VUV", "WST", "XAF", "XCD", "XOF", "XPF", "YER", "ZAR", "ZMW", "ZWL",
+];
+
+fn parse_price(texts: &mut Vec<String>, default_currency: Option<&str>) {
+    let mut result = Vec::new();
+
+    for text in texts.iter() {
+        if let Some((amount, rest)) = extract_amount(text) {
+            match detect_currency(&rest).or(default_currency.map(|c| c.to_string())) {
+                Some(currency) => result.push(format!("{} {}", amount, currency)),
+                None => result.push(amount),
+            }
+        }
+    }
+
+    *texts = result;
+}
+
+/// Convert each text to the integer part of its first number, dropping texts without
+/// a number or whose number does not fit in 64 bits.
+fn to_int(texts: &mut Vec<String>) {
+    texts.retain_mut(|text| {
+        let value = extract_amount(text).and_then(|(amount, _)| {
+            let integer = amount.split('.').next().unwrap_or_default();
+            integer.parse::<i64>().ok()
+        });
+        if let Some(value) = value {
+            *text = value.to_string();
+        }
+        value.is_some()
+    });
+}
+
+/// Convert each text to its first number, read like `parse_price` reads amounts,
+/// dropping texts without a number.
+fn to_float(texts: &mut Vec<String>) {
+    texts.retain_mut(|text| {
+        let value = extract_amount(text).and_then(|(amount, _)| amount.parse::<f64>().ok());
+        if let Some(value) = value {
+            *text = value.to_string();
+        }
+        value.is_some()
+    });
+}
+
+/// Check whether a text is a single number, allowing a sign, currency symbols or codes,
+/// a percent sign and whitespace around it
+fn is_number(text: &str) -> bool {
+    let Some((_, mut rest)) = extract_amount(text) else {
+        return false;
+    };
+    for (symbol, _) in CURRENCY_SYMBOLS {
+        rest = rest.replace(symbol, "");
+    }
+
+    rest.split(|c: char| c.is_whitespace() || c == '+' || c == '%')
+        .all(|word| word.is_empty() || CURRENCY_CODES.binary_search(&word).is_ok())
+}
+
+/// Replace each text with the numbers in it, normalized like `@to_float` does
+fn extract_numbers(texts: &mut Vec<String>) {
+    *texts = texts
+        .iter()
+        .flat_map(|text| {
+            let mut numbers = Vec::new();
+            let mut rest = text.clone();
+            while let Some((amount, remaining)) = extract_amount(&rest) {
+                // Continue after the number, where a hyphen joins it to the next one, as
+                // in a range, rather than making that one negative
+                let mut prefix = rest.find(|c: char| c.is_ascii_digit()).unwrap_or(0);
+                if amount.starts_with('-') {
+                    prefix -= 1;
+                }
+                let after = &remaining[prefix..];
+                rest = after.strip_prefix('-').unwrap_or(after).to_string();
+                numbers.push(amount);
+            }
+            numbers
+        })
+        .collect();
+}
+
+const EMAIL_PATTERN: &str = r"[A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,}";
+
+/// Absolute URLs and `www.` addresses, up to whitespace or a quote
+const URL_PATTERN: &str = r#"(?:https?|ftp)://[^\s<>"']+|www\.[^\s<>"']+"#;
+
+/// Remove punctuation that ends the sentence around a URL rather than the URL itself,
+/// including a closing parenthesis that was not opened in the URL
+fn trim_url(mut url: &str) -> &str {
+    loop {
+        let trimmed = url.trim_end_matches(['.', ',', ';', ':', '!', '?', ']', '}']);
+        let trimmed = match trimmed.strip_suffix(')') {
+            Some(inner) if inner.matches('(').count() < trimmed.matches(')').count() => inner,
+            _ => trimmed,
+        };
+        if trimmed.len() == url.len() {
+            return url;
+        }
+        url = trimmed;
+    }
+}
+
+/// Find the first number in the text and normalize it to `1234.56` form.
+/// Returns the normalized amount and the text with the number removed.
+fn extract_amount(text: &str) -> Option<(String, String)> {
+    let chars: Vec<char> = text.chars().collect();
+    let is_group_space = |c: char| c == ' ' || c == '\u{a0}' || c == '\u{202f}' || c == '\u{2009}';
+
+    let start = chars.iter().position(|c| c.is_ascii_digit())?;
+    let mut end = start;
+    while end < chars.len() {
+        let c = chars[end];
+        let next_is_digit = chars.get(end + 1).is_some_and(|n| n.is_ascii_digit());
+        if c.is_ascii_digit() || ((c == '.' || c == ',' || c == '\'' || is_group_space(c)) && next_is_digit) {
+            end += 1;
+        } else {
+            break;
+        }
+    }
+
+    let negative = start > 0 && chars[start - 1] == '-';
+    let raw: String = chars[start..end]
+        .iter()
+        .filter(|c| !is_group_space(**c) && **c != '\'')
+        .collect();
+
+    let dots = raw.matches('.').count();
+    let commas = raw.matches(',').count();
+    let decimal_sep = if dots > 0 && commas > 0 {
+        // Both present: whichever comes last is the decimal separator
+        if raw.rfind('.') > raw.rfind(',') { Some('.') } else { Some(',') }
+    } else if dots + commas == 1 {
+        // A single separator followed by exactly three digits is a thousands separator
+        let sep = if dots == 1 { '.' } else { ',' };
+        let frac_len = raw.len() - raw.find(sep).unwrap() - 1;
+        if frac_len == 3 { None } else { Some(sep) }
+    } else {
+        None
+    };
+
+    let mut amount = String::with_capacity(raw.len() + 1);
+    if negative {
+        amount.push('-');
+    }
+    for c in raw.chars() {
+        if c.is_ascii_digit() {
+            amount.push(c);
+        } else if Some(c) == decimal_sep {
+            amount.push('.');
+        }
+    }
+
+    let prefix_end = if negative { start - 1 } else { start };
+    let rest: String = chars[..prefix_end].iter().chain(chars[end..].iter()).collect();
+
+    Some((amount, rest))
+}
+
+/// Detect a currency symbol or, failing that, an ISO 4217 currency code.
+fn detect_currency(text: &str) -> Option<String> {
+    if let Some((_, code)) = CURRENCY_SYMBOLS.iter().find(|(symbol, _)| text.contains(symbol)) {
+        return Some(code.to_string());
+    }
+
+    text.split(|c: char| !c.is_ascii_alphabetic())
+        .find(|word| CURRENCY_CODES.binary_search(word).is_ok())
+        .map(|code| code.to_string())
+}
+
+/// Convert relative dates ("3 hours ago", "yesterday", "in 2 weeks") to absolute
+/// timestamps relative to `now`. Texts that are not relative dates are left unchanged.
+fn parse_relative_date(texts: &mut [String], now: &DateTime) {
+    texts.iter_mut().for_each(|text| {
+        if let Some(datetime) = resolve_relative_date(text, now) {
+            *text = datetime.to_iso();
+        }
+    });
+}
+
+fn resolve_relative_date(text: &str, now: &DateTime) -> Option<DateTime> {
+    let text = text.trim().to_lowercase();
+
+    match text.as_str() {
+        "now" | "just now" | "today" => return Some(now.clone()),
+        "yesterday" => return now.add_seconds(-86_400),
+        "tomorrow" => return now.add_seconds(86_400),
+        _ => {}
+    }
+
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let (sign, words) = match words.as_slice() {
+        ["in", rest @ ..] => (1, rest),
+        [rest @ .., "ago"] => (-1, rest),
+        ["last", unit] => (-1, &["1", *unit][..]),
+        ["next", unit] => (1, &["1", *unit][..]),
+        _ => return None,
+    };
+
+    let (amount, unit) = match words {
+        [amount, unit] => {
+            let amount = match *amount {
+                "a" | "an" | "one" => 1,
+                n => n.parse::<i64>().ok()?,
+            };
+            (amount, *unit)
+        }
+        // "5min ago", "2h ago"
+        [compact] => {
+            let split = compact.find(|c: char| !c.is_ascii_digit())?;
+            (compact[..split].parse::<i64>().ok()?, &compact[split..])
+        }
+        _ => return None,
+    };
+
+    // Amounts too large to represent leave the text unchanged
+    let amount = amount.checked_mul(sign)?;
+    match unit.trim_end_matches('s') {
+        "sec" | "second" => now.add_seconds(amount),
+        "m" | "min" | "minute" => now.add_seconds(amount.checked_mul(60)?),
+        "h" | "hr" | "hour" => now.add_seconds(amount.checked_mul(3600)?),
+        "d" | "day" => now.add_seconds(amount.checked_mul(86_400)?),
+        "w" | "wk" | "week" => now.add_seconds(amount.checked_mul(7 * 86_400)?),
+        "mo" | "month" => now.add_months(amount),
+        "y" | "yr" | "year" => now.add_months(amount.checked_mul(12)?),
+        _ => None,
+    }
+}
+
+/// Strip tags and decode entities in texts that contain HTML markup
+fn html_to_text(texts: &mut [String]) -> InterpreterResult<()> {
+    for text in texts.iter_mut() {
+        *text = html::html_to_text(text)?;
+    }
+
+    Ok(())
 }
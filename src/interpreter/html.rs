@@ -3,73 +3,158 @@
 // This module is responsible for HTML document parsing and DOM tree manipulation,
 // providing a series of helper functions to simplify the use of html5ever.
 
-use super::error::{InterpreterError, InterpreterResult};
+use super::attrs;
+use super::dom::{Dom, NodeData};
+use super::error::InterpreterResult;
+use super::options::InterpreterOptions;
 use super::result::NodeHandle;
+use crate::pattern::Pattern;
 use html5ever::driver::ParseOpts;
 use html5ever::parse_document;
 use html5ever::tendril::TendrilSink;
 use html5ever::tree_builder::TreeBuilderOpts;
-use markup5ever_rcdom::{NodeData, RcDom};
+use markup5ever_rcdom::RcDom;
 use std::collections::HashMap;
 use std::default::Default;
+use std::io::Read;
+use std::sync::Arc;
 
 /// Parse HTML document and return document root node
+///
+/// With the `tl` feature, the document is parsed by `tl` instead of html5ever.
 pub fn parse_html(html: &str) -> InterpreterResult<NodeHandle> {
-    let opts = ParseOpts {
+    #[cfg(feature = "tl")]
+    return parse_html_tl(html);
+
+    #[cfg(not(feature = "tl"))]
+    parse_html_from_reader(&mut html.as_bytes())
+}
+
+/// Parse an HTML document with `tl`. Elements are nested as written: missing `html`,
+/// `head` and `body` elements are not added, and misnested tags are not repaired.
+#[cfg(feature = "tl")]
+pub fn parse_html_tl(html: &str) -> InterpreterResult<NodeHandle> {
+    let vdom = tl::parse(html, tl::ParserOptions::default())
+        .map_err(|e| super::error::InterpreterError::html_parse_error(e.to_string()))?;
+    let dom = Arc::new(Dom::from_tl(&vdom));
+    let root = dom.root();
+
+    Ok(NodeHandle::new(dom, root))
+}
+
+/// Parse an HTML document read incrementally from a reader. The input is decoded as
+/// UTF-8, with malformed sequences replaced.
+pub fn parse_html_from_reader<R: Read>(reader: &mut R) -> InterpreterResult<NodeHandle> {
+    parse_html_with(reader, default_parse_opts())
+}
+
+/// Parser options used unless configured otherwise: the doctype is dropped
+pub fn default_parse_opts() -> ParseOpts {
+    ParseOpts {
         tree_builder: TreeBuilderOpts {
             drop_doctype: true,
             ..Default::default()
         },
         ..Default::default()
-    };
+    }
+}
+
+/// Parse an HTML document read from a reader with the given parser options
+pub fn parse_html_with<R: Read>(reader: &mut R, opts: ParseOpts) -> InterpreterResult<NodeHandle> {
+    let dom = Arc::new(parse_dom(reader, opts)?);
+    let root = dom.root();
+
+    Ok(NodeHandle::new(dom, root))
+}
+
+/// Parse an HTML document with the given parser options, also recording the attributes
+/// the tokenizer drops when an element repeats an attribute name
+pub fn parse_html_keeping_duplicates(html: &str, opts: ParseOpts) -> InterpreterResult<NodeHandle> {
+    let dom = parse_dom(&mut html.as_bytes(), opts)?;
+    let dom = Arc::new(dom.with_duplicate_attrs(attrs::scan_duplicates(html)));
+    let root = dom.root();
+
+    Ok(NodeHandle::new(dom, root))
+}
 
+fn parse_dom<R: Read>(reader: &mut R, opts: ParseOpts) -> InterpreterResult<Dom> {
     let dom = parse_document(RcDom::default(), opts)
         .from_utf8()
-        .read_from(&mut html.as_bytes())
-        .map_err(|e| InterpreterError::html_parse_error(&format!("HTML parsing error: {:?}", e)))?;
+        .read_from(reader)?;
 
-    let document = dom.document;
-
-    Ok(NodeHandle::from_html5(document))
+    // Copy the single-threaded RcDom into a shareable arena
+    Ok(Dom::from_html5(&dom.document).with_warnings(dom.errors))
 }
 
-/// Get child nodes from node handle
-pub fn get_children(node: &NodeHandle) -> InterpreterResult<Vec<NodeHandle>> {
-    let handle = node.handle().ok_or_else(|| {
-        InterpreterError::execution_error("Node does not have a valid HTML reference")
-    })?;
+/// Parse an XML document and return document root node. Elements are matched by
+/// their local name, without namespace prefix.
+#[cfg(feature = "xml")]
+pub fn parse_xml(xml: &str) -> InterpreterResult<NodeHandle> {
+    let dom = xml5ever::driver::parse_document(RcDom::default(), Default::default())
+        .from_utf8()
+        .read_from(&mut xml.as_bytes())?;
+
+    let dom = Arc::new(Dom::from_html5(&dom.document).with_warnings(dom.errors));
+    let root = dom.root();
+
+    Ok(NodeHandle::new(dom, root))
+}
 
-    // Get child nodes
-    let children = handle.children.borrow();
-    let mut result = Vec::with_capacity(children.len());
+/// Parse a fragment of HTML markup and return its text content with tags stripped
+/// and entities decoded
+pub fn html_to_text(markup: &str) -> InterpreterResult<String> {
+    let document = parse_html(markup)?;
+    let mut result = String::new();
 
-    // Wrap each child node as NodeHandle
-    for child in children.iter() {
-        result.push(NodeHandle::from_html5(child.clone()));
+    for child in get_children(&document)? {
+        result.push_str(&extract_text(&child)?);
     }
 
     Ok(result)
 }
 
+/// Get child nodes from node handle
+pub fn get_children(node: &NodeHandle) -> InterpreterResult<Vec<NodeHandle>> {
+    Ok(node.children())
+}
+
 /// Extract text content from node
 pub fn extract_text(node: &NodeHandle) -> InterpreterResult<String> {
-    let handle = node.handle().ok_or_else(|| {
-        InterpreterError::execution_error("Node does not have a valid HTML reference")
-    })?;
+    extract_text_with(node, &InterpreterOptions::default())
+}
 
+/// Extract text content from node using the given interpreter options
+pub fn extract_text_with(
+    node: &NodeHandle,
+    options: &InterpreterOptions,
+) -> InterpreterResult<String> {
     // If it's a text node, return text content directly
-    if let NodeData::Text { contents } = &handle.data {
-        let text = contents.borrow();
-        return Ok(text.to_string());
+    if let NodeData::Text { contents } = node.data() {
+        #[cfg(feature = "entities")]
+        if !options.entities.is_empty() {
+            return Ok(decode_entities(contents, &options.entities));
+        }
+
+        return Ok(contents.clone());
+    }
+
+    if options.block_text && !node.is_text() {
+        return render_block_text(node, options);
     }
 
     // If it's an element node, recursively extract text content from all child nodes
     if node.is_element() {
+        if options.void_element_text
+            && let Some(text) = void_element_text(node)
+        {
+            return Ok(text);
+        }
+
         let mut result = String::new();
         let children = get_children(node)?;
 
         for child in children {
-            result.push_str(&extract_text(&child)?);
+            result.push_str(&extract_text_with(&child, options)?);
         }
 
         return Ok(result);
@@ -79,96 +164,264 @@ pub fn extract_text(node: &NodeHandle) -> InterpreterResult<String> {
     Ok(String::new())
 }
 
-/// Find elements by tag name
-pub fn find_by_tag(
+/// Extract the text of the node's own text nodes, leaving out the text of child elements
+pub fn extract_own_text_with(
     node: &NodeHandle,
-    tag_name: &str,
-    is_regex: bool,
-) -> InterpreterResult<Vec<NodeHandle>> {
-    let handle = node.handle().ok_or_else(|| {
-        InterpreterError::execution_error("Node does not have a valid HTML reference")
-    })?;
+    options: &InterpreterOptions,
+) -> InterpreterResult<String> {
+    if node.is_text() {
+        return extract_text_with(node, options);
+    }
 
-    let mut result = Vec::new();
+    let mut result = String::new();
+    for child in get_children(node)?.iter().filter(|child| child.is_text()) {
+        result.push_str(&extract_text_with(child, options)?);
+    }
 
-    // If current node is an element node, check its tag name
-    if let NodeData::Element { name, .. } = &handle.data {
-        let current_tag = name.local.to_string();
+    Ok(result)
+}
 
-        let is_match = if is_regex {
-            // Use regex for matching
-            let re = regex::Regex::new(tag_name).map_err(|e| {
-                InterpreterError::execution_error(&format!("Invalid regex pattern: {}", e))
-            })?;
-            re.is_match(&current_tag)
-        } else {
-            current_tag == tag_name
+/// Elements laid out on lines of their own when rendering block text
+const BLOCK_ELEMENTS: &[&str] = &[
+    "address", "article", "aside", "blockquote", "caption", "dd", "details", "dialog", "div",
+    "dl", "dt", "fieldset", "figcaption", "figure", "footer", "form", "h1", "h2", "h3", "h4",
+    "h5", "h6", "header", "hgroup", "hr", "li", "main", "nav", "ol", "p", "pre", "section",
+    "summary", "table", "tr", "ul",
+];
+
+/// Render the text of a node following its layout: block elements start new lines,
+/// `<br>` breaks the line, list items are prefixed with `- ` (or their number inside
+/// `<ol>`), and whitespace is collapsed outside `<pre>`. Scripts and styles are skipped.
+pub fn render_block_text(
+    node: &NodeHandle,
+    options: &InterpreterOptions,
+) -> InterpreterResult<String> {
+    let mut text = BlockText::default();
+    text.node(node, options, false)?;
+
+    Ok(text.out.trim_end().to_string())
+}
+
+/// Output of `render_block_text`. Line breaks and spaces are deferred until the next
+/// visible character, so that consecutive block boundaries produce a single line break
+/// and no line ends with a space.
+#[derive(Default)]
+struct BlockText {
+    out: String,
+    space: bool,
+    line_break: bool,
+}
+
+impl BlockText {
+    fn node(
+        &mut self,
+        node: &NodeHandle,
+        options: &InterpreterOptions,
+        pre: bool,
+    ) -> InterpreterResult<()> {
+        let name = match node.data() {
+            NodeData::Text { .. } => {
+                self.text(&extract_text_with(node, options)?, pre);
+                return Ok(());
+            }
+            NodeData::Element { name, .. } => name.as_str(),
+            NodeData::Document => "",
+            _ => return Ok(()),
         };
 
-        if is_match {
-            result.push(node.clone());
+        if options.void_element_text
+            && let Some(text) = void_element_text(node)
+        {
+            self.text(&text, pre);
+            return Ok(());
+        }
+
+        match name {
+            "script" | "style" | "template" | "head" => return Ok(()),
+            "br" => {
+                self.out.truncate(self.out.trim_end_matches(' ').len());
+                self.out.push('\n');
+                self.space = false;
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        let is_block = BLOCK_ELEMENTS.contains(&name);
+        if is_block {
+            self.line_break = true;
+        }
+        if name == "li" {
+            let bullet = list_item_marker(node);
+            self.flush();
+            self.out.push_str(&bullet);
+        }
+
+        let pre = pre || name == "pre";
+        for child in node.children() {
+            self.node(&child, options, pre)?;
+        }
+
+        if is_block {
+            self.line_break = true;
         }
+        Ok(())
     }
 
-    // Recursively search child nodes
-    let children = get_children(node)?;
-    for child in children {
-        let mut child_results = find_by_tag(&child, tag_name, is_regex)?;
-        result.append(&mut child_results);
+    fn text(&mut self, text: &str, pre: bool) {
+        if pre {
+            if !text.is_empty() {
+                self.flush();
+                self.out.push_str(text);
+            }
+            return;
+        }
+
+        for c in text.chars() {
+            if c.is_whitespace() {
+                self.space = true;
+            } else {
+                self.flush();
+                self.out.push(c);
+            }
+        }
     }
 
-    Ok(result)
+    /// Write the pending line break or space before the next visible character
+    fn flush(&mut self) {
+        if self.line_break && !self.out.is_empty() {
+            self.out.truncate(self.out.trim_end_matches(' ').len());
+            if !self.out.ends_with('\n') {
+                self.out.push('\n');
+            }
+        } else if self.space && !self.out.is_empty() && !self.out.ends_with([' ', '\n']) {
+            self.out.push(' ');
+        }
+        self.line_break = false;
+        self.space = false;
+    }
 }
 
-/// Find elements by class name
-pub fn find_by_class(
-    node: &NodeHandle,
-    class_name: &str,
-    is_regex: bool,
-) -> InterpreterResult<Vec<NodeHandle>> {
-    let handle = node.handle().ok_or_else(|| {
-        InterpreterError::execution_error("Node does not have a valid HTML reference")
-    })?;
+/// `- ` for items of unordered lists, `N. ` for items of ordered lists
+fn list_item_marker(item: &NodeHandle) -> String {
+    let Some(list) = item.parent().filter(|parent| parent.tag_name() == Some("ol")) else {
+        return "- ".to_string();
+    };
 
-    let mut result = Vec::new();
+    let start: usize = get_attribute(&list, "start")
+        .and_then(|start| start.trim().parse().ok())
+        .unwrap_or(1);
+    let position = list
+        .children()
+        .iter()
+        .filter(|child| child.tag_name() == Some("li"))
+        .take_while(|child| *child != item)
+        .count();
+
+    format!("{}. ", start + position)
+}
 
-    // If current node is an element node, check its class attribute
-    if let NodeData::Element { attrs, .. } = &handle.data {
-        let attributes = attrs.borrow();
-        for attr in attributes.iter() {
-            if attr.name.local.to_string() == "class" {
-                let class_value = attr.value.to_string();
-
-                // Split class attribute value
-                let classes: Vec<&str> = class_value.split_whitespace().collect();
-
-                let is_match = if is_regex {
-                    // Use regex to match any class name
-                    let re = regex::Regex::new(class_name).map_err(|e| {
-                        InterpreterError::execution_error(&format!("Invalid regex pattern: {}", e))
-                    })?;
-                    classes.iter().any(|c| re.is_match(c))
-                } else {
-                    // Check if it contains specified class name
-                    classes.contains(&class_name)
-                };
+/// Replace `&name;` references found in `entities`, leaving anything else untouched
+#[cfg(feature = "entities")]
+fn decode_entities(text: &str, entities: &HashMap<String, String>) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let name_len = rest[1..]
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or(rest.len() - 1);
+        let replacement = rest[1 + name_len..]
+            .starts_with(';')
+            .then(|| entities.get(&rest[1..1 + name_len]))
+            .flatten();
+
+        match replacement {
+            Some(value) => {
+                result.push_str(value);
+                rest = &rest[name_len + 2..];
+            }
+            None => {
+                result.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
 
-                if is_match {
-                    result.push(node.clone());
-                    break;
-                }
+    result.push_str(rest);
+    result
+}
+
+/// Get the visible text of a void element: `alt` for images, `value` for inputs
+fn void_element_text(node: &NodeHandle) -> Option<String> {
+    let NodeData::Element { name, attrs } = node.data() else {
+        return None;
+    };
+
+    let attr = |attr_name: &str| {
+        attrs
+            .iter()
+            .find(|attr| attr.name == attr_name)
+            .map(|attr| attr.value.clone())
+    };
+
+    match name.as_str() {
+        "img" | "area" => Some(attr("alt").unwrap_or_default()),
+        "input" => {
+            let input_type = attr("type").unwrap_or_default().to_ascii_lowercase();
+            match input_type.as_str() {
+                "image" => Some(attr("alt").unwrap_or_default()),
+                // Controls whose value is not shown as text
+                "hidden" | "password" | "checkbox" | "radio" | "file" => Some(String::new()),
+                _ => Some(attr("value").unwrap_or_default()),
             }
         }
+        _ => None,
+    }
+}
+
+/// Wrap document tree indices as node handles
+fn handles(node: &NodeHandle, indices: &[usize]) -> Vec<NodeHandle> {
+    indices
+        .iter()
+        .map(|index| NodeHandle::new(node.dom().clone(), *index))
+        .collect()
+}
+
+/// Find elements by tag name
+pub fn find_by_tag(
+    node: &NodeHandle,
+    tag_name: &str,
+    is_regex: bool,
+) -> InterpreterResult<Vec<NodeHandle>> {
+    // Plain names are answered from the document's tag index
+    if !is_regex {
+        return Ok(handles(node, node.dom().elements_by_tag(tag_name, node.index())));
     }
 
-    // Recursively search child nodes
-    let children = get_children(node)?;
-    for child in children {
-        let mut child_results = find_by_class(&child, class_name, is_regex)?;
-        result.append(&mut child_results);
+    let re = Pattern::new(tag_name)?;
+    Ok(find_matching(node, |current| current.tag_name().is_some_and(|name| re.is_match(name))))
+}
+
+/// Find elements by class name
+pub fn find_by_class(
+    node: &NodeHandle,
+    class_name: &str,
+    is_regex: bool,
+) -> InterpreterResult<Vec<NodeHandle>> {
+    // Plain names are answered from the document's class index
+    if !is_regex {
+        return Ok(handles(node, node.dom().elements_by_class(class_name, node.index())));
     }
 
-    Ok(result)
+    let re = Pattern::new(class_name)?;
+    Ok(find_matching(node, |current| {
+        get_attribute(current, "class")
+            .is_some_and(|classes| classes.split_whitespace().any(|c| re.is_match(c)))
+    }))
 }
 
 /// Find elements by ID
@@ -177,190 +430,341 @@ pub fn find_by_id(
     id_value: &str,
     is_regex: bool,
 ) -> InterpreterResult<Vec<NodeHandle>> {
-    let handle = node.handle().ok_or_else(|| {
-        InterpreterError::execution_error("Node does not have a valid HTML reference")
-    })?;
+    // Plain values are answered from the document's id index
+    if !is_regex {
+        return Ok(handles(node, node.dom().elements_by_id(id_value, node.index())));
+    }
+
+    let re = Pattern::new(id_value)?;
+    Ok(find_matching(node, |current| {
+        get_attribute(current, "id").is_some_and(|id| re.is_match(&id))
+    }))
+}
+
+/// Nodes of the subtree of `node`, itself included, that pass the check, in document order
+fn find_matching(node: &NodeHandle, check: impl Fn(&NodeHandle) -> bool) -> Vec<NodeHandle> {
+    let dom = node.dom();
+
+    (node.index()..node.node().subtree_end)
+        .map(|index| NodeHandle::new(dom.clone(), index))
+        .filter(|current| check(current))
+        .collect()
+}
+
+/// Find elements by ARIA role. An explicit `role` attribute takes precedence over
+/// the implicit role of the element's tag.
+pub fn find_by_role(
+    node: &NodeHandle,
+    role: &str,
+    is_regex: bool,
+) -> InterpreterResult<Vec<NodeHandle>> {
+    let re = if is_regex {
+        Some(Pattern::new(role)?)
+    } else {
+        None
+    };
 
     let mut result = Vec::new();
+    let dom = node.dom();
 
-    // If current node is an element node, check its id attribute
-    if let NodeData::Element { attrs, .. } = &handle.data {
-        let attributes = attrs.borrow();
-        for attr in attributes.iter() {
-            if attr.name.local.to_string() == "id" {
-                let current_id = attr.value.to_string();
-
-                let is_match = if is_regex {
-                    // Use regex for matching
-                    let re = regex::Regex::new(id_value).map_err(|e| {
-                        InterpreterError::execution_error(&format!("Invalid regex pattern: {}", e))
-                    })?;
-                    re.is_match(&current_id)
-                } else {
-                    current_id == id_value
-                };
+    for index in node.index()..node.node().subtree_end {
+        let current = NodeHandle::new(dom.clone(), index);
+        let roles = get_roles(&current);
 
-                if is_match {
-                    result.push(node.clone());
-                    break;
-                }
-            }
-        }
-    }
+        let is_match = match &re {
+            Some(re) => roles.iter().any(|r| re.is_match(r)),
+            None => roles.iter().any(|r| r == role),
+        };
 
-    // Recursively search child nodes
-    let children = get_children(node)?;
-    for child in children {
-        let mut child_results = find_by_id(&child, id_value, is_regex)?;
-        result.append(&mut child_results);
+        if is_match {
+            result.push(current);
+        }
     }
 
     Ok(result)
 }
 
-/// Find elements by attribute
-pub fn find_by_attr(
-    node: &NodeHandle,
-    attr_name: &str,
-    attr_value: Option<&str>,
-    is_regex: bool,
-) -> InterpreterResult<Vec<NodeHandle>> {
-    let handle = node.handle().ok_or_else(|| {
-        InterpreterError::execution_error("Node does not have a valid HTML reference")
-    })?;
+/// Get the ARIA roles of an element: the tokens of its `role` attribute, or its implicit role
+pub fn get_roles(node: &NodeHandle) -> Vec<String> {
+    let NodeData::Element { name, attrs } = node.data() else {
+        return Vec::new();
+    };
 
-    let mut result = Vec::new();
+    let attr = |attr_name: &str| attrs.iter().find(|attr| attr.name == attr_name);
 
-    // If current node is an element node, check its attributes
-    if let NodeData::Element { attrs, .. } = &handle.data {
-        let attributes = attrs.borrow();
-
-        for attr in attributes.iter() {
-            let current_name = attr.name.local.to_string();
-
-            let name_match = if is_regex {
-                // Use regex to match attribute name
-                match regex::Regex::new(attr_name) {
-                    Ok(re) => re.is_match(&current_name),
-                    Err(e) => {
-                        return Err(InterpreterError::execution_error(&format!(
-                            "Invalid regex pattern: {}",
-                            e
-                        )));
-                    }
-                }
-            } else {
-                current_name == attr_name
-            };
-
-            // If attribute value is provided, also need to match
-            let value_match = if let Some(value) = attr_value {
-                let current_value = attr.value.to_string();
-
-                if is_regex {
-                    // Use regex to match attribute value
-                    match regex::Regex::new(value) {
-                        Ok(re) => re.is_match(&current_value),
-                        Err(e) => {
-                            return Err(InterpreterError::execution_error(&format!(
-                                "Invalid regex pattern: {}",
-                                e
-                            )));
-                        }
-                    }
-                } else {
-                    // Directly compare attribute values
-                    current_value == value
-                }
-            } else {
-                // If no attribute value provided, just match attribute name
-                true
-            };
+    if let Some(role) = attr("role") {
+        let roles: Vec<String> = role.value.split_whitespace().map(|r| r.to_string()).collect();
+        if !roles.is_empty() {
+            return roles;
+        }
+    }
 
-            if name_match && value_match {
-                result.push(node.clone());
-                break;
+    let implicit = match name.as_str() {
+        "a" | "area" if attr("href").is_some() => "link",
+        "article" => "article",
+        "aside" => "complementary",
+        "button" => "button",
+        "dialog" => "dialog",
+        "footer" => "contentinfo",
+        "form" => "form",
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => "heading",
+        "header" => "banner",
+        "hr" => "separator",
+        "img" => "img",
+        "li" => "listitem",
+        "main" => "main",
+        "nav" => "navigation",
+        "ol" | "ul" | "menu" => "list",
+        "option" => "option",
+        "progress" => "progressbar",
+        "section" => "region",
+        "select" => "combobox",
+        "table" => "table",
+        "tbody" | "thead" | "tfoot" => "rowgroup",
+        "td" => "cell",
+        "textarea" => "textbox",
+        "th" => "columnheader",
+        "tr" => "row",
+        "input" => {
+            let input_type = attr("type").map(|a| a.value.to_ascii_lowercase());
+            match input_type.as_deref() {
+                Some("checkbox") => "checkbox",
+                Some("radio") => "radio",
+                Some("range") => "slider",
+                Some("number") => "spinbutton",
+                Some("search") => "searchbox",
+                Some("button") | Some("submit") | Some("reset") | Some("image") => "button",
+                Some("hidden") | Some("file") | Some("password") | Some("color") | Some("date") => {
+                    return Vec::new();
+                }
+                _ => "textbox",
             }
         }
+        _ => return Vec::new(),
+    };
+
+    vec![implicit.to_string()]
+}
+
+/// A selector argument compiled once per selector step: compared exactly, or matched by
+/// its pattern when the selector is a regex
+pub enum ValueMatcher {
+    Exact(String),
+    Pattern(Pattern),
+}
+
+impl ValueMatcher {
+    /// Compile a selector argument
+    pub fn new(value: &str, is_regex: bool) -> InterpreterResult<Self> {
+        Ok(if is_regex {
+            ValueMatcher::Pattern(Pattern::new(value)?)
+        } else {
+            ValueMatcher::Exact(value.to_string())
+        })
     }
 
-    // Recursively search child nodes
-    let children = get_children(node)?;
-    for child in children {
-        let mut child_results = find_by_attr(&child, attr_name, attr_value, is_regex)?;
-        result.append(&mut child_results);
+    /// Check whether a name or value matches
+    pub fn is_match(&self, text: &str) -> bool {
+        match self {
+            ValueMatcher::Exact(value) => text == value,
+            ValueMatcher::Pattern(re) => re.is_match(text),
+        }
     }
+}
 
-    Ok(result)
+/// Find elements with an attribute whose name and, if given, value match. Without a name
+/// matcher, any attribute whose value matches will do.
+pub fn find_by_attr(
+    node: &NodeHandle,
+    attr_name: Option<&ValueMatcher>,
+    attr_value: Option<&ValueMatcher>,
+) -> Vec<NodeHandle> {
+    find_matching(node, |current| {
+        current.attrs().iter().any(|attr| {
+            attr_name.is_none_or(|name| name.is_match(&attr.name))
+                && attr_value.is_none_or(|value| value.is_match(&attr.value))
+        })
+    })
 }
 
 /// Get element attribute value
-pub fn get_attribute(
-    node: &NodeHandle,
-    attr_name: &str,
-    is_regex: bool,
-) -> InterpreterResult<Option<String>> {
-    let handle = node.handle().ok_or_else(|| {
-        InterpreterError::execution_error("Node does not have a valid HTML reference")
-    })?;
-
-    // If not an element node, return None
-    if !node.is_element() {
-        return Ok(None);
+pub fn get_attribute(node: &NodeHandle, attr_name: &str) -> Option<String> {
+    node.attrs()
+        .iter()
+        .find(|attr| attr.name == attr_name)
+        .map(|attr| attr.value.clone())
+}
+
+/// Get the value of the first attribute whose name matches
+pub fn find_attribute(node: &NodeHandle, attr_name: &ValueMatcher) -> Option<String> {
+    node.attrs()
+        .iter()
+        .find(|attr| attr_name.is_match(&attr.name))
+        .map(|attr| attr.value.clone())
+}
+
+/// Get the language of a node from the nearest `lang` attribute on itself or an ancestor
+pub fn get_lang(node: &NodeHandle) -> Option<String> {
+    let mut current = Some(node.clone());
+
+    while let Some(node) = current {
+        if let NodeData::Element { attrs, .. } = node.data()
+            && let Some(attr) = attrs.iter().find(|attr| attr.name == "lang")
+        {
+            return Some(attr.value.clone());
+        }
+        current = node.parent();
     }
 
-    if let NodeData::Element { attrs, .. } = &handle.data {
-        let attributes = attrs.borrow();
+    None
+}
 
-        for attr in attributes.iter() {
-            let current_name = attr.name.local.to_string();
+/// Check whether a node is likely visible to a user: neither it nor any ancestor is
+/// hidden through attributes, inline styles, or by being a non-rendered element
+pub fn is_visible(node: &NodeHandle) -> bool {
+    let mut current = Some(node.clone());
 
-            let name_match = if is_regex {
-                match regex::Regex::new(attr_name) {
-                    Ok(re) => re.is_match(&current_name),
-                    Err(e) => {
-                        return Err(InterpreterError::execution_error(&format!(
-                            "Invalid regex pattern: {}",
-                            e
-                        )));
+    while let Some(node) = current {
+        if let NodeData::Element { name, attrs } = node.data() {
+            if matches!(name.as_str(), "head" | "script" | "style" | "template") {
+                return false;
+            }
+
+            for attr in attrs {
+                let value = attr.value.trim().to_ascii_lowercase();
+                let hidden = match attr.name.as_str() {
+                    "hidden" => true,
+                    "aria-hidden" => value == "true",
+                    "type" => name == "input" && value == "hidden",
+                    "style" => {
+                        let style: String = value.chars().filter(|c| !c.is_whitespace()).collect();
+                        style.contains("display:none") || style.contains("visibility:hidden")
                     }
+                    _ => false,
+                };
+
+                if hidden {
+                    return false;
                 }
-            } else {
-                current_name == attr_name
-            };
-            if name_match {
-                return Ok(Some(attr.value.to_string()));
             }
         }
+        current = node.parent();
     }
 
-    Ok(None)
+    true
+}
+
+/// Find the text nodes within the subtree of `node` (inclusive), in document order,
+/// leaving out whitespace-only ones
+pub fn find_text_nodes(node: &NodeHandle) -> Vec<NodeHandle> {
+    (node.index()..node.node().subtree_end)
+        .map(|index| NodeHandle::new(node.dom().clone(), index))
+        .filter(|node| matches!(node.data(), NodeData::Text { contents } if !contents.trim().is_empty()))
+        .collect()
+}
+
+/// Find the `a` and `area` elements within the subtrees of `nodes`, in document order.
+/// Contexts may be nested, so duplicates are removed.
+pub fn find_links(nodes: &[NodeHandle]) -> InterpreterResult<Vec<NodeHandle>> {
+    let mut anchors = Vec::new();
+    for node in nodes {
+        anchors.extend(find_by_tag(node, "a", false)?);
+        anchors.extend(find_by_tag(node, "area", false)?);
+    }
+    anchors.sort_by_key(|node| node.id());
+    anchors.dedup();
+
+    Ok(anchors)
 }
 
 /// Get href attribute value
 pub fn get_href(node: &NodeHandle) -> InterpreterResult<Option<String>> {
-    get_attribute(node, "href", false)
+    Ok(get_attribute(node, "href"))
 }
 
 /// Get src attribute value
 pub fn get_src(node: &NodeHandle) -> InterpreterResult<Option<String>> {
-    get_attribute(node, "src", false)
+    Ok(get_attribute(node, "src"))
+}
+
+/// Get the effective values of a form control: the `value` attribute of inputs (`on`
+/// for checkboxes and radio buttons without one), the selected options of selects, the
+/// text of textareas, and the value or text of options. Other elements give their
+/// `value` attribute, if any.
+pub fn get_form_values(node: &NodeHandle) -> InterpreterResult<Vec<String>> {
+    let value = get_attribute(node, "value");
+
+    match node.tag_name() {
+        Some("input") => {
+            let is_checkable = get_attribute(node, "type")
+                .is_some_and(|t| matches!(t.trim().to_ascii_lowercase().as_str(), "checkbox" | "radio"));
+            let default = if is_checkable { "on" } else { "" };
+            Ok(vec![value.unwrap_or_else(|| default.to_string())])
+        }
+        Some("textarea") => Ok(vec![extract_text(node)?]),
+        Some("option") => Ok(vec![get_option_value(node)?]),
+        Some("select") => {
+            let options = find_by_tag(node, "option", false)?;
+            let mut selected = Vec::new();
+            for option in &options {
+                if get_attribute(option, "selected").is_some() {
+                    selected.push(option);
+                }
+            }
+
+            // Without `multiple` the last selected option wins, and the first enabled
+            // option is selected when none is
+            if get_attribute(node, "multiple").is_none() {
+                let mut chosen = selected.last().copied();
+                if chosen.is_none() {
+                    for option in &options {
+                        if get_attribute(option, "disabled").is_none() {
+                            chosen = Some(option);
+                            break;
+                        }
+                    }
+                }
+                selected = chosen.into_iter().collect();
+            }
+
+            selected.into_iter().map(get_option_value).collect()
+        }
+        _ => Ok(value.into_iter().collect()),
+    }
+}
+
+/// Value of an option: its `value` attribute, or its text with whitespace collapsed
+fn get_option_value(option: &NodeHandle) -> InterpreterResult<String> {
+    match get_attribute(option, "value") {
+        Some(value) => Ok(value),
+        None => Ok(extract_text(option)?.split_whitespace().collect::<Vec<_>>().join(" ")),
+    }
+}
+
+/// Tag names of the element ancestors of a node and of the node itself, outermost
+/// first, separated by `/`, e.g. `html/body/ul/li`
+pub fn get_tag_path(node: &NodeHandle) -> String {
+    let mut path = Vec::new();
+    let mut current = Some(node.clone());
+    while let Some(node) = current {
+        if let Some(name) = node.tag_name() {
+            path.push(name.to_string());
+        }
+        current = node.parent();
+    }
+    path.reverse();
+
+    path.join("/")
 }
 
 /// Get all node attributes
 pub fn get_all_attributes(node: &NodeHandle) -> InterpreterResult<HashMap<String, String>> {
-    let handle = node.handle().ok_or_else(|| {
-        InterpreterError::execution_error("Node does not have a valid HTML reference")
-    })?;
-
     let mut result = HashMap::new();
 
-    if let NodeData::Element { attrs, .. } = &handle.data {
-        let attributes = attrs.borrow();
-
-        for attr in attributes.iter() {
-            let name = attr.name.local.to_string();
-            let value = attr.value.to_string();
+    if let NodeData::Element { attrs, .. } = node.data() {
+        for attr in attrs.iter() {
+            let name = attr.name.clone();
+            let value = attr.value.clone();
             result.insert(name, value);
         }
     }
@@ -14,7 +14,7 @@ pub fn apply_index_selection(it: &mut Interpreter, index: &IndexNode) -> Interpr
 
 fn apply_single_index(it: &mut Interpreter, index: &Literal) -> InterpreterResult<()> {
     it.result = match &it.result {
-        SelectionResult::Nodes(nodes) => {
+        SelectionResult::Nodes(nodes, _) => {
             let idx = match index {
                 Literal::Int(n) => {
                     let n = *n;
@@ -37,7 +37,7 @@ fn apply_single_index(it: &mut Interpreter, index: &Literal) -> InterpreterResul
             let selected_node = nodes[idx as usize].clone();
             SelectionResult::with_nodes(vec![selected_node])
         }
-        SelectionResult::Texts(texts) => {
+        SelectionResult::Texts(texts, _) => {
             let idx = match index {
                 Literal::Int(n) => {
                     let n = *n;
@@ -67,7 +67,7 @@ fn apply_single_index(it: &mut Interpreter, index: &Literal) -> InterpreterResul
 
 fn apply_multiple_indices(it: &mut Interpreter, indices: &Vec<Literal>) -> InterpreterResult<()> {
     it.result = match &it.result {
-        SelectionResult::Nodes(nodes) => {
+        SelectionResult::Nodes(nodes, _) => {
             let mut selected_nodes = Vec::with_capacity(indices.len());
 
             for index in indices {
@@ -77,7 +77,7 @@ fn apply_multiple_indices(it: &mut Interpreter, indices: &Vec<Literal>) -> Inter
 
             SelectionResult::with_nodes(selected_nodes)
         }
-        SelectionResult::Texts(texts) => {
+        SelectionResult::Texts(texts, _) => {
             let mut selected_texts = Vec::with_capacity(indices.len());
 
             for index in indices {
@@ -98,8 +98,8 @@ fn apply_range_indices(
     step: &Option<Literal>,
 ) -> InterpreterResult<()> {
     let len = match &it.result {
-        SelectionResult::Nodes(nodes) => nodes.len(),
-        SelectionResult::Texts(texts) => texts.len(),
+        SelectionResult::Nodes(nodes, _) => nodes.len(),
+        SelectionResult::Texts(texts, _) => texts.len(),
     } as i64;
 
     let start_index = match start {
@@ -0,0 +1,330 @@
+// interpreter/markdown.rs - Markdown rendering of subtrees
+//
+// Headings, paragraphs, links, images, lists, emphasis, code and quotes are converted
+// to their CommonMark form. Other block elements such as `div` or `section` only
+// separate paragraphs, other inline elements contribute their text, and non-content
+// elements (`script`, `style`, ...) are dropped.
+
+use super::dom::NodeData;
+use super::html;
+use super::result::NodeHandle;
+
+/// Elements that start a new block
+const BLOCK_TAGS: &[&str] = &[
+    "address", "article", "aside", "blockquote", "body", "caption", "dd", "details",
+    "dialog", "div", "dl", "dt", "fieldset", "figcaption", "figure", "footer", "form",
+    "h1", "h2", "h3", "h4", "h5", "h6", "header", "hgroup", "hr", "html", "li", "main",
+    "nav", "ol", "p", "pre", "section", "summary", "table", "tbody", "td", "tfoot", "th",
+    "thead", "tr", "ul",
+];
+
+/// Elements whose content is not part of the readable text
+const SKIPPED_TAGS: &[&str] = &[
+    "head", "noscript", "script", "select", "style", "svg", "template", "textarea",
+];
+
+/// Render the subtree of `node` as Markdown
+pub fn to_markdown(node: &NodeHandle) -> String {
+    blocks(std::slice::from_ref(node)).join("\n\n")
+}
+
+/// Render sibling nodes as Markdown blocks, gathering consecutive inline content into
+/// paragraphs
+fn blocks(nodes: &[NodeHandle]) -> Vec<String> {
+    let mut blocks = Vec::new();
+    let mut paragraph = Inline::default();
+
+    for node in nodes {
+        if is_skipped(node) {
+            continue;
+        }
+
+        if is_block(node) {
+            paragraph.flush_into(&mut blocks);
+            blocks.extend(block(node).into_iter().filter(|b| !b.is_empty()));
+        } else {
+            paragraph.node(node);
+        }
+    }
+
+    paragraph.flush_into(&mut blocks);
+    blocks
+}
+
+fn block(node: &NodeHandle) -> Vec<String> {
+    let children = node.children();
+
+    let rendered = match node.tag_name().unwrap_or_default() {
+        tag @ ("h1" | "h2" | "h3" | "h4" | "h5" | "h6") => {
+            let text = inline(&children);
+            if text.is_empty() {
+                return Vec::new();
+            }
+            let level = tag[1..].parse().unwrap_or(1);
+            format!("{} {}", "#".repeat(level), text)
+        }
+        "hr" => "---".to_string(),
+        "pre" => code_block(node),
+        "ul" => list(node, false),
+        "ol" => list(node, true),
+        "blockquote" => blocks(&children)
+            .join("\n\n")
+            .lines()
+            .map(|line| if line.is_empty() { ">".to_string() } else { format!("> {}", line) })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => return blocks(&children),
+    };
+
+    vec![rendered]
+}
+
+fn list(node: &NodeHandle, ordered: bool) -> String {
+    let start: usize = attr(node, "start")
+        .and_then(|s| s.trim().parse().ok())
+        .unwrap_or(1);
+
+    let items = node.children().into_iter().filter(|c| c.tag_name() == Some("li"));
+    let mut rendered = Vec::new();
+
+    for (i, item) in items.enumerate() {
+        let marker = if ordered {
+            format!("{}. ", start + i)
+        } else {
+            "- ".to_string()
+        };
+        let indent = " ".repeat(marker.len());
+
+        // Keep the item tight so a nested list stays attached to its text
+        let body = blocks(&item.children()).join("\n");
+        let mut lines = body.lines();
+        let mut text = format!("{}{}", marker, lines.next().unwrap_or_default());
+        for line in lines {
+            text.push('\n');
+            if !line.is_empty() {
+                text.push_str(&indent);
+                text.push_str(line);
+            }
+        }
+        rendered.push(text);
+    }
+
+    rendered.join("\n")
+}
+
+fn code_block(node: &NodeHandle) -> String {
+    let text = html::extract_text(node).unwrap_or_default();
+    let text = text.trim_end_matches('\n');
+
+    // `<pre><code class="language-rust">` names the language of the block
+    let language = node
+        .children()
+        .iter()
+        .find(|c| c.tag_name() == Some("code"))
+        .and_then(|code| attr(code, "class"))
+        .and_then(|class| {
+            class
+                .split_whitespace()
+                .find_map(|c| c.strip_prefix("language-").or_else(|| c.strip_prefix("lang-")))
+        })
+        .unwrap_or_default()
+        .to_string();
+
+    let mut fence = "```".to_string();
+    while text.contains(&fence) {
+        fence.push('`');
+    }
+
+    format!("{}{}\n{}\n{}", fence, language, text, fence)
+}
+
+fn inline(nodes: &[NodeHandle]) -> String {
+    let mut inline = Inline::default();
+    for node in nodes {
+        inline.node(node);
+    }
+    inline.out
+}
+
+/// Inline Markdown with collapsed whitespace. Spaces at the edges are remembered rather
+/// than written so that emphasis markers can be placed around the trimmed content.
+#[derive(Default)]
+struct Inline {
+    out: String,
+    leading_space: bool,
+    pending_space: bool,
+}
+
+impl Inline {
+    fn node(&mut self, node: &NodeHandle) {
+        match node.data() {
+            NodeData::Text { contents } => self.text(contents),
+            NodeData::Element { name, .. } => self.element(node, name),
+            _ => {}
+        }
+    }
+
+    fn children(&mut self, node: &NodeHandle) {
+        for child in node.children() {
+            self.node(&child);
+        }
+    }
+
+    fn element(&mut self, node: &NodeHandle, name: &str) {
+        match name {
+            "br" => {
+                if !self.out.is_empty() {
+                    self.pending_space = false;
+                    self.out.push_str("\\\n");
+                }
+            }
+            "em" | "i" => self.wrap(node, "*", "*"),
+            "strong" | "b" => self.wrap(node, "**", "**"),
+            "del" | "s" | "strike" => self.wrap(node, "~~", "~~"),
+            "code" | "kbd" | "samp" => self.code(node),
+            "a" => self.link(node),
+            "img" => self.image(node),
+            _ if is_skipped(node) => {}
+            _ => self.children(node),
+        }
+    }
+
+    fn wrap(&mut self, node: &NodeHandle, open: &str, close: &str) {
+        let mut inner = Inline::default();
+        inner.children(node);
+
+        if inner.leading_space {
+            self.space();
+        }
+        if !inner.out.is_empty() {
+            self.push(open);
+            self.out.push_str(&inner.out);
+            self.out.push_str(close);
+        }
+        if inner.pending_space {
+            self.space();
+        }
+    }
+
+    fn link(&mut self, node: &NodeHandle) {
+        let Some(href) = attr(node, "href").map(str::trim) else {
+            self.children(node);
+            return;
+        };
+
+        let mut inner = Inline::default();
+        inner.children(node);
+        if inner.out.is_empty() {
+            inner.out = escape(href);
+        }
+
+        let destination = if href.contains(|c: char| c.is_whitespace() || c == '(' || c == ')') {
+            format!("<{}>", href)
+        } else {
+            href.to_string()
+        };
+        let close = match attr(node, "title") {
+            Some(title) => format!("]({} \"{}\")", destination, title.replace('"', "\\\"")),
+            None => format!("]({})", destination),
+        };
+
+        if inner.leading_space {
+            self.space();
+        }
+        self.push("[");
+        self.out.push_str(&inner.out);
+        self.out.push_str(&close);
+        if inner.pending_space {
+            self.space();
+        }
+    }
+
+    fn image(&mut self, node: &NodeHandle) {
+        if let Some(src) = attr(node, "src") {
+            let alt = escape(attr(node, "alt").unwrap_or_default().trim());
+            self.push(&format!("![{}]({})", alt, src.trim()));
+        }
+    }
+
+    fn code(&mut self, node: &NodeHandle) {
+        let text = html::extract_text(node).unwrap_or_default().replace('\n', " ");
+        if text.is_empty() {
+            return;
+        }
+
+        if text.contains('`') {
+            self.push(&format!("`` {} ``", text));
+        } else {
+            self.push(&format!("`{}`", text));
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        for c in text.chars() {
+            if c.is_whitespace() {
+                self.space();
+            } else {
+                let mut buf = [0; 4];
+                self.push(&escape(c.encode_utf8(&mut buf)));
+            }
+        }
+    }
+
+    fn space(&mut self) {
+        if self.out.is_empty() {
+            self.leading_space = true;
+        } else if !self.out.ends_with('\n') {
+            self.pending_space = true;
+        }
+    }
+
+    fn push(&mut self, s: &str) {
+        if self.pending_space {
+            self.out.push(' ');
+            self.pending_space = false;
+        }
+        self.out.push_str(s);
+    }
+
+    /// End the current paragraph, adding it to `blocks` if it has any content
+    fn flush_into(&mut self, blocks: &mut Vec<String>) {
+        let mut text = std::mem::take(self).out;
+        while let Some(stripped) = text.strip_suffix("\\\n") {
+            text.truncate(stripped.len());
+        }
+        if !text.is_empty() {
+            blocks.push(text);
+        }
+    }
+}
+
+fn is_block(node: &NodeHandle) -> bool {
+    match node.data() {
+        NodeData::Document => true,
+        NodeData::Element { name, .. } => BLOCK_TAGS.contains(&name.as_str()),
+        _ => false,
+    }
+}
+
+fn is_skipped(node: &NodeHandle) -> bool {
+    node.tag_name().is_some_and(|tag| SKIPPED_TAGS.contains(&tag))
+}
+
+/// Escape characters that would otherwise be read as Markdown syntax
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '*' | '_' | '`' | '[' | ']') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn attr<'a>(node: &'a NodeHandle, name: &str) -> Option<&'a str> {
+    node.attrs()
+        .iter()
+        .find(|a| a.name == name)
+        .map(|a| a.value.as_str())
+}
@@ -1,23 +1,46 @@
+pub mod attrs;
+pub mod charset;
+#[cfg(any(feature = "gzip", feature = "brotli"))]
+pub mod compressed;
+pub mod datetime;
+pub mod dom;
 pub mod element;
 pub mod error;
+pub mod ext;
+pub mod format;
 pub mod function;
 pub mod html;
 pub mod index;
+pub mod markdown;
+pub mod node_map;
+pub mod options;
 pub mod pipeline;
 pub mod result;
+pub mod rewrite;
+pub mod serialize;
 pub mod set;
 pub mod text;
+pub mod url;
 
 use super::{
     parse,
     parser::{
         ElementNode, FunctionNode, IndexNode, Node, SelectorNode, SetOperationNode, TextNode,
-        Visitable, Visitor,
+        SyntaxVersion, Visitable, Visitor, parse_with, version::VersionGate,
     },
 };
 
+use html5ever::driver::ParseOpts;
+use std::collections::HashSet;
+use std::io::Read;
+use std::sync::Arc;
+
+pub use dom::HtmlWarning;
 pub use error::{InterpreterError, InterpreterResult};
-pub use result::{NodeHandle, SelectionResult};
+pub use node_map::NodeMap;
+pub use options::InterpreterOptions;
+pub use result::{EmptyReason, NodeHandle, NodePosition, SelectionResult};
+pub use serialize::SerializeOptions;
 
 
 
@@ -32,6 +55,16 @@ pub struct Interpreter {
 
     /// Flag indicating if it's the first interpretation
     pub is_first_interpret: bool,
+
+    /// Evaluation options, shared between branches
+    pub options: Arc<InterpreterOptions>,
+
+    /// First stage of the last selection that emptied a non-empty input
+    pub empty_reason: Option<EmptyReason>,
+
+    /// Bytes of results produced so far by the current selection, tracked when the
+    /// options set a memory budget
+    pub memory_used: usize,
 }
 
 impl Interpreter {
@@ -40,11 +73,99 @@ impl Interpreter {
 
         let document = html::parse_html(html)?;
 
-        Ok(Interpreter {
+        Ok(Self::with_document(document))
+    }
+
+    /// Create an interpreter, failing if the parser had to recover from malformed markup.
+    /// Always parses with html5ever, which reports what it recovered from.
+    pub fn new_strict(html: &str) -> InterpreterResult<Self> {
+        let it = Self::from_reader(&mut html.as_bytes())?;
+        it.ensure_well_formed()?;
+
+        Ok(it)
+    }
+
+    /// Create an interpreter from HTML read from a reader, parsed with the given options
+    pub fn with_parse_opts<R: Read>(reader: &mut R, opts: ParseOpts) -> InterpreterResult<Self> {
+        let document = html::parse_html_with(reader, opts)?;
+
+        Ok(Self::with_document(document))
+    }
+
+    /// Fail with the parser's messages if it had to recover from malformed markup
+    pub fn ensure_well_formed(&self) -> InterpreterResult<()> {
+        self.document.dom().ensure_well_formed()
+    }
+
+    /// Errors the parser recovered from while building the document
+    pub fn warnings(&self) -> &[HtmlWarning] {
+        self.document.dom().warnings()
+    }
+
+    /// Create an interpreter over an XML document
+    #[cfg(feature = "xml")]
+    pub fn new_xml(xml: &str) -> InterpreterResult<Self> {
+        let document = html::parse_xml(xml)?;
+
+        Ok(Self::with_document(document))
+    }
+
+    /// Create an interpreter from HTML read incrementally from a reader
+    pub fn from_reader<R: Read>(reader: &mut R) -> InterpreterResult<Self> {
+        let document = html::parse_html_from_reader(reader)?;
+
+        Ok(Self::with_document(document))
+    }
+
+    /// Create an interpreter over an already parsed document
+    pub fn with_document(document: NodeHandle) -> Self {
+        Interpreter {
             document: document.clone(),
             result: SelectionResult::with_nodes(vec![document]),
             is_first_interpret: true,
-        })
+            options: Arc::new(InterpreterOptions::default()),
+            empty_reason: None,
+            memory_used: 0,
+        }
+    }
+
+    /// Create an interpreter over the same document and options, starting from the
+    /// current selection. Only the selection is copied; the document tree is shared.
+    pub fn branch(&self) -> Self {
+        Interpreter {
+            document: self.document.clone(),
+            result: self.result.clone(),
+            is_first_interpret: false,
+            options: Arc::clone(&self.options),
+            empty_reason: None,
+            memory_used: self.memory_used,
+        }
+    }
+
+    /// Like `branch`, but starting from the document root instead of the current selection
+    pub(crate) fn fork(&self) -> Self {
+        Interpreter {
+            document: self.document.clone(),
+            result: SelectionResult::with_nodes(vec![self.document.clone()]),
+            is_first_interpret: false,
+            options: Arc::clone(&self.options),
+            empty_reason: None,
+            memory_used: 0,
+        }
+    }
+
+    /// Evaluate an AST starting from the given context, leaving the current selection
+    /// untouched. An empty result carries the reason it is empty.
+    pub(crate) fn evaluate(
+        &self,
+        context: SelectionResult,
+        node: &Node,
+    ) -> InterpreterResult<SelectionResult> {
+        let mut branch = self.fork();
+        branch.result = context;
+        branch.visit_node(node)?;
+        branch.result.set_empty_reason(branch.empty_reason);
+        Ok(branch.result)
     }
 
     /// Reset selection state
@@ -56,7 +177,15 @@ impl Interpreter {
     /// Select matching nodes
     pub fn select(&mut self, selector: &str) -> InterpreterResult<SelectionResult> {
         // Parse selector into AST
-        let ast = parse(selector).map_err(|e| InterpreterError::ParserError(e.to_string()))?;
+        let ast = self.parse_selector(selector)?;
+
+        self.select_parsed(&ast)
+    }
+
+    /// Select matching nodes with an already parsed selector
+    pub fn select_parsed(&mut self, ast: &Node) -> InterpreterResult<SelectionResult> {
+        self.empty_reason = None;
+        self.memory_used = 0;
 
         // No need to reset on first call, already initialized in new()
         // Need to reset selection state for subsequent calls
@@ -69,59 +198,201 @@ impl Interpreter {
 
         ast.accept(self)?;
 
-        // Create and return a copy of the result
+        // Create and return a copy of the result, with the reason it is empty
+        self.result.set_empty_reason(self.empty_reason.clone());
         Ok(self.result.clone())
     }
 
 
 
 
-    /// Select nodes in specified context
-    pub fn select_from(
-        &mut self,
-        context: &SelectionResult,
-        selector: &str,
-    ) -> InterpreterResult<SelectionResult> {
-        // Save original state
-        let original_result = self.result.clone();
-        let was_first = self.is_first_interpret;
+    /// Parse a selector with the configured validation passes
+    pub fn parse_selector(&self, selector: &str) -> InterpreterResult<Node> {
+        let version = self.options.syntax_version;
+        let result = match (&self.options.validator, version < SyntaxVersion::LATEST) {
+            (None, false) => parse(selector),
+            (validator, pinned) => {
+                let mut validator = validator.clone().unwrap_or_default();
+                if pinned {
+                    validator = validator.with_pass(VersionGate(version));
+                }
+                parse_with(selector, &validator).map(|(node, _)| node)
+            }
+        };
 
-        // Set context as current result
-        self.result = context.clone();
-        self.is_first_interpret = false; // Ensure selection state is not reset
+        result.map_err(|e| InterpreterError::ParserError(e.to_string()))
+    }
 
+    /// Select texts, each paired with the element it was extracted from. The stages
+    /// before the last one select the elements; the last stage, which produces the
+    /// texts, then runs on each element separately, so functions over the whole list
+    /// such as `@sort` or `@count` apply to the texts of one element at a time. The
+    /// operands of set operations are paired separately and combined like their texts.
+    pub fn select_with_nodes(&self, selector: &str) -> InterpreterResult<Vec<(String, NodeHandle)>> {
+        let ast = self.parse_selector(selector)?;
 
-        let ast = parse(selector).map_err(|e| InterpreterError::ParserError(e.to_string()))?;
-        self.visit_node(&ast)?;
+        self.pair_texts(SelectionResult::with_nodes(vec![self.document.clone()]), &ast)
+    }
 
-        let result = self.result.clone();
+    /// Evaluate `node` from `context`, pairing each text with its element
+    fn pair_texts(
+        &self,
+        context: SelectionResult,
+        node: &Node,
+    ) -> InterpreterResult<Vec<(String, NodeHandle)>> {
+        let last = match node {
+            Node::Pipeline(..) => {
+                let mut stages = pipeline::stages(node);
+                let last = stages.pop().expect("a pipeline has at least one stage");
+
+                let mut context = context;
+                for stage in stages {
+                    if context.is_empty() {
+                        return Ok(Vec::new());
+                    }
+                    context = self.evaluate(context, stage)?;
+                }
+                return self.pair_texts(context, last);
+            }
+            Node::SetOperation(op) => return self.pair_set_operation(context, op),
+            last => last,
+        };
+
+        let mut pairs = Vec::new();
+        for node in context.nodes()? {
+            let result = self.evaluate(SelectionResult::with_nodes(vec![node.clone()]), last)?;
+            if result.is_nodes() {
+                return Err(InterpreterError::execution_error(
+                    "The selector must end with a text selector or function to pair texts with nodes",
+                ));
+            }
+            pairs.extend(result.texts()?.iter().map(|text| (text.clone(), node.clone())));
+        }
 
-        // Restore original state
-        self.result = original_result;
-        self.is_first_interpret = was_first;
+        Ok(pairs)
+    }
 
-        Ok(result)
+    /// Pair the texts of both operands and combine them like the set operation combines
+    /// texts, by their values
+    fn pair_set_operation(
+        &self,
+        context: SelectionResult,
+        op: &SetOperationNode,
+    ) -> InterpreterResult<Vec<(String, NodeHandle)>> {
+        let texts = |pairs: &[(String, NodeHandle)]| -> HashSet<String> {
+            pairs.iter().map(|(text, _)| text.clone()).collect()
+        };
+
+        Ok(match op {
+            SetOperationNode::Union(left, right) => {
+                let mut pairs = self.pair_texts(context.clone(), left)?;
+                pairs.extend(self.pair_texts(context, right)?);
+                let mut seen = HashSet::new();
+                pairs.retain(|(text, _)| seen.insert(text.clone()));
+                pairs
+            }
+            SetOperationNode::Intersection(left, right) => {
+                let left = texts(&self.pair_texts(context.clone(), left)?);
+                let mut pairs = self.pair_texts(context, right)?;
+                pairs.retain(|(text, _)| left.contains(text));
+                pairs
+            }
+            SetOperationNode::Difference(left, right) => {
+                let mut pairs = self.pair_texts(context.clone(), left)?;
+                let right = texts(&self.pair_texts(context, right)?);
+                pairs.retain(|(text, _)| !right.contains(text));
+                pairs
+            }
+            SetOperationNode::Fallback(left, right, min) => {
+                let pairs = self.pair_texts(context.clone(), left)?;
+                if pairs.len() < *min {
+                    self.pair_texts(context, right)?
+                } else {
+                    pairs
+                }
+            }
+        })
+    }
+
+    /// Select nodes in specified context, leaving the current selection untouched
+    pub fn select_from(
+        &self,
+        context: &SelectionResult,
+        selector: &str,
+    ) -> InterpreterResult<SelectionResult> {
+        let ast = self.parse_selector(selector)?;
+
+        self.evaluate(context.clone(), &ast)
+    }
+}
+
+/// Name of a stage in diagnostics: the index or function call itself rather than the
+/// whole expression it applies to
+fn stage_label(node: &Node) -> String {
+    match node {
+        Node::IndexSelection(_, index) => format!(":{}", index),
+        Node::FunctionCall(_, func) => format!("@{}", func),
+        _ => node.to_string(),
     }
 }
 
 /// Implement Visitor trait to traverse and execute AST
 impl Visitor<InterpreterResult<()>> for Interpreter {
     fn visit_node(&mut self, node: &Node) -> InterpreterResult<()> {
+        let input = self.result.count();
+
         match node {
-            Node::Selector(selector) => self.visit_selector(selector),
-            Node::Pipeline(left, right) => self.visit_pipeline(left, right),
+            Node::Selector(selector) => self.visit_selector(selector)?,
+            Node::Pipeline(left, right) => return self.visit_pipeline(left, right),
             Node::IndexSelection(inner, index) => {
                 self.visit_node(inner)?;
-                self.visit_index(index)
+                self.visit_index(index)?
             }
             Node::SetOperation(op) => {
-                self.visit_set_operation(op)
+                self.visit_set_operation(op)?
             }
             Node::FunctionCall(inner, func) => {
-                self.visit_node(inner)?;
-                self.visit_function(func)
+                // Collect the per-item text functions applied in a row, outermost first
+                let mut chain = vec![func];
+                let mut base = inner.as_ref();
+                if function::is_per_item(&func.name) {
+                    while let Node::FunctionCall(inner, func) = base
+                        && function::is_per_item(&func.name)
+                    {
+                        chain.push(func);
+                        base = inner;
+                    }
+                }
+
+                self.visit_node(base)?;
+                if chain.len() > 1 && self.result.is_texts() {
+                    chain.reverse();
+                    function::apply_fused(self, &chain)?
+                } else {
+                    for func in chain.iter().rev() {
+                        self.visit_function(func)?
+                    }
+                }
+            }
+        }
+
+        if let Some(budget) = self.options.memory_budget {
+            self.memory_used += self.result.estimated_size();
+            if self.memory_used > budget {
+                return Err(InterpreterError::MemoryLimitExceeded(
+                    stage_label(node),
+                    self.memory_used,
+                    budget,
+                ));
             }
         }
+
+        // Stages that ran on an already empty input are not the cause
+        if self.empty_reason.is_none() && input > 0 && self.result.is_empty() {
+            self.empty_reason = Some(EmptyReason { stage: stage_label(node), input });
+        }
+
+        Ok(())
     }
 
     fn visit_selector(&mut self, selector: &SelectorNode) -> InterpreterResult<()> {
@@ -0,0 +1,122 @@
+// interpreter/node_map.rs - Per-node annotations
+//
+// Node IDs are unique across documents and stable for the lifetime of a parse, so
+// values computed in one query (scores, labels) can be attached to nodes and looked up
+// again from the results of another.
+
+use super::result::NodeHandle;
+use std::collections::BTreeMap;
+use std::collections::btree_map;
+
+/// Map from nodes to values, keyed by node ID. Iteration is in document order for nodes
+/// of the same document.
+#[derive(Debug, Clone)]
+pub struct NodeMap<T> {
+    entries: BTreeMap<usize, (NodeHandle, T)>,
+}
+
+impl<T> Default for NodeMap<T> {
+    fn default() -> Self {
+        NodeMap {
+            entries: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T> NodeMap<T> {
+    /// Create an empty map
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Attach a value to a node, returning the value it replaces
+    pub fn insert(&mut self, node: &NodeHandle, value: T) -> Option<T> {
+        self.entries
+            .insert(node.id(), (node.clone(), value))
+            .map(|(_, old)| old)
+    }
+
+    /// Get the value attached to a node
+    pub fn get(&self, node: &NodeHandle) -> Option<&T> {
+        self.entries.get(&node.id()).map(|(_, value)| value)
+    }
+
+    /// Get a mutable reference to the value attached to a node
+    pub fn get_mut(&mut self, node: &NodeHandle) -> Option<&mut T> {
+        self.entries.get_mut(&node.id()).map(|(_, value)| value)
+    }
+
+    /// Get the value attached to a node, attaching one computed by `f` if there is none
+    pub fn get_or_insert_with<F: FnOnce() -> T>(&mut self, node: &NodeHandle, f: F) -> &mut T {
+        &mut self
+            .entries
+            .entry(node.id())
+            .or_insert_with(|| (node.clone(), f()))
+            .1
+    }
+
+    /// Detach and return the value attached to a node
+    pub fn remove(&mut self, node: &NodeHandle) -> Option<T> {
+        self.entries.remove(&node.id()).map(|(_, value)| value)
+    }
+
+    /// Check whether a value is attached to a node
+    pub fn contains(&self, node: &NodeHandle) -> bool {
+        self.entries.contains_key(&node.id())
+    }
+
+    /// Number of annotated nodes
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Check if no node is annotated
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Iterate over annotated nodes and their values
+    pub fn iter(&self) -> NodeMapIter<'_, T> {
+        NodeMapIter {
+            inner: self.entries.values(),
+        }
+    }
+}
+
+impl<T> FromIterator<(NodeHandle, T)> for NodeMap<T> {
+    fn from_iter<I: IntoIterator<Item = (NodeHandle, T)>>(iter: I) -> Self {
+        let mut map = NodeMap::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<T> Extend<(NodeHandle, T)> for NodeMap<T> {
+    fn extend<I: IntoIterator<Item = (NodeHandle, T)>>(&mut self, iter: I) {
+        for (node, value) in iter {
+            self.insert(&node, value);
+        }
+    }
+}
+
+/// Iterator over the entries of a `NodeMap`
+pub struct NodeMapIter<'a, T> {
+    inner: btree_map::Values<'a, usize, (NodeHandle, T)>,
+}
+
+impl<'a, T> Iterator for NodeMapIter<'a, T> {
+    type Item = (&'a NodeHandle, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(node, value)| (node, value))
+    }
+}
+
+impl<'a, T> IntoIterator for &'a NodeMap<T> {
+    type Item = (&'a NodeHandle, &'a T);
+    type IntoIter = NodeMapIter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
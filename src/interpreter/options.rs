@@ -0,0 +1,55 @@
+// interpreter/options.rs - Interpreter configuration
+//
+// Options that change how selectors are evaluated against a document.
+
+use crate::parser::{SyntaxVersion, Validator};
+use super::ext::Extensions;
+#[cfg(feature = "entities")]
+use std::collections::HashMap;
+
+/// Interpreter options
+#[derive(Debug, Clone, Default)]
+pub struct InterpreterOptions {
+    /// When flattening element text, render `alt` of `<img>`/`<area>` and the value
+    /// of visible `<input>` controls, so the text matches what a user sees
+    pub void_element_text: bool,
+
+    /// When flattening element text, put block elements and `<br>` on separate lines
+    /// and prefix list items with bullets, instead of concatenating text nodes
+    pub block_text: bool,
+
+    /// Validation passes run over every selector; the built-in rules when `None`
+    pub validator: Option<Validator>,
+
+    /// Upper bound in bytes on the results produced while evaluating one selector,
+    /// summed over its stages. Evaluation fails with `MemoryLimitExceeded` once it is
+    /// passed; unlimited when `None`.
+    pub memory_budget: Option<usize>,
+
+    /// URL the document was retrieved from, used by `@absolute` to resolve relative
+    /// URLs, together with the document's `<base href>`
+    pub base_url: Option<String>,
+
+    /// Longest value in bytes that text selectors produce. Longer texts and attribute
+    /// values, such as inline base64 images, are cut at a character boundary and end
+    /// with `…[+N bytes]`, N being the number of bytes removed; unlimited when `None`.
+    pub max_value_len: Option<usize>,
+
+    /// Make text functions that index into strings, such as `@slice`, fail with
+    /// `InvalidStringIndex` when an index is out of range or inside a multi-byte
+    /// character, instead of clamping the indices or leaving the text unchanged
+    pub strict_functions: bool,
+
+    /// Grammar version selectors are written against
+    pub syntax_version: SyntaxVersion,
+
+    /// User data attached to the document with `set_ext`, for code that evaluates
+    /// selectors to read
+    pub ext: Extensions,
+
+    /// Additional named entities (name without `&` and `;`) decoded in extracted text.
+    /// The parser leaves references it does not know, such as `&nbsp2;`, as literal text;
+    /// since decoding happens afterwards, an escaped `&amp;name;` is decoded as well.
+    #[cfg(feature = "entities")]
+    pub entities: HashMap<String, String>,
+}
@@ -20,3 +20,15 @@ pub fn apply_pipeline(it: &mut Interpreter, left: &Node, right: &Node) -> Interp
 
     Ok(())
 }
+
+/// The stages of a pipeline, in the order they run
+pub(crate) fn stages(node: &Node) -> Vec<&Node> {
+    match node {
+        Node::Pipeline(left, right) => {
+            let mut stages = stages(left);
+            stages.extend(self::stages(right));
+            stages
+        }
+        node => vec![node],
+    }
+}
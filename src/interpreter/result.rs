@@ -1,136 +1,293 @@
+use super::dom::{Attribute, Dom, Node, NodeData};
 use super::error::{InterpreterError, InterpreterResult};
-use markup5ever_rcdom::{Handle as Html5Handle, NodeData};
 use std::fmt;
-use std::ptr;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
 
 
 /// HTML node handle type
-/// Wraps html5ever's node reference, providing additional functionality
-#[derive(Debug, Clone)]
+/// Points at a node inside a shared document tree, providing additional functionality
+#[derive(Clone)]
 pub struct NodeHandle {
-    // HTML node reference
-    node: Option<Html5Handle>,
+    // Shared document tree
+    dom: Arc<Dom>,
 
-    // Node identifier, used for debugging and comparison
-    node_id: String,
+    // Node index in the document tree
+    index: usize,
 }
 
 impl NodeHandle {
 
-    /// Create node from html5ever handle
-    pub(crate) fn from_html5(handle: Html5Handle) -> Self {
-        // Use the memory address of the underlying node as part of the identifier
-        let ptr_value = ptr::addr_of!(*handle) as usize;
+    /// Create node handle from a document tree and node index
+    pub(crate) fn new(dom: Arc<Dom>, index: usize) -> Self {
+        NodeHandle { dom, index }
+    }
+
+    /// Get node ID
+    ///
+    /// IDs are assigned in document order while parsing and are unique across all
+    /// documents parsed by the process, so they can be used as hash keys.
+    pub fn id(&self) -> usize {
+        self.dom.node_id(self.index)
+    }
+
+    /// Get node index within its document tree
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Get the document tree this node belongs to
+    pub fn dom(&self) -> &Arc<Dom> {
+        &self.dom
+    }
+
+    /// Get underlying tree node
+    pub fn node(&self) -> &Node {
+        self.dom.node(self.index)
+    }
+
+    /// Get node content
+    pub fn data(&self) -> &NodeData {
+        &self.node().data
+    }
+
+    /// Get the root node of the document this node belongs to
+    pub fn root(&self) -> NodeHandle {
+        NodeHandle::new(self.dom.clone(), self.dom.root())
+    }
+
+    /// Get parent node
+    pub fn parent(&self) -> Option<NodeHandle> {
+        self.node()
+            .parent
+            .map(|index| NodeHandle::new(self.dom.clone(), index))
+    }
+
+    /// Get child nodes
+    pub fn children(&self) -> Vec<NodeHandle> {
+        self.node()
+            .children
+            .iter()
+            .map(|index| NodeHandle::new(self.dom.clone(), *index))
+            .collect()
+    }
 
-        NodeHandle {
-            node: Some(handle),
-            node_id: format!("node-{:#x}", ptr_value),
+    /// Get the tag name of an element node
+    pub fn tag_name(&self) -> Option<&str> {
+        match self.data() {
+            NodeData::Element { name, .. } => Some(name.as_str()),
+            _ => None,
         }
     }
 
-    /// Get node ID
-    pub fn id(&self) -> &str {
-        &self.node_id
+    /// Get the attributes of an element in source order. When an attribute is repeated,
+    /// only its first occurrence is included.
+    pub fn attrs(&self) -> &[Attribute] {
+        match self.data() {
+            NodeData::Element { attrs, .. } => attrs,
+            _ => &[],
+        }
+    }
+
+    /// Get the `data-*` attributes of an element in source order, with the `data-` prefix
+    /// removed from their names
+    pub fn dataset(&self) -> Vec<(&str, &str)> {
+        self.attrs()
+            .iter()
+            .filter_map(|a| Some((a.name.strip_prefix("data-")?, a.value.as_str())))
+            .collect()
     }
 
-    /// Get underlying html5ever handle
-    pub fn handle(&self) -> Option<&Html5Handle> {
-        self.node.as_ref()
+    /// Get the attributes dropped because the element repeated their names, in source
+    /// order. Always empty unless the document was parsed with
+    /// `QueryBuilder::keep_duplicate_attrs`.
+    pub fn duplicate_attrs(&self) -> &[Attribute] {
+        self.dom.duplicate_attrs(self.index)
+    }
+
+    /// Get the position of an element among its same-tag siblings, together with the
+    /// parent's tag name. Useful to rebuild table or grid coordinates of results.
+    pub fn position(&self) -> Option<NodePosition> {
+        let tag = self.tag_name()?;
+        let parent = self.parent()?;
+
+        let mut sibling_index = 0;
+        let mut sibling_count = 0;
+        for sibling in parent.node().children.iter() {
+            if let NodeData::Element { name, .. } = &self.dom.node(*sibling).data
+                && name == tag
+            {
+                if *sibling == self.index {
+                    sibling_index = sibling_count;
+                }
+                sibling_count += 1;
+            }
+        }
+
+        Some(NodePosition {
+            parent_tag: parent.tag_name().map(|t| t.to_string()),
+            sibling_index,
+            sibling_count,
+        })
     }
 
     /// Get node type name
     pub fn node_type(&self) -> &str {
-        if let Some(handle) = &self.node {
-            match &handle.data {
-                NodeData::Document => "Document",
-                NodeData::Element { .. } => "Element",
-                NodeData::Text { .. } => "Text",
-                NodeData::Comment { .. } => "Comment",
-                NodeData::ProcessingInstruction { .. } => "ProcessingInstruction",
-                NodeData::Doctype { .. } => "Doctype",
-            }
-        } else {
-            "None"
+        match self.data() {
+            NodeData::Document => "Document",
+            NodeData::Element { .. } => "Element",
+            NodeData::Text { .. } => "Text",
+            NodeData::Comment { .. } => "Comment",
+            NodeData::ProcessingInstruction { .. } => "ProcessingInstruction",
+            NodeData::Doctype { .. } => "Doctype",
         }
     }
 
     /// Check if it's an element node
     pub fn is_element(&self) -> bool {
-        if let Some(handle) = &self.node {
-            matches!(handle.data, NodeData::Element { .. })
-        } else {
-            false
-        }
+        matches!(self.data(), NodeData::Element { .. })
     }
 
     /// Check if it's a text node
     pub fn is_text(&self) -> bool {
-        if let Some(handle) = &self.node {
-            matches!(handle.data, NodeData::Text { .. })
-        } else {
-            false
-        }
+        matches!(self.data(), NodeData::Text { .. })
+    }
+
+    /// Render the node and its descendants as Markdown. Headings, links, images, lists,
+    /// emphasis, code and quotes are converted; other markup contributes its text.
+    pub fn to_markdown(&self) -> String {
+        super::markdown::to_markdown(self)
+    }
+
+    /// Serialize the node and its descendants as HTML with default options.
+    pub fn to_html(&self) -> String {
+        super::serialize::to_html(self, &super::SerializeOptions::default())
     }
 }
 
 impl fmt::Display for NodeHandle {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Node({})", self.node_id)
+        write!(f, "Node({})", self.id())
+    }
+}
+
+impl fmt::Debug for NodeHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The document tree is shared by every handle, so only the node itself is shown
+        f.debug_struct("NodeHandle")
+            .field("index", &self.index)
+            .field("tag", &self.tag_name().unwrap_or(self.node_type()))
+            .finish()
     }
 }
 
 impl PartialEq for NodeHandle {
     fn eq(&self, other: &Self) -> bool {
-        // If both nodes have underlying node references, compare if they point to the same node
-        if let (Some(self_handle), Some(other_handle)) = (&self.node, &other.node) {
-            ptr::addr_of!(**self_handle) == ptr::addr_of!(**other_handle)
-        } else {
-            // If there's no underlying node reference, compare IDs
-            self.node_id == other.node_id
-        }
+        // Node IDs are unique across documents
+        self.id() == other.id()
+    }
+}
+
+impl Eq for NodeHandle {}
+
+impl Hash for NodeHandle {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id().hash(state);
+    }
+}
+
+/// Position of an element among its siblings
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodePosition {
+    /// Tag name of the parent element, `None` when the parent is the document
+    pub parent_tag: Option<String>,
+
+    /// Index among sibling elements with the same tag name
+    pub sibling_index: usize,
+
+    /// Number of sibling elements with the same tag name, including this one
+    pub sibling_count: usize,
+}
+
+/// Why a selection came out empty: the first stage that received input but produced
+/// nothing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmptyReason {
+    /// The stage, as selector text (e.g. `tag a`, `href`, `@contains,x` or `:3`)
+    pub stage: String,
+
+    /// Number of nodes or texts the stage received
+    pub input: usize,
+}
+
+impl fmt::Display for EmptyReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}` produced no results from {} input item(s)", self.stage, self.input)
     }
 }
 
 /// Selection result enum
+///
+/// Each variant also carries the reason an empty result came out empty, when the
+/// selector that produced it recorded one; see [`SelectionResult::empty_reason`].
 #[derive(Debug, Clone)]
 pub enum SelectionResult {
     /// Selected HTML nodes
-    Nodes(Vec<NodeHandle>),
+    Nodes(Vec<NodeHandle>, Option<EmptyReason>),
 
     /// Extracted text values
-    Texts(Vec<String>),
+    Texts(Vec<String>, Option<EmptyReason>),
 }
 
 impl SelectionResult {
     /// Create a new empty node result
     pub fn new() -> Self {
-        SelectionResult::Nodes(Vec::new())
+        SelectionResult::Nodes(Vec::new(), None)
     }
 
     /// Create a result with nodes
     pub fn with_nodes(nodes: Vec<NodeHandle>) -> Self {
-        SelectionResult::Nodes(nodes)
+        SelectionResult::Nodes(nodes, None)
     }
 
     /// Create a result with texts
     pub fn with_texts(texts: Vec<String>) -> Self {
-        SelectionResult::Texts(texts)
+        SelectionResult::Texts(texts, None)
+    }
+
+    /// Why the result is empty: the first stage of the selector that received nodes or
+    /// texts but produced none. `None` for non-empty results and for results that were
+    /// not produced by a selector.
+    pub fn empty_reason(&self) -> Option<&EmptyReason> {
+        match self {
+            SelectionResult::Nodes(_, reason) | SelectionResult::Texts(_, reason) => reason.as_ref(),
+        }
+    }
+
+    /// Record why the result is empty; ignored when it is not
+    pub(crate) fn set_empty_reason(&mut self, empty_reason: Option<EmptyReason>) {
+        let is_empty = self.is_empty();
+        match self {
+            SelectionResult::Nodes(_, reason) | SelectionResult::Texts(_, reason) => {
+                *reason = empty_reason.filter(|_| is_empty);
+            }
+        }
     }
 
     /// Check if it's a node result
     pub fn is_nodes(&self) -> bool {
-        matches!(self, SelectionResult::Nodes(_))
+        matches!(self, SelectionResult::Nodes(..))
     }
 
     /// Check if it's a text result
     pub fn is_texts(&self) -> bool {
-        matches!(self, SelectionResult::Texts(_))
+        matches!(self, SelectionResult::Texts(..))
     }
 
     /// Get node result, return error if not a node result
     pub fn nodes(&self) -> InterpreterResult<&Vec<NodeHandle>> {
         match self {
-            SelectionResult::Nodes(nodes) => Ok(nodes),
+            SelectionResult::Nodes(nodes, _) => Ok(nodes),
             _ => Err(InterpreterError::execution_error("Result type is not nodes")),
         }
     }
@@ -138,7 +295,7 @@ impl SelectionResult {
     /// Get mutable node result, return error if not a node result
     pub fn nodes_mut(&mut self) -> InterpreterResult<&mut Vec<NodeHandle>> {
         match self {
-            SelectionResult::Nodes(nodes) => Ok(nodes),
+            SelectionResult::Nodes(nodes, _) => Ok(nodes),
             _ => Err(InterpreterError::execution_error("Result type is not nodes")),
         }
     }
@@ -146,7 +303,7 @@ impl SelectionResult {
     /// Get text result, return error if not a text result
     pub fn texts(&self) -> InterpreterResult<&Vec<String>> {
         match self {
-            SelectionResult::Texts(texts) => Ok(texts),
+            SelectionResult::Texts(texts, _) => Ok(texts),
             _ => Err(InterpreterError::execution_error("Result type is not texts")),
         }
     }
@@ -154,7 +311,7 @@ impl SelectionResult {
     /// Get mutable text result, return error if not a text result
     pub fn texts_mut(&mut self) -> InterpreterResult<&mut Vec<String>> {
         match self {
-            SelectionResult::Texts(texts) => Ok(texts),
+            SelectionResult::Texts(texts, _) => Ok(texts),
             _ => Err(InterpreterError::execution_error("Result type is not texts")),
         }
     }
@@ -162,23 +319,35 @@ impl SelectionResult {
     /// Get the number of elements in the result (nodes or texts)
     pub fn count(&self) -> usize {
         match self {
-            SelectionResult::Nodes(nodes) => nodes.len(),
-            SelectionResult::Texts(texts) => texts.len(),
+            SelectionResult::Nodes(nodes, _) => nodes.len(),
+            SelectionResult::Texts(texts, _) => texts.len(),
+        }
+    }
+
+    /// Approximate memory held by the result in bytes: the node handles, or the texts
+    /// including their contents
+    pub fn estimated_size(&self) -> usize {
+        match self {
+            SelectionResult::Nodes(nodes, _) => nodes.len() * std::mem::size_of::<NodeHandle>(),
+            SelectionResult::Texts(texts, _) => texts
+                .iter()
+                .map(|text| std::mem::size_of::<String>() + text.len())
+                .sum(),
         }
     }
 
     /// Check if the result is empty
     pub fn is_empty(&self) -> bool {
         match self {
-            SelectionResult::Nodes(nodes) => nodes.is_empty(),
-            SelectionResult::Texts(texts) => texts.is_empty(),
+            SelectionResult::Nodes(nodes, _) => nodes.is_empty(),
+            SelectionResult::Texts(texts, _) => texts.is_empty(),
         }
     }
 
     /// Get the first node, return error if not a node result or empty
     pub fn first_node(&self) -> InterpreterResult<&NodeHandle> {
         match self {
-            SelectionResult::Nodes(nodes) => nodes
+            SelectionResult::Nodes(nodes, _) => nodes
                 .first()
                 .ok_or_else(|| InterpreterError::execution_error("Node result is empty")),
             _ => Err(InterpreterError::execution_error("Result type is not nodes")),
@@ -188,7 +357,7 @@ impl SelectionResult {
     /// Get the first text value, return error if not a text result or empty
     pub fn first_text(&self) -> InterpreterResult<&str> {
         match self {
-            SelectionResult::Texts(texts) => texts
+            SelectionResult::Texts(texts, _) => texts
                 .first()
                 .ok_or_else(|| InterpreterError::execution_error("Text result is empty"))
                 .map(|s| s.as_str()),
@@ -199,7 +368,7 @@ impl SelectionResult {
     /// Get node at specific index, return error if not a node result or index out of bounds
     pub fn node_at(&self, index: usize) -> InterpreterResult<&NodeHandle> {
         match self {
-            SelectionResult::Nodes(nodes) => nodes
+            SelectionResult::Nodes(nodes, _) => nodes
                 .get(index)
                 .ok_or_else(|| InterpreterError::IndexOutOfBounds(index, nodes.len())),
             _ => Err(InterpreterError::execution_error("Result type is not nodes")),
@@ -209,7 +378,7 @@ impl SelectionResult {
     /// Get text value at specific index, return error if not a text result or index out of bounds
     pub fn text_at(&self, index: usize) -> InterpreterResult<&str> {
         match self {
-            SelectionResult::Texts(texts) => texts
+            SelectionResult::Texts(texts, _) => texts
                 .get(index)
                 .ok_or_else(|| InterpreterError::IndexOutOfBounds(index, texts.len()))
                 .map(|s| s.as_str()),
@@ -217,11 +386,31 @@ impl SelectionResult {
         }
     }
 
+    /// Format the result as a JSON array: strings for texts, `{"tag", "attrs", "text"}`
+    /// objects for nodes
+    pub fn to_json(&self) -> String {
+        super::format::to_json(self)
+    }
+
+    /// Format the result as CSV with a header row, quoting fields as needed
+    pub fn to_csv(&self) -> String {
+        super::format::to_csv(self)
+    }
+
+    /// Write the result as newline-delimited JSON, one object per item, returning the
+    /// number of lines written
+    pub fn write_ndjson<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<usize> {
+        super::format::write_ndjson(self, writer)
+    }
+
     /// Convert selection result to formatted string
+    ///
+    /// Texts are joined with newlines without escaping; use `to_json` or `to_csv` for
+    /// output that has to be parsed again.
     pub fn to_string_result(&self) -> String {
         match self {
-            SelectionResult::Texts(texts) => texts.join("\n"),
-            SelectionResult::Nodes(nodes) => {
+            SelectionResult::Texts(texts, _) => texts.join("\n"),
+            SelectionResult::Nodes(nodes, _) => {
                 if nodes.is_empty() {
                     "Empty result".to_string()
                 } else {
@@ -261,20 +450,20 @@ impl Iterator for SelectionResultIter {
 
     fn next(&mut self) -> Option<Self::Item> {
         match &self.inner {
-            SelectionResult::Nodes(nodes) => {
+            SelectionResult::Nodes(nodes, _) => {
                 if self.index < nodes.len() {
                     let node = nodes[self.index].clone();
                     self.index += 1;
-                    Some(SelectionResult::Nodes(vec![node]))
+                    Some(SelectionResult::with_nodes(vec![node]))
                 } else {
                     None
                 }
             },
-            SelectionResult::Texts(texts) => {
+            SelectionResult::Texts(texts, _) => {
                 if self.index < texts.len() {
                     let text = texts[self.index].clone();
                     self.index += 1;
-                    Some(SelectionResult::Texts(vec![text]))
+                    Some(SelectionResult::with_texts(vec![text]))
                 } else {
                     None
                 }
@@ -282,4 +471,51 @@ impl Iterator for SelectionResultIter {
         }
     }
 }
- 
\ No newline at end of file
+
+/// Texts serialize as an array of strings, nodes as an array of
+/// `{"tag": ..., "attrs": {...}, "text": ...}` objects (`tag` is null for non-elements).
+#[cfg(feature = "serde")]
+impl serde::Serialize for SelectionResult {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeSeq;
+
+        match self {
+            SelectionResult::Texts(texts, _) => texts.serialize(serializer),
+            SelectionResult::Nodes(nodes, _) => {
+                let mut seq = serializer.serialize_seq(Some(nodes.len()))?;
+                for node in nodes {
+                    seq.serialize_element(node)?;
+                }
+                seq.end()
+            }
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for NodeHandle {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::{Error, SerializeMap, SerializeStruct};
+
+        /// Attributes as a map in source order
+        struct Attrs<'a>(&'a [Attribute]);
+
+        impl serde::Serialize for Attrs<'_> {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                let mut map = serializer.serialize_map(Some(self.0.len()))?;
+                for attr in self.0 {
+                    map.serialize_entry(&attr.name, &attr.value)?;
+                }
+                map.end()
+            }
+        }
+
+        let text = super::html::extract_text(self).map_err(S::Error::custom)?;
+
+        let mut node = serializer.serialize_struct("Node", 3)?;
+        node.serialize_field("tag", &self.tag_name())?;
+        node.serialize_field("attrs", &Attrs(self.attrs()))?;
+        node.serialize_field("text", &text)?;
+        node.end()
+    }
+}
@@ -0,0 +1,101 @@
+// interpreter/rewrite.rs - URL rewriting for mirroring and proxying
+//
+// Documents are immutable and shared, so rewriting produces a copy of the tree with new
+// attribute values. Only the URL parts are passed to the callback: the candidates of a
+// `srcset` keep their width and density descriptors, and `url()` references in `style`
+// attributes keep their quotes and the surrounding declarations.
+
+use std::sync::Arc;
+
+use super::result::NodeHandle;
+
+/// Rewrite the URLs in the document of `node`, returning the handle of the same node in
+/// the rewritten copy
+pub fn rewrite_urls<F: FnMut(&str) -> String>(node: &NodeHandle, mut rewrite: F) -> NodeHandle {
+    let dom = node.dom().map_attributes(|_, attr| {
+        attr.value = match attr.name.as_str() {
+            "href" | "src" => rewrite(&attr.value),
+            "srcset" => rewrite_srcset(&attr.value, &mut rewrite),
+            "style" => rewrite_css_urls(&attr.value, &mut rewrite),
+            _ => return,
+        };
+    });
+
+    NodeHandle::new(Arc::new(dom), node.index())
+}
+
+/// Rewrite the URL of each candidate in a `srcset` list such as `a.png 1x, b.png 2x`
+fn rewrite_srcset<F: FnMut(&str) -> String>(srcset: &str, rewrite: &mut F) -> String {
+    let mut out = String::with_capacity(srcset.len());
+    let mut rest = srcset;
+
+    loop {
+        // Separators and whitespace between candidates are kept as they are
+        let candidate = rest.trim_start_matches(|c: char| c.is_ascii_whitespace() || c == ',');
+        out.push_str(&rest[..rest.len() - candidate.len()]);
+        if candidate.is_empty() {
+            break;
+        }
+
+        let url_end = candidate.find(|c: char| c.is_ascii_whitespace()).unwrap_or(candidate.len());
+        let url = &candidate[..url_end];
+
+        // A URL directly followed by commas is a candidate without descriptors
+        let trimmed = url.trim_end_matches(',');
+        if trimmed.len() < url.len() {
+            out.push_str(&rewrite(trimmed));
+            rest = &candidate[trimmed.len()..];
+            continue;
+        }
+
+        out.push_str(&rewrite(url));
+        let descriptors = &candidate[url_end..];
+        let descriptors_end = descriptors.find(',').unwrap_or(descriptors.len());
+        out.push_str(&descriptors[..descriptors_end]);
+        rest = &descriptors[descriptors_end..];
+    }
+
+    out
+}
+
+/// Rewrite the `url()` references in CSS, quoted or not
+fn rewrite_css_urls<F: FnMut(&str) -> String>(css: &str, rewrite: &mut F) -> String {
+    let mut out = String::with_capacity(css.len());
+    let mut rest = css;
+
+    // ASCII lowercasing keeps byte offsets, so positions found in the copy apply to `rest`
+    while let Some(start) = rest.to_ascii_lowercase().find("url(") {
+        let (head, tail) = rest.split_at(start + "url(".len());
+        out.push_str(head);
+
+        let body = tail.trim_start();
+        out.push_str(&tail[..tail.len() - body.len()]);
+
+        let quote = body.chars().next().filter(|c| matches!(c, '"' | '\''));
+        let url_start = quote.map_or(0, char::len_utf8);
+        let Some(url_len) = body[url_start..].find(quote.unwrap_or(')')) else {
+            // Unterminated reference, leave the rest untouched
+            rest = body;
+            break;
+        };
+        let url = &body[url_start..url_start + url_len];
+
+        match quote {
+            Some(quote) => {
+                out.push(quote);
+                out.push_str(&rewrite(url));
+                out.push(quote);
+                rest = &body[url_start + url_len + 1..];
+            }
+            None => {
+                let trimmed = url.trim_end();
+                out.push_str(&rewrite(trimmed));
+                out.push_str(&url[trimmed.len()..]);
+                rest = &body[url_len..];
+            }
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
@@ -0,0 +1,358 @@
+// interpreter/serialize.rs - HTML serialization of documents and subtrees
+//
+// Writes the tree back out as HTML. The default output follows the structure of the
+// parsed tree as it is; `Layout::Pretty` indents block elements and `Layout::Minified`
+// drops comments and insignificant whitespace. Contents of `script`, `style` and other
+// raw text elements are written unescaped, and preformatted text is never reflowed.
+
+use super::dom::NodeData;
+use super::result::NodeHandle;
+
+/// Options for serializing HTML
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SerializeOptions {
+    /// Whitespace handling of the output
+    pub layout: Layout,
+
+    /// How void elements such as `<br>` are written
+    pub void_style: VoidStyle,
+
+    /// Which characters are written as character references
+    pub entities: EntityPolicy,
+}
+
+/// Whitespace handling of serialized HTML
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Layout {
+    /// Text and whitespace exactly as in the tree
+    #[default]
+    AsParsed,
+
+    /// Block elements on lines of their own, indented by two spaces per level
+    Pretty,
+
+    /// Comments dropped, whitespace collapsed, and whitespace next to block elements removed
+    Minified,
+}
+
+/// How void elements are written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum VoidStyle {
+    /// `<br>`
+    #[default]
+    Html,
+
+    /// `<br />`, as expected by XML tools
+    SelfClosing,
+}
+
+/// Which characters are written as character references
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EntityPolicy {
+    /// Only what is required: `&`, `<` and `>` in text, `&` and `"` in attribute
+    /// values, and non-breaking spaces as `&nbsp;`
+    #[default]
+    Minimal,
+
+    /// Additionally every non-ASCII character as a numeric reference, for output that
+    /// must survive a non-UTF-8 channel
+    Ascii,
+}
+
+/// Elements without content or end tag
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "keygen", "link", "meta",
+    "param", "source", "track", "wbr",
+];
+
+/// Elements whose text is not escaped
+const RAW_TEXT_ELEMENTS: &[&str] = &[
+    "iframe", "noembed", "noframes", "noscript", "plaintext", "script", "style", "xmp",
+];
+
+/// Elements whose whitespace is significant
+const PREFORMATTED_ELEMENTS: &[&str] = &["listing", "pre", "textarea"];
+
+/// Elements laid out as blocks: whitespace around them can be dropped when minifying,
+/// and they start a new line when pretty printing
+const BLOCK_ELEMENTS: &[&str] = &[
+    "address", "article", "aside", "base", "blockquote", "body", "caption", "col",
+    "colgroup", "dd", "details", "dialog", "div", "dl", "dt", "fieldset", "figcaption",
+    "figure", "footer", "form", "h1", "h2", "h3", "h4", "h5", "h6", "head", "header",
+    "hgroup", "hr", "html", "li", "link", "main", "meta", "nav", "ol", "option", "p", "pre",
+    "script", "section", "select", "style", "summary", "table", "tbody", "td", "template",
+    "tfoot", "th", "thead", "title", "tr", "ul",
+];
+
+/// Serialize a node and its descendants. The document node serializes as the whole
+/// document.
+pub fn to_html(node: &NodeHandle, options: &SerializeOptions) -> String {
+    let mut serializer = Serializer {
+        options,
+        out: String::new(),
+    };
+
+    match options.layout {
+        Layout::Pretty => {
+            if matches!(node.data(), NodeData::Document) {
+                serializer.pretty_children(node, 0);
+            } else {
+                serializer.pretty_node(node, 0);
+            }
+            serializer.out.trim_start_matches('\n').to_string()
+        }
+        _ => {
+            serializer.node(node);
+            serializer.out
+        }
+    }
+}
+
+struct Serializer<'a> {
+    options: &'a SerializeOptions,
+    out: String,
+}
+
+impl Serializer<'_> {
+    /// Write a node on the current line
+    fn node(&mut self, node: &NodeHandle) {
+        match node.data() {
+            NodeData::Document => {
+                for child in node.children() {
+                    self.node(&child);
+                }
+            }
+            NodeData::Element { name, .. } => {
+                self.start_tag(node);
+                if VOID_ELEMENTS.contains(&name.as_str()) {
+                    return;
+                }
+                // The parser drops a newline right after `<pre>`, so one that belongs to
+                // the content has to be doubled
+                let children = node.children();
+                if PREFORMATTED_ELEMENTS.contains(&name.as_str())
+                    && children.first().is_some_and(|first| {
+                        matches!(first.data(), NodeData::Text { contents } if contents.starts_with('\n'))
+                    })
+                {
+                    self.out.push('\n');
+                }
+                for child in children {
+                    self.node(&child);
+                }
+                self.end_tag(name);
+            }
+            NodeData::Text { contents } => self.text(node, contents),
+            NodeData::Comment { contents } => {
+                if self.options.layout != Layout::Minified {
+                    self.out.push_str(&format!("<!--{}-->", contents));
+                }
+            }
+            NodeData::Doctype {
+                name,
+                public_id,
+                system_id,
+            } => {
+                self.out.push_str(&format!("<!DOCTYPE {}", name));
+                if !public_id.is_empty() {
+                    self.out.push_str(&format!(" PUBLIC \"{}\"", public_id));
+                    if !system_id.is_empty() {
+                        self.out.push_str(&format!(" \"{}\"", system_id));
+                    }
+                } else if !system_id.is_empty() {
+                    self.out.push_str(&format!(" SYSTEM \"{}\"", system_id));
+                }
+                self.out.push('>');
+            }
+            NodeData::ProcessingInstruction { target, contents } => {
+                self.out.push_str(&format!("<?{} {}>", target, contents));
+            }
+        }
+    }
+
+    /// Write a node that starts on a line of its own, followed by its children laid out
+    /// by block
+    fn pretty_node(&mut self, node: &NodeHandle, depth: usize) {
+        self.newline(depth);
+
+        let Some(name) = node.tag_name() else {
+            self.node(node);
+            return;
+        };
+
+        // Keep elements whose content is all inline on one line, and never reflow
+        // preformatted or raw text
+        let has_block_children = node.children().iter().any(is_block);
+        if VOID_ELEMENTS.contains(&name)
+            || PREFORMATTED_ELEMENTS.contains(&name)
+            || RAW_TEXT_ELEMENTS.contains(&name)
+            || !has_block_children
+        {
+            self.node(node);
+            return;
+        }
+
+        self.start_tag(node);
+        self.pretty_children(node, depth + 1);
+        self.newline(depth);
+        self.end_tag(name);
+    }
+
+    fn pretty_children(&mut self, parent: &NodeHandle, depth: usize) {
+        let mut on_inline_line = false;
+
+        for child in parent.children() {
+            if is_block(&child) {
+                self.pretty_node(&child, depth);
+                on_inline_line = false;
+                continue;
+            }
+
+            if is_blank(&child) {
+                continue;
+            }
+            if !on_inline_line {
+                self.newline(depth);
+                on_inline_line = true;
+            }
+            self.node(&child);
+        }
+    }
+
+    fn newline(&mut self, depth: usize) {
+        self.out.truncate(self.out.trim_end_matches(' ').len());
+        self.out.push('\n');
+        self.out.push_str(&"  ".repeat(depth));
+    }
+
+    fn start_tag(&mut self, node: &NodeHandle) {
+        let Some(name) = node.tag_name() else {
+            return;
+        };
+
+        self.out.push('<');
+        self.out.push_str(name);
+        for attr in node.attrs() {
+            self.out.push(' ');
+            self.out.push_str(&attr.name);
+            self.out.push_str("=\"");
+            self.escape(&attr.value, true);
+            self.out.push('"');
+        }
+
+        if VOID_ELEMENTS.contains(&name) && self.options.void_style == VoidStyle::SelfClosing {
+            self.out.push_str(" />");
+        } else {
+            self.out.push('>');
+        }
+    }
+
+    fn end_tag(&mut self, name: &str) {
+        self.out.push_str("</");
+        self.out.push_str(name);
+        self.out.push('>');
+    }
+
+    fn text(&mut self, node: &NodeHandle, contents: &str) {
+        let parent = node.parent();
+        let parent_tag = parent.as_ref().and_then(|p| p.tag_name()).unwrap_or_default();
+        if RAW_TEXT_ELEMENTS.contains(&parent_tag) {
+            self.out.push_str(contents);
+            return;
+        }
+
+        let reflow = self.options.layout != Layout::AsParsed && !is_preformatted(node);
+        if !reflow {
+            self.escape(contents, false);
+            return;
+        }
+
+        if is_blank(node) && (self.options.layout == Layout::Pretty || next_to_block(node)) {
+            return;
+        }
+
+        let mut collapsed = String::with_capacity(contents.len());
+        for word in contents.split_ascii_whitespace() {
+            if !collapsed.is_empty() {
+                collapsed.push(' ');
+            }
+            collapsed.push_str(word);
+        }
+
+        // Keep the separation from neighbouring inline content
+        let at_line_start = self.out.is_empty() || self.out.ends_with([' ', '\n']);
+        if contents.starts_with(|c: char| c.is_ascii_whitespace()) && !at_line_start {
+            self.out.push(' ');
+        }
+        self.escape(&collapsed, false);
+        if contents.ends_with(|c: char| c.is_ascii_whitespace()) && !collapsed.is_empty() {
+            self.out.push(' ');
+        }
+    }
+
+    fn escape(&mut self, text: &str, in_attribute: bool) {
+        for c in text.chars() {
+            match c {
+                '&' => self.out.push_str("&amp;"),
+                '\u{a0}' => self.out.push_str("&nbsp;"),
+                '"' if in_attribute => self.out.push_str("&quot;"),
+                '<' if !in_attribute => self.out.push_str("&lt;"),
+                '>' if !in_attribute => self.out.push_str("&gt;"),
+                c if !c.is_ascii() && self.options.entities == EntityPolicy::Ascii => {
+                    self.out.push_str(&format!("&#x{:X};", c as u32))
+                }
+                c => self.out.push(c),
+            }
+        }
+    }
+}
+
+/// Check whether a node is laid out as a block. Comments and doctypes are, so that
+/// they get a line of their own when pretty printing.
+fn is_block(node: &NodeHandle) -> bool {
+    match node.data() {
+        NodeData::Element { name, .. } => BLOCK_ELEMENTS.contains(&name.as_str()),
+        NodeData::Text { .. } => false,
+        _ => true,
+    }
+}
+
+fn is_blank(node: &NodeHandle) -> bool {
+    matches!(node.data(), NodeData::Text { contents } if contents.trim_ascii().is_empty())
+}
+
+fn is_preformatted(node: &NodeHandle) -> bool {
+    let mut current = node.parent();
+    while let Some(ancestor) = current {
+        if ancestor
+            .tag_name()
+            .is_some_and(|tag| PREFORMATTED_ELEMENTS.contains(&tag))
+        {
+            return true;
+        }
+        current = ancestor.parent();
+    }
+    false
+}
+
+/// Check whether whitespace at this position separates nothing visible: the text is at
+/// the edge of a block element or next to one
+fn next_to_block(node: &NodeHandle) -> bool {
+    let Some(parent) = node.parent() else {
+        return true;
+    };
+    let siblings = parent.children();
+    let Some(position) = siblings.iter().position(|s| s == node) else {
+        return true;
+    };
+
+    let before = position.checked_sub(1).map(|i| &siblings[i]);
+    let after = siblings.get(position + 1);
+    let parent_is_block = is_block(&parent) || matches!(parent.data(), NodeData::Document);
+
+    match (before, after) {
+        (Some(before), Some(after)) => is_block(before) || is_block(after),
+        (Some(sibling), None) | (None, Some(sibling)) => parent_is_block || is_block(sibling),
+        (None, None) => parent_is_block,
+    }
+}
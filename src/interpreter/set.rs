@@ -14,6 +14,7 @@ pub fn apply_set_operation(it: &mut Interpreter, node: &SetOperationNode) -> Int
         SetOperationNode::Union(left, right) => union_operation(it, left, right)?,
         SetOperationNode::Intersection(left, right) => intersection_operation(it, left, right)?,
         SetOperationNode::Difference(left, right) => difference_operation(it, left, right)?,
+        SetOperationNode::Fallback(left, right, min) => fallback_operation(it, left, right, *min)?,
     }
 
     Ok(())
@@ -27,21 +28,28 @@ enum OperationResults {
 
 /// Execute both sides of the node and return results
 fn execute_sides(
-    it: &Interpreter,
+    it: &mut Interpreter,
     left: &Box<Node>,
     right: &Box<Node>,
     op_name: &str,
 ) -> InterpreterResult<OperationResults> {
-    // Execute left and right nodes, using clone but optimized to only copy necessary parts
-    let mut it_left = it.clone();
-    let mut it_right = it.clone();
-
-    it_left.visit_node(left)?;
-    it_right.visit_node(right)?;
+    // Both sides start from the current selection. The document tree is shared,
+    // so only the selection context is duplicated per branch.
+    #[cfg(feature = "rayon")]
+    let (left_results, right_results) = {
+        let mut right_it = it.branch();
+        let memory_before = it.memory_used;
+        let (left_results, right_results) = rayon::join(
+            || execute_branch(it, left),
+            || execute_branch(&mut right_it, right),
+        );
+        // The right branch started from the shared count; add what it produced
+        it.memory_used += right_it.memory_used - memory_before;
+        (left_results?, right_results?)
+    };
 
-    // Get results from both sides
-    let left_results = it_left.result;
-    let right_results = it_right.result;
+    #[cfg(not(feature = "rayon"))]
+    let (left_results, right_results) = (execute_branch(it, left)?, execute_branch(it, right)?);
 
     // Ensure both sides have consistent result types
     if left_results.is_nodes() != right_results.is_nodes() {
@@ -66,6 +74,37 @@ fn execute_sides(
     }
 }
 
+/// Execute one side of a set operation, restoring the starting selection afterwards
+fn execute_branch(it: &mut Interpreter, node: &Node) -> InterpreterResult<SelectionResult> {
+    let context = it.result.clone();
+    // An empty side does not explain the result of the whole operation
+    let empty_reason = it.empty_reason.take();
+    let outcome = it.visit_node(node);
+    let result = std::mem::replace(&mut it.result, context);
+    it.empty_reason = empty_reason;
+    outcome.map(|_| result)
+}
+
+/// Fallback operation. Unlike the other operations, the right side is only evaluated
+/// when the left side falls short, and its result is used as it is.
+fn fallback_operation(
+    it: &mut Interpreter,
+    left: &Node,
+    right: &Node,
+    min: usize,
+) -> InterpreterResult<()> {
+    let context = it.result.clone();
+    it.visit_node(left)?;
+
+    if it.result.count() < min {
+        it.result = context;
+        it.empty_reason = None;
+        it.visit_node(right)?;
+    }
+
+    Ok(())
+}
+
 /// Union operation
 fn union_operation(
     it: &mut Interpreter,
@@ -84,7 +123,7 @@ fn union_operation(
 
             // Add left side results
             for node in left_nodes {
-                let id = node.id().to_string();
+                let id = node.id();
                 if seen_ids.insert(id) {
                     result.push(node);
                 }
@@ -92,7 +131,7 @@ fn union_operation(
 
             // Add unseen right side results
             for node in right_nodes {
-                let id = node.id().to_string();
+                let id = node.id();
                 if seen_ids.insert(id) {
                     result.push(node);
                 }
@@ -139,9 +178,9 @@ fn intersection_operation(
     match execute_sides(it, left, right, "intersection")? {
         OperationResults::Nodes(left_nodes, right_nodes) => {
             // Create hash set of left side node IDs, pre-allocate capacity
-            let left_ids: HashSet<String> = left_nodes
+            let left_ids: HashSet<usize> = left_nodes
                 .iter()
-                .map(|node| node.id().to_string())
+                .map(|node| node.id())
                 .collect();
 
             // Estimate result capacity (worst case is all from right side)
@@ -149,7 +188,7 @@ fn intersection_operation(
             
             // Filter right side nodes, only keep nodes with IDs appearing on the left side
             for node in right_nodes {
-                let node_id = node.id().to_string();
+                let node_id = node.id();
                 if left_ids.contains(&node_id) {
                     node_result.push(node);
                 }
@@ -188,9 +227,9 @@ fn difference_operation(
     match execute_sides(it, left, right, "difference")? {
         OperationResults::Nodes(left_nodes, right_nodes) => {
             // Create hash set of right side node IDs, pre-allocate capacity
-            let right_ids: HashSet<String> = right_nodes
+            let right_ids: HashSet<usize> = right_nodes
                 .iter()
-                .map(|node| node.id().to_string())
+                .map(|node| node.id())
                 .collect();
 
             // Estimate result capacity (worst case is all from left side)
@@ -198,7 +237,7 @@ fn difference_operation(
             
             // Filter left side nodes, exclude nodes with IDs appearing on the right side
             for node in left_nodes {
-                let node_id = node.id().to_string();
+                let node_id = node.id();
                 if !right_ids.contains(&node_id) {
                     node_result.push(node);
                 }
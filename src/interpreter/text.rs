@@ -1,6 +1,7 @@
 use super::error::{InterpreterError, InterpreterResult};
 use super::result::NodeHandle;
-use super::{Interpreter, SelectionResult, html};
+use super::html::ValueMatcher;
+use super::{Interpreter, InterpreterOptions, SelectionResult, html};
 use crate::parser::ast::TextNode;
 
 /// Apply text selector
@@ -8,10 +9,48 @@ pub fn apply_text_selector(it: &mut Interpreter, text_node: &TextNode) -> Interp
     let nodes = it.result.nodes()?;
 
     let result = match text_node {
-        TextNode::Text => extract_text_content(&nodes)?,
+        TextNode::Text => extract_text_content(&nodes, &it.options)?,
+        TextNode::OwnText => {
+            let mut texts = Vec::with_capacity(nodes.len());
+            for node in nodes {
+                texts.push(html::extract_own_text_with(node, &it.options)?);
+            }
+            texts
+        }
         TextNode::Href => extract_href_values(&nodes)?,
         TextNode::Src => extract_src_values(&nodes)?,
+        TextNode::Value => {
+            let mut values = Vec::new();
+            for node in nodes {
+                values.extend(html::get_form_values(node)?);
+            }
+            values
+        }
+        TextNode::Data => nodes
+            .iter()
+            .flat_map(|node| {
+                node.dataset()
+                    .into_iter()
+                    .map(|(name, value)| format!("{}={}", name, value))
+                    .collect::<Vec<_>>()
+            })
+            .collect(),
+        TextNode::Markdown => nodes.iter().map(NodeHandle::to_markdown).collect(),
+        TextNode::Links => extract_link_pairs(nodes, &it.options)?,
+        TextNode::TagPath => nodes.iter().map(html::get_tag_path).collect(),
         TextNode::AttrValue(name, is_regex) => extract_attr_values(&nodes, name, *is_regex)?,
+        TextNode::AttrPresent(name, is_regex) => {
+            let name = ValueMatcher::new(name, *is_regex)?;
+            nodes
+                .iter()
+                .map(|node| html::find_attribute(node, &name).is_some().to_string())
+                .collect()
+        }
+    };
+
+    let result = match it.options.max_value_len {
+        Some(limit) => result.into_iter().map(|text| truncate_value(text, limit)).collect(),
+        None => result,
     };
 
     it.result = SelectionResult::with_texts(result);
@@ -19,12 +58,46 @@ pub fn apply_text_selector(it: &mut Interpreter, text_node: &TextNode) -> Interp
     Ok(())
 }
 
+/// Cut a value to at most `limit` bytes, marker included, marking how much was removed.
+/// A limit too small for the marker cuts the value without one.
+fn truncate_value(mut text: String, limit: usize) -> String {
+    if text.len() <= limit {
+        return text;
+    }
+
+    let floor_boundary = |text: &str, mut end: usize| {
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        end
+    };
+
+    // Removing more text can lengthen the count in the marker, so shrink until both fit
+    let mut end = floor_boundary(&text, limit);
+    loop {
+        let marker = format!("…[+{} bytes]", text.len() - end);
+        if marker.len() > limit {
+            text.truncate(floor_boundary(&text, limit));
+            return text;
+        }
+        if end + marker.len() <= limit {
+            text.truncate(end);
+            text.push_str(&marker);
+            return text;
+        }
+        end = floor_boundary(&text, limit - marker.len());
+    }
+}
+
 /// Extract text content from nodes
-fn extract_text_content(nodes: &Vec<NodeHandle>) -> InterpreterResult<Vec<String>> {
+fn extract_text_content(
+    nodes: &Vec<NodeHandle>,
+    options: &InterpreterOptions,
+) -> InterpreterResult<Vec<String>> {
     let mut text_values = Vec::with_capacity(nodes.len());
 
     for node in nodes {
-        match html::extract_text(node) {
+        match html::extract_text_with(node, options) {
             Ok(text) => text_values.push(text),
             Err(err) => {
                 return Err(InterpreterError::TextExtractionError(format!(
@@ -38,6 +111,24 @@ fn extract_text_content(nodes: &Vec<NodeHandle>) -> InterpreterResult<Vec<String
     Ok(text_values)
 }
 
+/// Pair the text of each link within the nodes with its href, as `text<TAB>href`
+fn extract_link_pairs(
+    nodes: &[NodeHandle],
+    options: &InterpreterOptions,
+) -> InterpreterResult<Vec<String>> {
+    let mut pairs = Vec::new();
+
+    for node in html::find_links(nodes)? {
+        if let Some(href) = html::get_href(&node)? {
+            let text = html::extract_text_with(&node, options)?;
+            let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+            pairs.push(format!("{}\t{}", text, href));
+        }
+    }
+
+    Ok(pairs)
+}
+
 /// Extract href attribute values from nodes
 fn extract_href_values(nodes: &Vec<NodeHandle>) -> InterpreterResult<Vec<String>> {
     let mut href_values = Vec::new();
@@ -80,21 +171,9 @@ fn extract_src_values(nodes: &Vec<NodeHandle>) -> InterpreterResult<Vec<String>>
 
 
 /// Extract attribute values from nodes
-fn extract_attr_values(nodes: &Vec<NodeHandle>, name: &str, is_regex: bool) -> InterpreterResult<Vec<String>> {
-    let mut src_values = Vec::new();
-
-    for node in nodes {
-        match html::get_attribute(node, name, is_regex){
-            Ok(Some(src)) => src_values.push(src),
-            Ok(None) => {} // Node has no src attribute, skip
-            Err(err) => {
-                return Err(InterpreterError::AttributeExtractionError(format!(
-                    "Unable to extract src attribute: {}",
-                    err
-                )));
-            }
-        }
-    }
+fn extract_attr_values(nodes: &[NodeHandle], name: &str, is_regex: bool) -> InterpreterResult<Vec<String>> {
+    let name = ValueMatcher::new(name, is_regex)?;
 
-    Ok(src_values)
+    // Nodes without the attribute are skipped
+    Ok(nodes.iter().filter_map(|node| html::find_attribute(node, &name)).collect())
 }
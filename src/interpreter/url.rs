@@ -0,0 +1,190 @@
+// interpreter/url.rs - Resolution of relative URLs
+//
+// Resolves references such as `../a.png` or `//cdn.example/x.js` against a base URL
+// following RFC 3986, section 5. Only the syntax is handled: hosts are not normalized
+// and percent-encoding is kept as written.
+
+use super::result::NodeHandle;
+
+/// Components of a URI reference
+struct Parts<'a> {
+    scheme: Option<&'a str>,
+    authority: Option<&'a str>,
+    path: &'a str,
+    query: Option<&'a str>,
+    fragment: Option<&'a str>,
+}
+
+/// Resolve `reference` against the absolute URL `base`. Returns `None` if `base` is not
+/// absolute.
+pub fn resolve(base: &str, reference: &str) -> Option<String> {
+    let base = split(base.trim());
+    let scheme = base.scheme?;
+
+    // Browsers ignore surrounding whitespace and line breaks within URLs in attributes
+    let reference: String = reference
+        .trim()
+        .chars()
+        .filter(|c| !matches!(c, '\t' | '\n' | '\r'))
+        .collect();
+    let reference = split(&reference);
+
+    let (scheme, authority, path, query) = if let Some(ref_scheme) = reference.scheme {
+        (
+            ref_scheme,
+            reference.authority,
+            remove_dot_segments(reference.path),
+            reference.query,
+        )
+    } else if reference.authority.is_some() {
+        (
+            scheme,
+            reference.authority,
+            remove_dot_segments(reference.path),
+            reference.query,
+        )
+    } else if reference.path.is_empty() {
+        (
+            scheme,
+            base.authority,
+            base.path.to_string(),
+            reference.query.or(base.query),
+        )
+    } else if reference.path.starts_with('/') {
+        (
+            scheme,
+            base.authority,
+            remove_dot_segments(reference.path),
+            reference.query,
+        )
+    } else {
+        let merged = if base.authority.is_some() && base.path.is_empty() {
+            format!("/{}", reference.path)
+        } else {
+            let dir = base.path.rfind('/').map_or("", |i| &base.path[..=i]);
+            format!("{}{}", dir, reference.path)
+        };
+        (
+            scheme,
+            base.authority,
+            remove_dot_segments(&merged),
+            reference.query,
+        )
+    };
+
+    let mut url = format!("{}:", scheme);
+    if let Some(authority) = authority {
+        url.push_str("//");
+        url.push_str(authority);
+        if path.is_empty() {
+            url.push('/');
+        }
+    }
+    url.push_str(&path);
+    if let Some(query) = query {
+        url.push('?');
+        url.push_str(query);
+    }
+    if let Some(fragment) = reference.fragment {
+        url.push('#');
+        url.push_str(fragment);
+    }
+
+    Some(url)
+}
+
+/// Base URL of the document `node` belongs to: the `href` of its first `<base>`
+/// element, resolved against `url`, or `url` itself
+pub fn document_base(node: &NodeHandle, url: Option<&str>) -> Option<String> {
+    let dom = node.dom();
+    let base_href = dom
+        .elements_by_tag("base", node.root().index())
+        .iter()
+        .map(|index| NodeHandle::new(dom.clone(), *index))
+        .find_map(|base| {
+            base.attrs()
+                .iter()
+                .find(|a| a.name == "href")
+                .map(|a| a.value.clone())
+        });
+
+    match (base_href, url) {
+        (Some(href), Some(url)) => resolve(url, &href).or_else(|| Some(url.to_string())),
+        // Only an absolute `<base href>` can stand on its own
+        (Some(href), None) => split(href.trim()).scheme.map(|_| href.trim().to_string()),
+        (None, url) => url.map(str::to_string),
+    }
+}
+
+fn split(uri: &str) -> Parts<'_> {
+    let (rest, fragment) = match uri.split_once('#') {
+        Some((rest, fragment)) => (rest, Some(fragment)),
+        None => (uri, None),
+    };
+    let (rest, query) = match rest.split_once('?') {
+        Some((rest, query)) => (rest, Some(query)),
+        None => (rest, None),
+    };
+
+    // A scheme is letters, digits, `+`, `-` and `.` before the first `:`, starting
+    // with a letter; a `:` after a `/` belongs to the path
+    let scheme_end = rest.find(':').filter(|&end| {
+        let scheme = &rest[..end];
+        scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+    });
+    let (scheme, rest) = match scheme_end {
+        Some(end) => (Some(&rest[..end]), &rest[end + 1..]),
+        None => (None, rest),
+    };
+
+    let (authority, path) = match rest.strip_prefix("//") {
+        Some(rest) => {
+            let end = rest.find('/').unwrap_or(rest.len());
+            (Some(&rest[..end]), &rest[end..])
+        }
+        None => (None, rest),
+    };
+
+    Parts {
+        scheme,
+        authority,
+        path,
+        query,
+        fragment,
+    }
+}
+
+/// Remove `.` and `..` segments from a path (RFC 3986, section 5.2.4)
+fn remove_dot_segments(path: &str) -> String {
+    let mut output: Vec<&str> = Vec::new();
+    let absolute = path.starts_with('/');
+    let segments: Vec<&str> = path.split('/').skip(usize::from(absolute)).collect();
+
+    for (i, segment) in segments.iter().enumerate() {
+        let last = i == segments.len() - 1;
+        match *segment {
+            "." => {
+                if last {
+                    output.push("");
+                }
+            }
+            ".." => {
+                output.pop();
+                if last {
+                    output.push("");
+                }
+            }
+            segment => output.push(segment),
+        }
+    }
+
+    let joined = output.join("/");
+    if absolute {
+        format!("/{}", joined)
+    } else {
+        joined
+    }
+}
@@ -1,6 +1,8 @@
 use std::error::Error;
 use std::fmt;
 
+use crate::pattern::glob_to_regex;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Token {
     // pipe operator
@@ -12,12 +14,18 @@ pub enum Token {
     Tag,   // tag
     Attr,  // attr
 
+    // relational selectors, followed by a parenthesized expression
+    Not, // not(
+    Has, // has(
+
     // text selection instruction
     Text, // text
     Src,  // src
     Href, // href
     // attribute value text selection: #
     Pound,
+    // attribute presence text selection: ?
+    Question,
 
     // function call
     Function(String), // @name
@@ -60,6 +68,8 @@ pub enum Token {
     Intersection,
     // ^
     Difference,
+    // ||
+    Fallback,
 
     EOF,
 }
@@ -72,6 +82,8 @@ impl fmt::Display for Token {
             Token::Id => write!(f, "id"),
             Token::Tag => write!(f, "tag"),
             Token::Attr => write!(f, "attr"),
+            Token::Not => write!(f, "not"),
+            Token::Has => write!(f, "has"),
             Token::Text => write!(f, "text"),
             Token::Src => write!(f, "src"),
             Token::Href => write!(f, "href"),
@@ -91,11 +103,13 @@ impl fmt::Display for Token {
             Token::Union => write!(f, "|"),
             Token::Intersection => write!(f, "&"),
             Token::Difference => write!(f, "^"),
+            Token::Fallback => write!(f, "||"),
             Token::Dot => write!(f, "."),
             Token::DotDot => write!(f, ".."),
             Token::Nil => write!(f, "nil"),
             Token::EOF => write!(f, "EOF"),
             Token::Pound => write!(f, "#"),
+            Token::Question => write!(f, "?"),
         }
     }
 }
@@ -133,6 +147,12 @@ pub struct Lexer {
     line: usize,
     // current column number
     column: usize,
+    // flags of the regex whose pattern is the next quoted string
+    regex_flags: Option<String>,
+    // whether the next quoted string is a glob
+    glob: bool,
+    // number of parentheses currently open
+    paren_depth: usize,
 }
 
 impl Lexer {
@@ -148,6 +168,9 @@ impl Lexer {
             current_char: None,
             line: 1,
             column: 0,
+            regex_flags: None,
+            glob: false,
+            paren_depth: 0,
         };
 
         lexer.read_char();
@@ -239,7 +262,12 @@ impl Lexer {
             }
             '|' => {
                 self.read_char();
-                Ok(Token::Union)
+                if self.current_char == Some('|') {
+                    self.read_char();
+                    Ok(Token::Fallback)
+                } else {
+                    Ok(Token::Union)
+                }
             }
             '^' => {
                 self.read_char();
@@ -250,17 +278,49 @@ impl Lexer {
                 Ok(Token::Intersection)
             }
             '@' => self.read_function(),
-            '"' => self.read_quoted_string(),
+            '"' => match (self.read_quoted_string()?, self.regex_flags.take()) {
+                (Token::String(glob), _) if std::mem::take(&mut self.glob) => {
+                    Ok(Token::String(glob_to_regex(&glob)))
+                }
+                (Token::String(pattern), Some(flags)) => {
+                    Ok(Token::String(format!("(?{}){}", flags, pattern)))
+                }
+                (token, _) => Ok(token),
+            },
+            // A glob such as `*"nav-*"` is matched as the equivalent regex
+            '*' if self.chars.get(self.read_position) == Some(&'"') => {
+                self.read_char();
+                self.glob = true;
+                Ok(Token::Tilde)
+            }
             '~' => {
                 self.read_char();
+
+                // Flags between `~` and a quoted pattern, as in `~i"nav"`, become an
+                // inline group at the start of the pattern: `(?i)nav`
+                let flags_len = self.chars[self.position.min(self.chars.len())..]
+                    .iter()
+                    .take_while(|c| c.is_ascii_alphabetic())
+                    .count();
+                if flags_len > 0 && self.chars.get(self.position + flags_len) == Some(&'"') {
+                    let mut flags = String::with_capacity(flags_len);
+                    for _ in 0..flags_len {
+                        flags.extend(self.current_char);
+                        self.read_char();
+                    }
+                    self.regex_flags = Some(flags);
+                }
+
                 Ok(Token::Tilde)
             }
             '(' => {
                 self.read_char();
+                self.paren_depth += 1;
                 Ok(Token::LeftParen)
             }
             ')' => {
                 self.read_char();
+                self.paren_depth = self.paren_depth.saturating_sub(1);
                 Ok(Token::RightParen)
             }
             '[' => {
@@ -288,6 +348,10 @@ impl Lexer {
                 self.read_char();
                 Ok(Token::Pound)
             }
+            '?' => {
+                self.read_char();
+                Ok(Token::Question)
+            }
             _ => self.read_string(),
         }
     }
@@ -514,11 +578,26 @@ impl Lexer {
             if c.is_whitespace() || c == '>' || c == ',' || c == '"' || c == '@' || c == ':' {
                 break;
             }
+            // Inside parentheses, `)` closes them rather than ending up in the value
+            if c == ')' && self.paren_depth > 0 {
+                break;
+            }
+            // `not(` and `has(` start relational selectors; elsewhere `(` is part of the
+            // value
+            if c == '('
+                && matches!(&self.chars[start_position..self.position], ['n', 'o', 't'] | ['h', 'a', 's'])
+            {
+                break;
+            }
             self.read_char();
         }
 
         let argument: String = self.chars[start_position..self.position].iter().collect();
 
+        if self.current_char == Some('(') {
+            return Ok(if argument == "not" { Token::Not } else { Token::Has });
+        }
+
         if argument.is_empty() {
             return Err(LexerError {
                 message: format!("Unrecognized characters: {:?}", self.current_char),
@@ -1,12 +1,25 @@
 pub mod lexer;
 pub mod parser;
 pub mod interpreter;
+pub mod corpus;
+pub mod document;
+pub mod extract;
+pub mod metadata;
+pub mod pattern;
 pub mod query;
+pub mod report;
+#[cfg(feature = "lol-html")]
+pub mod rewriter;
+pub mod stream;
 
 use lexer::*;
 use interpreter::*;
 use parser::*;
-pub use query::Query;
+pub use document::{Document, Walk, WalkEvent};
+pub use metadata::Metadata;
+pub use query::{AuditEntry, Link, Query, QueryBuilder, zip_records};
+#[cfg(feature = "async")]
+pub use query::QueryStream;
 
 
 
@@ -14,7 +27,7 @@ pub use query::Query;
 mod tests {
 
 
-    use super::Query;
+    use super::{Document, Interpreter, InterpreterError, Query};
 
     #[test]
     fn main_test() {
@@ -35,5 +48,463 @@ mod tests {
         println!("{:?}", result); // ["text2", "text3", "text4"]
     }
 
+    #[test]
+    fn parse_price_test() {
+        let html = r#"
+        <span class="price">£1,299.00</span>
+        <span class="price">1 299,00 €</span>
+        <span class="price">USD 42.5</span>
+        <span class="price">NEW £10</span>
+        <span class="price">sold out</span>
+        "#;
+        let q = Query::new(html);
+        let result = q.query("class price > text @parse_price").texts();
+        assert_eq!(result, vec!["1299.00 GBP", "1299.00 EUR", "42.5 USD", "10 GBP"]);
+    }
 
-}
\ No newline at end of file
+    #[test]
+    fn node_handle_debug_shows_only_the_node() {
+        let nodes = Query::new("<p>a</p>").query("tag p").nodes();
+        let debug = format!("{:?}", nodes[0]);
+        assert!(debug.starts_with("NodeHandle { index: ") && debug.ends_with(r#"tag: "p" }"#));
+    }
+
+    #[test]
+    fn max_value_len_counts_the_marker() {
+        let html = format!(r#"<img src="{}"><img src="short">"#, "x".repeat(100));
+        let srcs = Query::new(&html).max_value_len(30).query("tag img > src").texts();
+        assert_eq!(srcs, [format!("{}…[+84 bytes]", "x".repeat(16)), "short".to_string()]);
+        assert_eq!(srcs[0].len(), 30);
+    }
+
+    #[test]
+    fn query_is_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Query>();
+
+        let q = Query::new(r#"<p class="a">one</p><p class="b">two</p>"#);
+        let handles: Vec<_> = ["a", "b"]
+            .into_iter()
+            .map(|class| {
+                let q = q.clone();
+                std::thread::spawn(move || q.query(&format!("class {} > text", class)).texts())
+            })
+            .collect();
+        let results: Vec<Vec<String>> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert_eq!(results, vec![vec!["one"], vec!["two"]]);
+    }
+
+    #[test]
+    fn indexed_lookup_is_scoped_to_context() {
+        let html = r#"
+        <div id="x" class="a b"><p class="b">1</p></div>
+        <div class="c"><p class="b" id="x">2</p><span><p>3</p></span></div>
+        "#;
+        let q = Query::new(html);
+        assert_eq!(q.clone().query("class c > tag p > text").texts(), vec!["2", "3"]);
+        assert_eq!(q.clone().query("class c > class b > text").texts(), vec!["2"]);
+        assert_eq!(q.clone().query("class a > id x > text").texts(), vec!["1"]);
+        assert_eq!(q.query("class b > text").texts(), vec!["1", "1", "2"]);
+    }
+
+    #[test]
+    fn from_bytes_detects_charset() {
+        // "Привет" in windows-1251
+        let mut html = b"<meta charset=\"windows-1251\"><p>".to_vec();
+        html.extend_from_slice(&[0xCF, 0xF0, 0xE8, 0xE2, 0xE5, 0xF2]);
+        html.extend_from_slice(b"</p>");
+        let q = Query::from_bytes(&html);
+        assert_eq!(q.query("tag p > text").texts(), vec!["Привет"]);
+    }
+
+    #[test]
+    fn records_pair_columns() {
+        let html = r#"<li><b>A</b><i>1</i></li><li><b>B</b><i>2</i></li><li><b>C</b></li>"#;
+        let records = Query::new(html)
+            .records(&[("name", "tag b > text"), ("price", "tag i > text")]);
+        assert!(records.is_err());
+
+        let records = super::zip_records(vec![
+            ("name", vec!["A".to_string(), "B".to_string()]),
+            ("price", vec!["1".to_string(), "2".to_string()]),
+        ])
+        .unwrap();
+        assert_eq!(records[1]["name"], "B");
+        assert_eq!(records[1]["price"], "2");
+    }
+
+    #[test]
+    fn strict_mode_rejects_malformed_markup() {
+        let valid = "<!DOCTYPE html><html><head><title>t</title></head><body><p>x</p></body></html>";
+        assert!(Query::new(valid).warnings().is_empty());
+        assert!(Query::new_strict(valid).is_ok());
+
+        let malformed = "<!DOCTYPE html><html><body><p>x</div></body></html>";
+        // The tl backend reports no warnings; strict mode still parses with html5ever
+        #[cfg(not(feature = "tl"))]
+        assert!(!Query::new(malformed).warnings().is_empty());
+        assert!(Query::new_strict(malformed).is_err());
+    }
+
+    #[test]
+    fn json_and_csv_escape_values() {
+        let html = "<p title='a \"b\"'>one, two</p><p>line\nbreak</p>";
+        let q = Query::new(html);
+        assert_eq!(
+            q.clone().query("tag p > text").json().unwrap(),
+            r#"["one, two","line\nbreak"]"#
+        );
+        assert_eq!(
+            q.query("tag p").csv().unwrap(),
+            "tag,text,title\np,\"one, two\",\"a \"\"b\"\"\"\np,\"line\nbreak\",\n"
+        );
+    }
+
+    #[test]
+    fn queries_share_one_document() {
+        let doc = Document::parse("<div class=a><p>one</p></div><p>two</p>").unwrap();
+        let q = doc.query();
+        assert_eq!(q.clone().query("tag p > text").texts(), vec!["one", "two"]);
+
+        let copy = doc.clone();
+        let texts = std::thread::spawn(move || copy.query().query("class a > text").texts())
+            .join()
+            .unwrap();
+        assert_eq!(texts, vec!["one"]);
+        assert_eq!(q.document().root(), doc.root());
+    }
+
+    #[test]
+    fn metadata_collects_social_properties() {
+        let q = Query::new(
+            r#"<head><title> Page </title><meta name="description" content="About">
+            <link rel="canonical" href="https://example.com/page">
+            <meta property="og:image" content="a.png"><meta property="og:image" content="b.png">
+            <meta name="twitter:card" content="summary"></head>"#,
+        );
+        let meta = q.metadata();
+
+        assert_eq!(meta.title.as_deref(), Some("Page"));
+        assert_eq!(meta.description.as_deref(), Some("About"));
+        assert_eq!(meta.canonical.as_deref(), Some("https://example.com/page"));
+        assert_eq!(meta.open_graph.image.as_deref(), Some("a.png"));
+        assert_eq!(meta.open_graph.properties.len(), 2);
+        assert_eq!(meta.twitter.card.as_deref(), Some("summary"));
+    }
+
+    #[test]
+    fn block_text_separates_lines() {
+        let html = "<div><p>a  <b>b</b></p><p>c<br>d</p><ol><li>x</li><li>y</li></ol></div>";
+        let q = Query::new(html);
+        assert_eq!(q.clone().query("tag div > text").texts(), vec!["a  bcdxy"]);
+        assert_eq!(
+            q.block_text(true).query("tag div > text").texts(),
+            vec!["a b\nc\nd\n1. x\n2. y"]
+        );
+    }
+
+    #[test]
+    fn fused_functions_match_stepwise_evaluation() {
+        let q = Query::new("<p> Foo A </p><p>bar b</p><p> BAZ </p>");
+        let texts = q
+            .clone()
+            .query(r#"tag p > text @trim @lowercase @replace,"a","o" @starts_with,"b""#)
+            .texts();
+        assert_eq!(texts, vec!["bor b", "boz"]);
+
+        let q = q.query(r#"tag p > text @trim @contains,"o" @starts_with,"x""#);
+        let reason = q.empty_reason().unwrap();
+        assert_eq!((reason.stage.as_str(), reason.input), ("@starts_with,x", 1));
+
+        let result = Interpreter::new("<a>x</a>").unwrap().select("tag a > href").unwrap();
+        assert_eq!(result.empty_reason().unwrap().stage, "href");
+        let result = Interpreter::new("<a>x</a>").unwrap().select("tag a > text").unwrap();
+        assert!(result.empty_reason().is_none());
+    }
+
+    #[test]
+    fn corpus_reports_each_case() {
+        use crate::corpus::{Case, Corpus, Outcome, Output};
+
+        let report = Corpus::new()
+            .document("a", Document::parse("<h1>One</h1>").unwrap())
+            .document("b", Document::parse("<h1>Two</h1><h1>Three</h1>").unwrap())
+            .case(Case::texts("heading", "tag h1 > text", ["One"]).on("a"))
+            .case(Case::count("single h1", "tag h1", 1))
+            .case(Case::count("missing", "tag h1", 1).on("c"))
+            .run();
+
+        assert_eq!(report.results.len(), 4);
+        assert_eq!(report.passed(), 2);
+        let failures: Vec<_> = report.failures().map(|r| &r.outcome).collect();
+        assert!(matches!(failures[0], Outcome::Failed { actual: Output::Count(2) }));
+        assert!(matches!(failures[1], Outcome::UnknownDocument));
+    }
+
+    #[test]
+    fn rewrite_urls_keeps_descriptors_and_quotes() {
+        let html = r#"<img src="a.png" srcset="b.png 2x,c.png" style="background:url('d.png')">"#;
+        let q = Query::new(html).rewrite_urls(|url| format!("/m/{}", url));
+
+        let img = q.clone().query("tag img").node().unwrap();
+        let attrs: Vec<_> = img.attrs().iter().map(|a| a.value.as_str()).collect();
+        assert_eq!(attrs, ["/m/a.png", "/m/b.png 2x,/m/c.png", "background:url('/m/d.png')"]);
+        assert_eq!(q.query("tag img > src").texts(), ["/m/a.png"]);
+    }
+
+    #[test]
+    fn absolute_resolves_against_base_href() {
+        let q = Query::new(r#"<base href="/docs/"><a href="../img/a.png?x#y">a</a><a href="//cdn.example/b">b</a>"#)
+            .with_base_url("https://example.com/blog/post");
+        assert_eq!(
+            q.query("tag a > href @absolute").texts(),
+            ["https://example.com/img/a.png?x#y", "https://cdn.example/b"]
+        );
+    }
+
+    #[test]
+    fn strict_functions_report_bad_indices() {
+        let q = Query::new("<p>héllo</p>");
+        assert_eq!(q.clone().query("tag p > text @slice,0,2").texts(), ["héllo"]);
+
+        let strict = q.strict_functions(true);
+        assert_eq!(strict.clone().query("tag p > text @slice,0,3").texts(), ["hé"]);
+        assert!(matches!(
+            strict.query("tag p > text @slice,0,2").result(),
+            Err(InterpreterError::InvalidStringIndex(_, _, 0, 2, _))
+        ));
+    }
+
+    #[test]
+    fn regex_functions_extract_and_filter() {
+        let q = Query::new("<p>Price: $42.50</p><p>n/a</p><p>Price: $7 (was $9)</p>");
+        assert_eq!(
+            q.clone().query(r#"tag p > text @regex_capture,"\\$(\\d+)""#).texts(),
+            ["42", "7"]
+        );
+        assert_eq!(
+            q.clone().query(r#"tag p > text @regex_filter,"\\d" @regex_replace,"\\$(\\d+)","$1 USD""#).texts(),
+            ["Price: 42 USD.50", "Price: 7 USD (was 9 USD)"]
+        );
+        assert!(q.query(r#"tag p > text @regex_capture,"(a)",2"#).result().is_err());
+    }
+
+    #[test]
+    fn fallback_uses_right_side_below_count() {
+        let q = Query::new("<div class=a><p>a</p></div><div class=b><p>b1</p><p>b2</p></div>");
+        let a_or_b = |min: &str| {
+            let selector = format!("class a > tag p > text ||{} class b > tag p > text", min);
+            q.clone().query(&selector).texts()
+        };
+        assert_eq!(a_or_b(""), ["a"]);
+        assert_eq!(a_or_b("2"), ["b1", "b2"]);
+    }
+
+    #[test]
+    fn selector_diff_compares_stages() {
+        use crate::parser::{StageDiff, diff};
+
+        assert!(diff("class a>tag p > text", "class a > tag p > text").unwrap().is_empty());
+        let changes = diff("class a > tag div > tag p > text", "class a > tag p > text @trim").unwrap();
+        assert_eq!(
+            changes.changes().cloned().collect::<Vec<_>>(),
+            [
+                StageDiff::Removed("tag div".into()),
+                StageDiff::Changed { from: "text".into(), to: "text @trim".into() },
+            ]
+        );
+    }
+
+    #[test]
+    fn glob_matches_whole_values() {
+        let q = Query::new(r#"<p class="nav-main">a</p><p class="navbar">b</p><p class="nav.x">c</p>"#);
+        assert_eq!(q.clone().query(r#"class *"nav-*" > text"#).texts(), ["a"]);
+        assert_eq!(q.clone().query(r#"class *"nav.?" > text"#).texts(), ["c"]);
+
+        let q = Query::new("<p title=\"foo\nbar\">a</p>");
+        assert_eq!(q.query(r#"attr title *"foo*" > text"#).texts(), ["a"]);
+    }
+
+    #[test]
+    fn relational_selectors_filter_elements() {
+        let q = Query::new(r#"<div class="ad">a<img></div><div class="post">b<img></div><div>c</div>"#);
+        assert_eq!(q.clone().query("tag div > not(class ad) > text").texts(), ["b", "c"]);
+        assert_eq!(q.clone().query("tag div > not(class ad | class post) > text").texts(), ["c"]);
+        assert_eq!(q.query("tag div > has(tag img) > not(class ad) > text").texts(), ["b"]);
+    }
+
+    #[test]
+    fn sibling_steps_pair_labels_and_values() {
+        let q = Query::new("<dl><dt>Color</dt><dd>Red</dd><dt>Size</dt><dt>Alt</dt><dd>L</dd></dl>");
+        assert_eq!(q.clone().query("tag dt > next > text").texts(), ["Red", "Alt", "L"]);
+        assert_eq!(q.clone().query("tag dt > next tag dd > text").texts(), ["Red", "L", "L"]);
+        assert_eq!(q.query("tag dd > prev tag dt > text").texts(), ["Color", "Alt"]);
+    }
+
+    #[test]
+    fn structural_filters_use_position_in_parent() {
+        let q = Query::new("<table><tr><td>a</td><td>b</td><td>c</td></tr><tr><td>d</td><td>e</td></tr></table>");
+        assert_eq!(q.clone().query("tag td > first-child > text").texts(), ["a", "d"]);
+        assert_eq!(q.clone().query("tag td > last-child > text").texts(), ["c", "e"]);
+        assert_eq!(q.query("tag td > nth-child 2 > text").texts(), ["b", "e"]);
+
+        let q = Query::new("<div><p></p><p>a <b>b</b></p><p>c</p></div>");
+        assert_eq!(q.clone().query("tag p > empty").nodes().len(), 1);
+        assert_eq!(q.query("tag p > leaf > text").texts(), ["", "c"]);
+    }
+
+    #[test]
+    fn own_text_leaves_out_child_elements() {
+        let q = Query::new("<ul><li>Label <span>junk</span></li><li><b>x</b></li></ul>");
+        assert_eq!(q.query("tag li > owntext @trim").texts(), ["Label", ""]);
+    }
+
+    #[test]
+    fn value_reads_form_controls() {
+        let q = Query::new(r#"<form><input value="a"><input type="checkbox"><select><option>x</option><option selected>y</option></select><textarea>t</textarea></form>"#);
+        assert_eq!(q.query(r#"tag form > tag ~"input|select|textarea" > value"#).texts(), ["a", "on", "y", "t"]);
+    }
+
+    #[test]
+    fn data_lists_data_attributes() {
+        let q = Query::new(r#"<div class="p" data-id="7" data-kind="book"></div>"#);
+        assert_eq!(q.query("class p > data").texts(), ["id=7", "kind=book"]);
+    }
+
+    #[test]
+    fn text_nodes_can_be_selected_as_nodes() {
+        let q = Query::new("<div>\n <p>a <b>b</b> c</p>\n</div>");
+        assert_eq!(q.clone().query("tag div > textnode").nodes().len(), 3);
+        assert_eq!(q.query("tag p > textnode:-1 > text").texts(), [" c"]);
+    }
+
+    #[test]
+    fn depth_limit_skips_nested_matches() {
+        let q = Query::new("<ul><li>a</li><li>b<ul><li>c</li></ul></li></ul>");
+        assert_eq!(q.clone().query("tag ul:0 > tag li,1 > text").texts(), ["a", "bc"]);
+        assert_eq!(q.clone().query("tag ul:0 > tag ~\"^l.$\",1 > text").texts(), ["a", "bc"]);
+        assert_eq!(q.clone().query("tag ul:0 > role listitem,1 > text").texts(), ["a", "bc"]);
+        assert_eq!(q.query("tag ul:0 > tag li > text").texts(), ["a", "bc", "c"]);
+    }
+
+    #[test]
+    fn ancestor_steps_go_up_the_tree() {
+        let q = Query::new(r#"<div class="card"><a href="/p"><span><img></span><img></a></div>"#);
+        assert_eq!(q.clone().query("tag img > closest tag a > href").texts(), ["/p"]);
+        assert_eq!(q.clone().query("tag img > parent tag a > href").texts(), ["/p"]);
+        assert_eq!(q.clone().query("tag img > ancestors class card").nodes().len(), 1);
+        assert_eq!(q.query("tag a > child tag img").nodes().len(), 1);
+    }
+
+    #[test]
+    fn numeric_functions_convert_and_filter() {
+        let q = Query::new("<p>$1,299.99</p><p>1 299,50 €</p><p>3 left</p><p>sold out</p>");
+        assert_eq!(q.clone().query("tag p > text @to_int").texts(), ["1299", "1299", "3"]);
+        assert_eq!(q.clone().query("tag p > text @to_float").texts(), ["1299.99", "1299.5", "3"]);
+        assert_eq!(q.query("tag p > text @numeric @to_float").texts(), ["1299.99", "1299.5"]);
+
+        let q = Query::new("<p>5 EUR</p><p>5 ABC</p>");
+        assert_eq!(q.query("tag p > text @numeric").texts(), ["5 EUR"]);
+    }
+
+    #[test]
+    fn extract_functions_find_all_matches() {
+        let q = Query::new("<p>Call 555-1234 or mail a.b@example.org (see https://example.org/x).</p>");
+        assert_eq!(q.clone().query("tag p > text @extract_numbers").texts(), ["555", "1234"]);
+        assert_eq!(q.clone().query("tag p > text @extract_emails").texts(), ["a.b@example.org"]);
+        assert_eq!(q.query("tag p > text @extract_urls").texts(), ["https://example.org/x"]);
+    }
+
+    #[test]
+    fn texts_with_nodes_keep_their_elements() {
+        let q = Query::new("<p id=a>x</p><p id=b>y</p>");
+        let pairs = q.texts_with_nodes("tag p > text @uppercase").unwrap();
+        let ids: Vec<_> = pairs.iter().map(|(text, node)| (text.as_str(), node.attrs()[0].value.as_str())).collect();
+        assert_eq!(ids, [("X", "a"), ("Y", "b")]);
+    }
+
+    #[test]
+    fn aggregate_functions_reduce_texts() {
+        let q = Query::new("<p>$10</p><p>2.5</p><p>none</p>");
+        let run = |f: &str| q.clone().query(&format!("tag p > text @{}", f)).texts();
+        assert_eq!(run("count"), ["3"]);
+        assert_eq!(run("sum"), ["12.5"]);
+        assert_eq!(run("min"), ["2.5"]);
+        assert_eq!(run("avg"), ["6.25"]);
+        assert_eq!(q.clone().query("tag p @count").texts(), ["3"]);
+        assert_eq!(run("sort_numeric,\"desc\""), ["$10", "2.5", "none"]);
+    }
+
+    #[test]
+    fn page_class_detects_junk_pages() {
+        use crate::extract::page_kind::PageKind;
+
+        let kind = |html: &str| Document::parse(html).unwrap().page_class().kind;
+        assert_eq!(kind("<title>404 Not Found</title><p>Sorry.</p>"), PageKind::Error);
+        assert_eq!(kind("<title>Sign in</title><input type=password>"), PageKind::Login);
+        let article = format!("<title>Errors</title><p>{}</p>", "word ".repeat(100));
+        assert_eq!(kind(&article), PageKind::Content);
+        let article = format!("<title>Bots</title><p>A captcha {}</p>", "word ".repeat(800));
+        assert_eq!(kind(&article), PageKind::Content);
+    }
+
+    #[test]
+    fn relative_dates_resolve_against_reference() {
+        let q = Query::new("<p>2 weeks ago</p><p>99999999999999 weeks ago</p>");
+        let dates = q.query(r#"tag p > text @parse_relative_date,"2025-04-29T12:00:00Z""#).texts();
+        assert_eq!(dates, ["2025-04-15T12:00:00Z", "99999999999999 weeks ago"]);
+    }
+
+    #[test]
+    #[cfg(feature = "chrono")]
+    fn parse_date_keeps_text_missing_output_fields() {
+        let q = Query::new("<p>29 Apr 2025</p>");
+        let parse = |selector: &str| q.clone().query(selector).texts();
+        assert_eq!(parse(r#"tag p > text @parse_date,"%d %b %Y""#), ["2025-04-29"]);
+        assert_eq!(parse(r#"tag p > text @parse_date,"%d %b %Y","%Y-%m-%dT%H:%M""#), ["29 Apr 2025"]);
+        assert_eq!(parse(r#"tag p > text @parse_date,"%d %b %Y","%z""#), ["29 Apr 2025"]);
+    }
+
+    #[test]
+    fn texts_with_nodes_pair_set_operands() {
+        let q = Query::new("<p>a</p><span>b</span>");
+        let tags: Vec<_> = q
+            .texts_with_nodes("tag p > text | tag span > text")
+            .unwrap()
+            .into_iter()
+            .map(|(text, node)| (text, node.tag_name().map(str::to_string)))
+            .collect();
+        assert_eq!(tags, [("a".to_string(), Some("p".to_string())), ("b".to_string(), Some("span".to_string()))]);
+    }
+
+    #[test]
+    fn write_ndjson_writes_a_line_per_item() {
+        let q = Query::new(r#"<a href="/a">A</a><a href="/b">B</a>"#);
+        let mut out = Vec::new();
+        assert_eq!(q.write_ndjson("tag a > href", &mut out).unwrap(), 2);
+        assert_eq!(String::from_utf8(out).unwrap(), "{\"text\":\"/a\"}\n{\"text\":\"/b\"}\n");
+    }
+
+    #[test]
+    fn selector_words_are_not_reserved() {
+        let q = Query::new(r#"<p class="lang role" lang="en">a</p><nav class="role"></nav>"#);
+        assert_eq!(q.clone().query("class lang > text").texts(), ["a"]);
+        assert_eq!(q.clone().query("class role > role navigation").nodes().len(), 1);
+        assert_eq!(q.query("tag p > lang en > text").texts(), ["a"]);
+        assert_eq!(Query::new(r#"<p class="owntext">a<b>b</b></p>"#).query("class owntext > owntext").texts(), ["a"]);
+        assert!(Query::new(r#"<p class="tagpath">a</p>"#).query("class tagpath > tagpath").text().unwrap().ends_with("p"));
+        assert_eq!(Query::new(r#"<nav class="links"><a href="/x">x</a></nav>"#).query("class links > links").texts(), ["x\t/x"]);
+        assert_eq!(Query::new(r#"<p class="markdown">a</p>"#).query("class markdown > markdown").texts(), ["a"]);
+    }
+
+    #[test]
+    fn markdown_renders_structure() {
+        let html = r#"<div><h2>Intro</h2><p>See <a href="/docs">the <em>docs</em></a>.</p>
+            <ul><li>one</li><li><code>two</code></li></ul><script>x()</script></div>"#;
+        let markdown = Query::new(html).query("tag div > markdown").texts();
+        assert_eq!(
+            markdown,
+            vec!["## Intro\n\nSee [the *docs*](/docs).\n\n- one\n- `two`"]
+        );
+    }
+}
@@ -0,0 +1,189 @@
+// metadata.rs - Document metadata extraction
+//
+// Collects the page title, description and canonical URL together with the OpenGraph
+// (`og:*`) and Twitter card (`twitter:*`) properties declared in `<meta>` tags.
+
+use super::interpreter::html;
+use super::NodeHandle;
+
+/// Metadata declared by a document
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metadata {
+    /// Text of the first `<title>` element, trimmed
+    pub title: Option<String>,
+
+    /// `<meta name="description">`
+    pub description: Option<String>,
+
+    /// `<link rel="canonical">`
+    pub canonical: Option<String>,
+
+    /// OpenGraph properties
+    pub open_graph: OpenGraph,
+
+    /// Twitter card properties
+    pub twitter: TwitterCard,
+}
+
+/// OpenGraph properties (`<meta property="og:...">`)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OpenGraph {
+    /// `og:title`
+    pub title: Option<String>,
+
+    /// `og:type`
+    pub kind: Option<String>,
+
+    /// `og:url`
+    pub url: Option<String>,
+
+    /// `og:image`, the first one if several are declared
+    pub image: Option<String>,
+
+    /// `og:description`
+    pub description: Option<String>,
+
+    /// `og:site_name`
+    pub site_name: Option<String>,
+
+    /// Every `og:*` property in source order, with the prefix removed, including
+    /// repeated and structured ones such as `image:width`
+    pub properties: Vec<(String, String)>,
+}
+
+/// Twitter card properties (`<meta name="twitter:...">`)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TwitterCard {
+    /// `twitter:card`
+    pub card: Option<String>,
+
+    /// `twitter:site`
+    pub site: Option<String>,
+
+    /// `twitter:creator`
+    pub creator: Option<String>,
+
+    /// `twitter:title`
+    pub title: Option<String>,
+
+    /// `twitter:description`
+    pub description: Option<String>,
+
+    /// `twitter:image`
+    pub image: Option<String>,
+
+    /// Every `twitter:*` property in source order, with the prefix removed
+    pub properties: Vec<(String, String)>,
+}
+
+/// Extract the metadata of the document `root` belongs to
+pub fn extract(root: &NodeHandle) -> Metadata {
+    let dom = root.dom();
+    let root = root.root();
+    let elements = |tag: &str| {
+        dom.elements_by_tag(tag, root.index())
+            .iter()
+            .map(|index| NodeHandle::new(dom.clone(), *index))
+            .collect::<Vec<_>>()
+    };
+
+    let mut metadata = Metadata {
+        // `<title>` inside SVG is an accessible name, not the document title
+        title: elements("title")
+            .iter()
+            .find(|node| !in_svg(node))
+            .and_then(|node| html::extract_text(node).ok())
+            .map(|text| text.trim().to_string()),
+        ..Default::default()
+    };
+
+    for link in elements("link") {
+        let is_canonical = attr(&link, "rel").is_some_and(|rel| {
+            rel.split_whitespace()
+                .any(|r| r.eq_ignore_ascii_case("canonical"))
+        });
+        if is_canonical && metadata.canonical.is_none() {
+            metadata.canonical = attr(&link, "href").map(|href| href.trim().to_string());
+        }
+    }
+
+    for meta in elements("meta") {
+        // OpenGraph uses `property`, Twitter `name`, but pages mix them up
+        let Some(key) = attr(&meta, "property").or_else(|| attr(&meta, "name")) else {
+            continue;
+        };
+        let Some(content) = attr(&meta, "content") else {
+            continue;
+        };
+        let key = key.trim().to_ascii_lowercase();
+        let content = content.trim().to_string();
+
+        if key == "description" {
+            metadata.description.get_or_insert(content);
+        } else if let Some(property) = key.strip_prefix("og:") {
+            metadata.open_graph.add(property, content);
+        } else if let Some(property) = key.strip_prefix("twitter:") {
+            metadata.twitter.add(property, content);
+        }
+    }
+
+    metadata
+}
+
+impl OpenGraph {
+    fn add(&mut self, property: &str, content: String) {
+        let field = match property {
+            "title" => &mut self.title,
+            "type" => &mut self.kind,
+            "url" => &mut self.url,
+            "image" | "image:url" => &mut self.image,
+            "description" => &mut self.description,
+            "site_name" => &mut self.site_name,
+            _ => {
+                self.properties.push((property.to_string(), content));
+                return;
+            }
+        };
+
+        field.get_or_insert_with(|| content.clone());
+        self.properties.push((property.to_string(), content));
+    }
+}
+
+impl TwitterCard {
+    fn add(&mut self, property: &str, content: String) {
+        let field = match property {
+            "card" => &mut self.card,
+            "site" => &mut self.site,
+            "creator" => &mut self.creator,
+            "title" => &mut self.title,
+            "description" => &mut self.description,
+            "image" | "image:src" => &mut self.image,
+            _ => {
+                self.properties.push((property.to_string(), content));
+                return;
+            }
+        };
+
+        field.get_or_insert_with(|| content.clone());
+        self.properties.push((property.to_string(), content));
+    }
+}
+
+fn in_svg(node: &NodeHandle) -> bool {
+    let mut current = node.parent();
+    while let Some(node) = current {
+        if node.tag_name() == Some("svg") {
+            return true;
+        }
+        current = node.parent();
+    }
+    false
+}
+
+fn attr<'a>(node: &'a NodeHandle, name: &str) -> Option<&'a str> {
+    node.attrs()
+        .iter()
+        .find(|a| a.name == name)
+        .map(|a| a.value.as_str())
+}
@@ -1,7 +1,18 @@
+// parser/ast.rs - Syntax tree of selectors
+//
+// The tree is public so that tools such as formatters, linters and code generators
+// can be built on the parser. Enums are `#[non_exhaustive]` and `FunctionNode` is
+// built with `FunctionNode::new`, so new grammar can be added in minor releases;
+// matches outside this crate need a wildcard arm. Source positions are kept beside the
+// tree, in a `SpanTable`, rather than in the nodes, so that trees compare equal and
+// cache alike regardless of formatting.
+
+use std::collections::HashMap;
 use std::fmt;
 
 /// Top-level node type.
 #[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
 pub enum Node {
     /// Selector node
     Selector(Box<SelectorNode>),
@@ -21,6 +32,7 @@ pub enum Node {
 
 /// Selector node type
 #[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
 pub enum SelectorNode {
     /// Element query selector
     ElementSelector(ElementNode),
@@ -31,6 +43,7 @@ pub enum SelectorNode {
 
 /// Element query node
 #[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
 pub enum ElementNode {
     /// Class selector: parameter, whether it's a regex
     Class(String, bool),
@@ -43,26 +56,108 @@ pub enum ElementNode {
 
     /// Attribute selector: attribute name, attribute value (optional), whether it's a regex
     Attr(String, Option<String>, bool),
+
+    /// Language filter: language tag, whether it's a regex
+    Lang(String, bool),
+
+    /// ARIA role selector (explicit or implicit role): role name, whether it's a regex
+    Role(String, bool),
+
+    /// Keep current elements the expression does not match, evaluated from each
+    /// element: `not(class ad)`
+    Not(Box<Node>),
+
+    /// Keep current elements containing a descendant the expression matches, evaluated
+    /// from each element: `has(tag img)`
+    Has(Box<Node>),
+
+    /// Move to the next sibling element, or the nearest following one that matches the
+    /// selector: `next tag dd`
+    Next(Option<Box<ElementNode>>),
+
+    /// Move to the previous sibling element, or the nearest preceding one that matches
+    /// the selector: `prev tag dt`
+    Prev(Option<Box<ElementNode>>),
+
+    /// Move to the child elements, or those matching the selector: `child tag li`
+    Child(Option<Box<ElementNode>>),
+
+    /// Move to the parent element, if it matches the selector: `parent tag a`
+    Parent(Option<Box<ElementNode>>),
+
+    /// Move to all ancestor elements, or those matching the selector: `ancestors tag div`
+    Ancestors(Option<Box<ElementNode>>),
+
+    /// Move to the nearest element matching the selector, starting from the element
+    /// itself and going up: `closest tag a`
+    Closest(Box<ElementNode>),
+
+    /// Keep current elements that are the first element child of their parent
+    FirstChild,
+
+    /// Keep current elements that are the last element child of their parent
+    LastChild,
+
+    /// Keep current elements that are the nth element child of their parent, counting
+    /// from 1: `nth-child 2`
+    NthChild(usize),
+
+    /// Keep current elements without any child nodes, not even text
+    Empty,
+
+    /// Keep current elements without element children
+    Leaf,
+
+    /// Text nodes within the current elements, leaving out whitespace-only ones
+    TextNodes,
+
+    /// Element selector matching at most the given number of levels below each current
+    /// element, which is level 0: `tag div,2`
+    Depth(Box<ElementNode>, usize),
 }
 
 /// Text query node
 #[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
 pub enum TextNode {
     /// Element text content
     Text,
 
+    /// Text of the element's own text nodes, leaving out the text of child elements
+    OwnText,
+
     /// href attribute value
     Href,
 
     /// src attribute value
     Src,
 
+    /// Effective value of form controls: the value of inputs, the selected options of
+    /// selects and the text of textareas
+    Value,
+
+    /// Every `data-*` attribute, as `name=value` without the `data-` prefix
+    Data,
+
+    /// Subtree rendered as Markdown
+    Markdown,
+
+    /// Text and href of each link within the subtree, separated by a tab
+    Links,
+
+    /// Tag names from the root element down to the node, separated by `/`
+    TagPath,
+
     /// attribute value
     AttrValue(String, bool),
+
+    /// "true" or "false" for the presence of an attribute
+    AttrPresent(String, bool),
 }
 
 /// Set operation node
 #[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
 pub enum SetOperationNode {
     /// Union: left operand | right operand
     Union(Box<Node>, Box<Node>),
@@ -72,10 +167,15 @@ pub enum SetOperationNode {
 
     /// Difference: left operand ^ right operand
     Difference(Box<Node>, Box<Node>),
+
+    /// Fallback: left operand ||N right operand, the left results unless there are
+    /// fewer than N of them (1 if omitted), then the right results
+    Fallback(Box<Node>, Box<Node>, usize),
 }
 
 /// Index selection node
 #[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
 pub enum IndexNode {
     /// Single index: index value
     Single(Literal),
@@ -89,6 +189,7 @@ pub enum IndexNode {
 
 /// Function node
 #[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
 pub struct FunctionNode {
     /// Function name
     pub name: String,
@@ -97,7 +198,9 @@ pub struct FunctionNode {
     pub arguments: Vec<Literal>,
 }
 
+/// Argument of a function or bound of an index
 #[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
 pub enum Literal {
     Int(i64),
     Str(String),
@@ -125,6 +228,185 @@ impl fmt::Display for Literal {
     }
 }
 
+/// Source range of a node: the position (line, column) of its first token and the
+/// position just past its last token
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Span {
+    pub start: (usize, usize),
+    pub end: (usize, usize),
+}
+
+/// Spans of the nodes of a parsed tree, looked up by node identity.
+///
+/// Lookups are only meaningful for the tree the table was built from, and the root
+/// must not have been moved since.
+#[derive(Debug, Clone, Default)]
+pub struct SpanTable {
+    spans: HashMap<usize, Span>,
+}
+
+impl FunctionNode {
+    pub fn new(name: &str, arguments: Vec<Literal>) -> Self {
+        FunctionNode {
+            name: name.to_string(),
+            arguments,
+        }
+    }
+}
+
+/// A parsed tree together with the spans of its nodes. The root is boxed and the tree
+/// cannot be changed through this type, so the spans stay valid when it is moved.
+#[derive(Debug)]
+pub struct SpannedNode {
+    root: Box<Node>,
+    spans: SpanTable,
+}
+
+impl SpannedNode {
+    pub(crate) fn new(root: Box<Node>, spans: &[Span]) -> Self {
+        let spans = SpanTable::new(&root, spans);
+        SpannedNode { root, spans }
+    }
+
+    /// Root of the tree
+    pub fn root(&self) -> &Node {
+        &self.root
+    }
+
+    /// Span of a node of the tree, found through `root`
+    pub fn span(&self, node: &Node) -> Option<Span> {
+        self.spans.span(node)
+    }
+
+    /// Spans of every node of the tree
+    pub fn spans(&self) -> &SpanTable {
+        &self.spans
+    }
+
+    /// Take the tree, dropping the spans
+    pub fn into_node(self) -> Node {
+        *self.root
+    }
+}
+
+impl Clone for SpannedNode {
+    fn clone(&self) -> Self {
+        // Spans are keyed by node address, so they are paired again with the copy
+        let mut nodes = Vec::new();
+        collect_post_order(&self.root, &mut nodes);
+        let spans: Vec<Span> = nodes
+            .into_iter()
+            .map(|node| self.spans.span(node).unwrap_or_default())
+            .collect();
+
+        SpannedNode::new(self.root.clone(), &spans)
+    }
+}
+
+impl SpanTable {
+    /// Pair the nodes of a tree with spans recorded in the order the parser built them,
+    /// which is children before parents
+    pub(crate) fn new(root: &Node, spans: &[Span]) -> Self {
+        let mut nodes = Vec::new();
+        collect_post_order(root, &mut nodes);
+
+        SpanTable {
+            spans: nodes
+                .into_iter()
+                .zip(spans.iter().copied())
+                .map(|(node, span)| (node as *const Node as usize, span))
+                .collect(),
+        }
+    }
+
+    /// Span of a node of the tree
+    pub fn span(&self, node: &Node) -> Option<Span> {
+        self.spans.get(&(node as *const Node as usize)).copied()
+    }
+}
+
+fn collect_post_order<'a>(node: &'a Node, nodes: &mut Vec<&'a Node>) {
+    for child in node.children() {
+        collect_post_order(child, nodes);
+    }
+    nodes.push(node);
+}
+
+/// Visit every node of a tree, parents before children
+pub fn walk<'a>(node: &'a Node, f: &mut dyn FnMut(&'a Node)) {
+    f(node);
+    for child in node.children() {
+        walk(child, f);
+    }
+}
+
+impl Node {
+    /// Element selector, e.g. `tag p`
+    pub fn element(element: ElementNode) -> Self {
+        Node::Selector(Box::new(SelectorNode::ElementSelector(element)))
+    }
+
+    /// Text selector, e.g. `text`
+    pub fn text(text: TextNode) -> Self {
+        Node::Selector(Box::new(SelectorNode::TextSelector(text)))
+    }
+
+    /// `left > right`
+    pub fn pipeline(left: Node, right: Node) -> Self {
+        Node::Pipeline(Box::new(left), Box::new(right))
+    }
+
+    /// Set operation, e.g. `left | right`
+    pub fn set_operation(op: SetOperationNode) -> Self {
+        Node::SetOperation(Box::new(op))
+    }
+
+    /// `node:index`
+    pub fn index(node: Node, index: IndexNode) -> Self {
+        Node::IndexSelection(Box::new(node), Box::new(index))
+    }
+
+    /// `node @function`
+    pub fn function_call(node: Node, function: FunctionNode) -> Self {
+        Node::FunctionCall(Box::new(node), function)
+    }
+
+    /// Direct child nodes, left to right
+    pub fn children(&self) -> Vec<&Node> {
+        match self {
+            Node::Selector(selector) => match selector.as_ref() {
+                SelectorNode::ElementSelector(element) => element.expressions(),
+                SelectorNode::TextSelector(_) => Vec::new(),
+            },
+            Node::Pipeline(left, right) => vec![left, right],
+            Node::SetOperation(op) => match op.as_ref() {
+                SetOperationNode::Union(left, right)
+                | SetOperationNode::Intersection(left, right)
+                | SetOperationNode::Difference(left, right)
+                | SetOperationNode::Fallback(left, right, _) => vec![left, right],
+            },
+            Node::IndexSelection(inner, _) | Node::FunctionCall(inner, _) => vec![inner],
+        }
+    }
+}
+
+impl ElementNode {
+    /// Expressions nested in the selector, such as the one of `not(...)`
+    pub fn expressions(&self) -> Vec<&Node> {
+        match self {
+            ElementNode::Not(inner) | ElementNode::Has(inner) => vec![inner],
+            ElementNode::Next(Some(filter))
+            | ElementNode::Prev(Some(filter))
+            | ElementNode::Child(Some(filter))
+            | ElementNode::Parent(Some(filter))
+            | ElementNode::Ancestors(Some(filter))
+            | ElementNode::Closest(filter)
+            | ElementNode::Depth(filter, _) => filter.expressions(),
+            _ => Vec::new(),
+        }
+    }
+}
+
 // Implementing the Display trait for debugging and error reporting
 impl fmt::Display for Node {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -171,6 +453,40 @@ impl fmt::Display for ElementNode {
                     write!(f, "tag {}", value)
                 }
             }
+            ElementNode::Lang(value, is_regex) => {
+                if *is_regex {
+                    write!(f, "lang ~{}", value)
+                } else {
+                    write!(f, "lang {}", value)
+                }
+            }
+            ElementNode::Role(value, is_regex) => {
+                if *is_regex {
+                    write!(f, "role ~{}", value)
+                } else {
+                    write!(f, "role {}", value)
+                }
+            }
+            ElementNode::Not(inner) => write!(f, "not({})", inner),
+            ElementNode::Has(inner) => write!(f, "has({})", inner),
+            ElementNode::Next(None) => write!(f, "next"),
+            ElementNode::Next(Some(filter)) => write!(f, "next {}", filter),
+            ElementNode::Prev(None) => write!(f, "prev"),
+            ElementNode::Prev(Some(filter)) => write!(f, "prev {}", filter),
+            ElementNode::Child(None) => write!(f, "child"),
+            ElementNode::Child(Some(filter)) => write!(f, "child {}", filter),
+            ElementNode::Parent(None) => write!(f, "parent"),
+            ElementNode::Parent(Some(filter)) => write!(f, "parent {}", filter),
+            ElementNode::Ancestors(None) => write!(f, "ancestors"),
+            ElementNode::Ancestors(Some(filter)) => write!(f, "ancestors {}", filter),
+            ElementNode::Closest(filter) => write!(f, "closest {}", filter),
+            ElementNode::FirstChild => write!(f, "first-child"),
+            ElementNode::LastChild => write!(f, "last-child"),
+            ElementNode::NthChild(n) => write!(f, "nth-child {}", n),
+            ElementNode::Empty => write!(f, "empty"),
+            ElementNode::Leaf => write!(f, "leaf"),
+            ElementNode::TextNodes => write!(f, "textnode"),
+            ElementNode::Depth(inner, depth) => write!(f, "{},{}", inner, depth),
             ElementNode::Attr(value, attr_value, is_regex) => {
                 if let Some(attr_value) = attr_value {
                     if *is_regex {
@@ -194,8 +510,14 @@ impl fmt::Display for TextNode {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             TextNode::Text => write!(f, "text"),
+            TextNode::OwnText => write!(f, "owntext"),
             TextNode::Href => write!(f, "href"),
             TextNode::Src => write!(f, "src"),
+            TextNode::Value => write!(f, "value"),
+            TextNode::Data => write!(f, "data"),
+            TextNode::Markdown => write!(f, "markdown"),
+            TextNode::Links => write!(f, "links"),
+            TextNode::TagPath => write!(f, "tagpath"),
             TextNode::AttrValue(name, is_regex) => {
                 if *is_regex {
                     write!(f, "#~{}", name)
@@ -203,6 +525,13 @@ impl fmt::Display for TextNode {
                     write!(f, "#{}", name)
                 }
             }
+            TextNode::AttrPresent(name, is_regex) => {
+                if *is_regex {
+                    write!(f, "?~{}", name)
+                } else {
+                    write!(f, "?{}", name)
+                }
+            }
         }
     }
 }
@@ -213,6 +542,10 @@ impl fmt::Display for SetOperationNode {
             SetOperationNode::Union(left, right) => write!(f, "{} | {}", left, right),
             SetOperationNode::Intersection(left, right) => write!(f, "{} & {}", left, right),
             SetOperationNode::Difference(left, right) => write!(f, "{} ^ {}", left, right),
+            SetOperationNode::Fallback(left, right, 1) => write!(f, "{} || {}", left, right),
+            SetOperationNode::Fallback(left, right, min) => {
+                write!(f, "{} ||{} {}", left, min, right)
+            }
         }
     }
 }
@@ -1,29 +1,31 @@
 use crate::Token;
 
 use super::{Node, ParseError, Parser};
-use super::element::parse_element;
+use super::element::{is_element_start, parse_element};
 use super::set::parse_set;
-use super::text::parse_text;
+use super::text::{is_text_start, parse_text};
 use super::index::parse_index;
 use super::function::parse_function;
 
 
 /// Parse basic selector expression
 pub fn parse_basic(it: &mut Parser) -> Result<Node, ParseError> {
+    let start = it.get_current_position();
+
     match &it.current_token {
-        Some((Token::Class, _, _))
-        | Some((Token::Id, _, _))
-        | Some((Token::Tag, _, _))
-        | Some((Token::Attr, _, _)) => {
+        Some((token, _, _)) if is_element_start(token) => {
 
             let selector = parse_element(it)?;
             let node = Node::Selector(Box::new(selector));
+            it.record_span(start);
 
-            parse_index(it, node)
+            let node = parse_index(it, node)?;
+            parse_function(it, node)
         }
-        Some((Token::Text, _, _)) | Some((Token::Href, _, _)) | Some((Token::Src, _, _)) | Some((Token::Pound, _, _)) => {
+        Some((token, _, _)) if is_text_start(token) => {
             let selector = parse_text(it)?;
             let node = Node::Selector(Box::new(selector));
+            it.record_span(start);
 
             let node = parse_index(it, node)?;
             parse_function(it, node)
@@ -0,0 +1,150 @@
+// parser/diff.rs - Structural comparison of selectors
+//
+// Compares two selectors by their ASTs rather than their text, so reformatting does not
+// show up as a change. Each selector is split into the stages of its pipeline (a
+// selector with its index and functions, or a parenthesized set operation), and the two
+// stage lists are aligned on their longest common subsequence. A stage removed and
+// another added at the same place are reported as one changed stage.
+
+use std::fmt;
+
+use super::{Node, ParseError, parse};
+
+/// One stage of the aligned pipelines
+#[derive(Debug, Clone, PartialEq)]
+pub enum StageDiff {
+    /// Stage present in both selectors
+    Unchanged(String),
+
+    /// Stage only in the new selector
+    Added(String),
+
+    /// Stage only in the old selector
+    Removed(String),
+
+    /// Stage replaced by another one
+    Changed { from: String, to: String },
+}
+
+/// Differences between two selectors, stage by stage
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SelectorDiff {
+    pub stages: Vec<StageDiff>,
+}
+
+impl SelectorDiff {
+    /// Check if the selectors have the same structure
+    pub fn is_empty(&self) -> bool {
+        self.stages
+            .iter()
+            .all(|stage| matches!(stage, StageDiff::Unchanged(_)))
+    }
+
+    /// Stages that differ
+    pub fn changes(&self) -> impl Iterator<Item = &StageDiff> {
+        self.stages
+            .iter()
+            .filter(|stage| !matches!(stage, StageDiff::Unchanged(_)))
+    }
+}
+
+/// Parse two selectors and compare their structure.
+pub fn diff(old: &str, new: &str) -> Result<SelectorDiff, ParseError> {
+    Ok(diff_nodes(&parse(old)?, &parse(new)?))
+}
+
+/// Compare the structure of two parsed selectors.
+pub fn diff_nodes(old: &Node, new: &Node) -> SelectorDiff {
+    let mut old_stages = Vec::new();
+    let mut new_stages = Vec::new();
+    stages(old, &mut old_stages);
+    stages(new, &mut new_stages);
+
+    // lengths[i][j]: longest common subsequence of old_stages[i..] and new_stages[j..]
+    let mut lengths = vec![vec![0usize; new_stages.len() + 1]; old_stages.len() + 1];
+    for i in (0..old_stages.len()).rev() {
+        for j in (0..new_stages.len()).rev() {
+            lengths[i][j] = if old_stages[i] == new_stages[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut result = Vec::new();
+    let mut removed = Vec::new();
+    let mut added = Vec::new();
+    let (mut i, mut j) = (0, 0);
+
+    while i < old_stages.len() || j < new_stages.len() {
+        if i < old_stages.len() && j < new_stages.len() && old_stages[i] == new_stages[j] {
+            flush(&mut removed, &mut added, &mut result);
+            result.push(StageDiff::Unchanged(old_stages[i].clone()));
+            i += 1;
+            j += 1;
+        } else if j < new_stages.len()
+            && (i == old_stages.len() || lengths[i][j + 1] >= lengths[i + 1][j])
+        {
+            added.push(new_stages[j].clone());
+            j += 1;
+        } else {
+            removed.push(old_stages[i].clone());
+            i += 1;
+        }
+    }
+    flush(&mut removed, &mut added, &mut result);
+
+    SelectorDiff { stages: result }
+}
+
+/// Split a pipeline into the text of its stages
+fn stages(node: &Node, out: &mut Vec<String>) {
+    match node {
+        Node::Pipeline(left, right) => {
+            stages(left, out);
+            stages(right, out);
+        }
+        // Parenthesized, as the operands are pipelines themselves
+        Node::SetOperation(op) => out.push(format!("({})", op)),
+        node => out.push(node.to_string()),
+    }
+}
+
+/// Pair up the stages removed and added between two unchanged ones
+fn flush(removed: &mut Vec<String>, added: &mut Vec<String>, out: &mut Vec<StageDiff>) {
+    let mut removed = removed.drain(..);
+    let mut added = added.drain(..);
+
+    loop {
+        match (removed.next(), added.next()) {
+            (Some(from), Some(to)) => out.push(StageDiff::Changed { from, to }),
+            (Some(from), None) => out.push(StageDiff::Removed(from)),
+            (None, Some(to)) => out.push(StageDiff::Added(to)),
+            (None, None) => break,
+        }
+    }
+}
+
+impl fmt::Display for StageDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StageDiff::Unchanged(stage) => write!(f, "  {}", stage),
+            StageDiff::Added(stage) => write!(f, "+ {}", stage),
+            StageDiff::Removed(stage) => write!(f, "- {}", stage),
+            StageDiff::Changed { from, to } => write!(f, "~ {} => {}", from, to),
+        }
+    }
+}
+
+impl fmt::Display for SelectorDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, stage) in self.stages.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", stage)?;
+        }
+        Ok(())
+    }
+}
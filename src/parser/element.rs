@@ -1,32 +1,86 @@
 use crate::lexer::Token;
 use crate::parser::ast::{ElementNode, SelectorNode};
-use crate::parser::error::ParseError;
+use crate::parser::error::{ParseError, ParseErrorKind};
 use super::Parser;
+use super::set::parse_set;
+
+/// Words that start a navigation step when they begin a selector. They are not
+/// reserved, so `class next` still selects the class `next`.
+const NAVIGATION_KEYWORDS: &[&str] = &["next", "prev", "child", "parent", "ancestors", "closest"];
+
+/// Words that start a structural filter, likewise not reserved
+const STRUCTURAL_KEYWORDS: &[&str] = &["first-child", "last-child", "nth-child", "empty", "leaf"];
+
+/// Word of the text node selector, likewise not reserved
+const TEXT_NODE_KEYWORD: &str = "textnode";
+
+/// Words of selectors taking a value, likewise not reserved, so `class lang` still
+/// selects the class `lang`
+const SELECTOR_KEYWORDS: &[&str] = &["lang", "role"];
+
+/// Check whether a token starts an element selector
+pub fn is_element_start(token: &Token) -> bool {
+    match token {
+        Token::Class | Token::Id | Token::Tag | Token::Attr => true,
+        Token::Not | Token::Has => true,
+        Token::String(word) => {
+            NAVIGATION_KEYWORDS.contains(&word.as_str())
+                || STRUCTURAL_KEYWORDS.contains(&word.as_str())
+                || word == TEXT_NODE_KEYWORD
+                || SELECTOR_KEYWORDS.contains(&word.as_str())
+        }
+        _ => false,
+    }
+}
 
 /// Parse element selector
 pub fn parse_element(it: &mut Parser) -> Result<SelectorNode, ParseError> {
     match &it.current_token {
         Some((Token::Class, _, _)) => {
             let element_node = parse_class_selector(it)?;
-            Ok(SelectorNode::ElementSelector(element_node))
+            Ok(SelectorNode::ElementSelector(parse_depth_limit(it, element_node)?))
         }
         Some((Token::Id, _, _)) => {
             let element_node = parse_id_selector(it)?;
-            Ok(SelectorNode::ElementSelector(element_node))
+            Ok(SelectorNode::ElementSelector(parse_depth_limit(it, element_node)?))
         }
         Some((Token::Tag, _, _)) => {
             let element_node = parse_tag_selector(it)?;
-            Ok(SelectorNode::ElementSelector(element_node))
+            Ok(SelectorNode::ElementSelector(parse_depth_limit(it, element_node)?))
         }
         Some((Token::Attr, _, _)) => {
             let element_node = parse_attr_selector(it)?;
+            Ok(SelectorNode::ElementSelector(parse_depth_limit(it, element_node)?))
+        }
+        Some((Token::String(word), _, _)) if word == "lang" => {
+            let element_node = parse_lang_selector(it)?;
+            Ok(SelectorNode::ElementSelector(element_node))
+        }
+        Some((Token::String(word), _, _)) if word == "role" => {
+            let element_node = parse_role_selector(it)?;
+            Ok(SelectorNode::ElementSelector(parse_depth_limit(it, element_node)?))
+        }
+        Some((Token::Not, _, _)) | Some((Token::Has, _, _)) => {
+            let element_node = parse_relational_selector(it)?;
+            Ok(SelectorNode::ElementSelector(element_node))
+        }
+        Some((Token::String(word), _, _)) if NAVIGATION_KEYWORDS.contains(&word.as_str()) => {
+            let element_node = parse_navigation_step(it)?;
+            Ok(SelectorNode::ElementSelector(element_node))
+        }
+        Some((Token::String(word), _, _)) if STRUCTURAL_KEYWORDS.contains(&word.as_str()) => {
+            let element_node = parse_structural_filter(it)?;
+            Ok(SelectorNode::ElementSelector(element_node))
+        }
+        Some((Token::String(word), _, _)) if word == TEXT_NODE_KEYWORD => {
+            let element_node = parse_text_node_selector(it)?;
             Ok(SelectorNode::ElementSelector(element_node))
         }
         _ => {
             let (line, column) = it.get_current_position();
             let current = it.get_current_token_str();
             Err(ParseError::unexpected_token(
-                "class, id, tag, or attr",
+                "element selector",
                 &current,
                 line,
                 column,
@@ -91,14 +145,190 @@ fn parse_attr_selector(it: &mut Parser) -> Result<ElementNode, ParseError> {
         return Err(ParseError::invalid_selector_value("empty attribute name", line, column));
     }
 
+    let (line, column) = it.get_current_position();
     let (is_regex, attr_value) = match parse_selector_value(it) {
         Ok((is_regex, attr_value)) => (is_regex, Some(attr_value)),
+        Err(e) if e.kind == ParseErrorKind::InvalidRegex => return Err(e),
         Err(_) => (false, None)
     };
 
+    // The regex flag of the value applies to the attribute name as well
+    if is_regex {
+        it.validate_regex(&attr_name, line, column)?;
+    }
+
     Ok(ElementNode::Attr(attr_name, attr_value, is_regex))
 }
 
+/// Parse language selector (lang)
+fn parse_lang_selector(it: &mut Parser) -> Result<ElementNode, ParseError> {
+
+    it.read_token(); // Consume keyword
+
+    let (is_regex, value) = parse_selector_value(it)?;
+
+    if value.is_empty() {
+        let (line, column) = it.get_current_position();
+        return Err(ParseError::invalid_selector_value("empty value", line, column));
+    }
+
+    Ok(ElementNode::Lang(value, is_regex))
+}
+
+/// Parse ARIA role selector (role)
+fn parse_role_selector(it: &mut Parser) -> Result<ElementNode, ParseError> {
+
+    it.read_token(); // Consume keyword
+
+    let (is_regex, value) = parse_selector_value(it)?;
+
+    if value.is_empty() {
+        let (line, column) = it.get_current_position();
+        return Err(ParseError::invalid_selector_value("empty value", line, column));
+    }
+
+    Ok(ElementNode::Role(value, is_regex))
+}
+
+/// Parse relational selector (not, has) with its parenthesized expression
+fn parse_relational_selector(it: &mut Parser) -> Result<ElementNode, ParseError> {
+    let is_not = it.check_token(&Token::Not);
+    it.read_token(); // Consume keyword
+    it.consume_token(&Token::LeftParen)?;
+
+    it.check_depth()?;
+
+    let expr = Box::new(parse_set(it)?);
+    match &it.current_token {
+        Some((Token::RightParen, _, _)) => {
+            it.consume_token(&Token::RightParen)?;
+
+            it.decrease_depth();
+        }
+        _ => {
+            let (line, column) = it.get_current_position();
+            let current = it.get_current_token_str();
+            return Err(ParseError::unexpected_token(
+                "right parenthesis ')'",
+                &current,
+                line,
+                column,
+            ));
+        }
+    }
+
+    Ok(if is_not { ElementNode::Not(expr) } else { ElementNode::Has(expr) })
+}
+
+/// Parse navigation step (next, prev, child, parent, ancestors, closest) with its filter
+fn parse_navigation_step(it: &mut Parser) -> Result<ElementNode, ParseError> {
+    let keyword = match &it.current_token {
+        Some((Token::String(word), _, _)) => word.clone(),
+        _ => String::new(),
+    };
+    it.read_token(); // Consume keyword
+
+    let filter = match &it.current_token {
+        Some((token, _, _)) if is_element_start(token) => match parse_element(it)? {
+            SelectorNode::ElementSelector(filter) => Some(Box::new(filter)),
+            SelectorNode::TextSelector(_) => None,
+        },
+        _ => None,
+    };
+
+    match keyword.as_str() {
+        "next" => Ok(ElementNode::Next(filter)),
+        "prev" => Ok(ElementNode::Prev(filter)),
+        "child" => Ok(ElementNode::Child(filter)),
+        "parent" => Ok(ElementNode::Parent(filter)),
+        "ancestors" => Ok(ElementNode::Ancestors(filter)),
+        _ => match filter {
+            Some(filter) => Ok(ElementNode::Closest(filter)),
+            None => {
+                let (line, column) = it.get_current_position();
+                let current = it.get_current_token_str();
+                Err(ParseError::unexpected_token(
+                    "element selector after closest",
+                    &current,
+                    line,
+                    column,
+                ))
+            }
+        },
+    }
+}
+
+/// Parse structural filter (first-child, last-child, nth-child, empty, leaf)
+fn parse_structural_filter(it: &mut Parser) -> Result<ElementNode, ParseError> {
+    let keyword = match &it.current_token {
+        Some((Token::String(word), _, _)) => word.clone(),
+        _ => String::new(),
+    };
+    it.read_token(); // Consume keyword
+
+    match keyword.as_str() {
+        "first-child" => Ok(ElementNode::FirstChild),
+        "last-child" => Ok(ElementNode::LastChild),
+        "empty" => Ok(ElementNode::Empty),
+        "leaf" => Ok(ElementNode::Leaf),
+        _ => match &it.current_token {
+            Some((Token::Number(n), _, _)) if *n > 0 => {
+                let n = *n;
+                it.read_token();
+                Ok(ElementNode::NthChild(n))
+            }
+            Some((Token::Number(_), line, column)) => Err(ParseError::invalid_selector_value(
+                "nth-child counts from 1",
+                *line,
+                *column,
+            )),
+            _ => {
+                let (line, column) = it.get_current_position();
+                let current = it.get_current_token_str();
+                Err(ParseError::unexpected_token(
+                    "position after nth-child",
+                    &current,
+                    line,
+                    column,
+                ))
+            }
+        },
+    }
+}
+
+/// Parse text node selector (textnode)
+fn parse_text_node_selector(it: &mut Parser) -> Result<ElementNode, ParseError> {
+    it.read_token(); // Consume keyword
+
+    Ok(ElementNode::TextNodes)
+}
+
+/// Parse the optional maximum depth after a searching selector (`tag div,2`)
+fn parse_depth_limit(it: &mut Parser, element_node: ElementNode) -> Result<ElementNode, ParseError> {
+    if !it.check_token(&Token::Comma) {
+        return Ok(element_node);
+    }
+    it.consume_token(&Token::Comma)?;
+
+    match &it.current_token {
+        Some((Token::Number(depth), _, _)) => {
+            let depth = *depth;
+            it.read_token();
+            Ok(ElementNode::Depth(Box::new(element_node), depth))
+        }
+        _ => {
+            let (line, column) = it.get_current_position();
+            let current = it.get_current_token_str();
+            Err(ParseError::unexpected_token(
+                "maximum depth",
+                &current,
+                line,
+                column,
+            ))
+        }
+    }
+}
+
 /// Parse selector value
 fn parse_selector_value(it: &mut Parser) -> Result<(bool, String), ParseError> {
     let is_regex = if it.check_token(&Token::Tilde) {
@@ -110,8 +340,11 @@ fn parse_selector_value(it: &mut Parser) -> Result<(bool, String), ParseError> {
 
     // Parse selector value (common argument or quoted argument)
     match &it.current_token {
-        Some((Token::String(value), _, _)) => {
+        Some((Token::String(value), line, column)) => {
             let value = value.clone();
+            if is_regex {
+                it.validate_regex(&value, *line, *column)?;
+            }
             it.read_token(); // Consume argument
             Ok((is_regex, value))
         }
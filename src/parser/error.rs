@@ -21,6 +21,12 @@ pub enum ParseErrorKind {
     
     /// Element query directive after text query directive
     ElementAfterTextSelector,
+
+    /// Invalid regular expression
+    InvalidRegex,
+
+    /// Rejected by a validation pass
+    RuleViolation,
 }
 
 impl fmt::Display for ParseErrorKind {
@@ -32,6 +38,8 @@ impl fmt::Display for ParseErrorKind {
             ParseErrorKind::SyntaxError => write!(f, "Syntax error"),
             ParseErrorKind::MultipleTextSelectors => write!(f, "Multiple text query directives"),
             ParseErrorKind::ElementAfterTextSelector => write!(f, "Element query after text query"),
+            ParseErrorKind::InvalidRegex => write!(f, "Invalid regular expression"),
+            ParseErrorKind::RuleViolation => write!(f, "Rule violation"),
         }
     }
 }
@@ -107,6 +115,17 @@ impl ParseError {
         }
     }
 
+    /// Create an invalid regular expression error
+    pub fn invalid_regex(pattern: &str, reason: &str, line: usize, column: usize) -> Self {
+        ParseError {
+            kind: ParseErrorKind::InvalidRegex,
+            message: format!("Invalid regular expression {:?}: {}", pattern, reason),
+            line,
+            column,
+            recovery_hint: Some("Please check the pattern after `~`, quoting it if it contains spaces or operators".to_string()),
+        }
+    }
+
     /// Create a nesting too deep error
     pub fn nesting_too_deep(max_depth: usize, line: usize, column: usize) -> Self {
         ParseError {
@@ -9,6 +9,7 @@ use super::{literal, Parser};
 pub fn parse_function(it: &mut Parser, mut node: Node) -> Result<Node, ParseError> {
     while let Some((Token::Function(function_name), _, _)) = &it.current_token {
         let function_name = function_name.clone();
+        let start = it.last_span_start();
         if function_name.is_empty() {
             let (line, column) = it.get_current_position();
             return Err(ParseError::syntax_error("Function name cannot be empty.", line, column));
@@ -25,6 +26,7 @@ pub fn parse_function(it: &mut Parser, mut node: Node) -> Result<Node, ParseErro
         };
 
         node = Node::FunctionCall(Box::new(node), function_node);
+        it.record_span(start);
 
         it.decrease_depth();
     }
@@ -14,6 +14,8 @@ pub fn parse_index(it: &mut Parser, mut node: Node) -> Result<Node, ParseError>
 
 /// 解析索引选择器
 pub fn parse_index_selector(it: &mut Parser, node: Node) -> Result<Node, ParseError> {
+    let start = it.last_span_start();
+
     if !it.check_token(&Token::Colon) {
         let (line, column) = it.get_current_position();
         let current = it.get_current_token_str();
@@ -90,5 +92,8 @@ pub fn parse_index_selector(it: &mut Parser, node: Node) -> Result<Node, ParseEr
 
     it.decrease_depth();
 
-    Ok(Node::IndexSelection(Box::new(node), Box::new(index_node)))
+    let node = Node::IndexSelection(Box::new(node), Box::new(index_node));
+    it.record_span(start);
+
+    Ok(node)
 }
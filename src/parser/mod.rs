@@ -1,6 +1,7 @@
 use crate::{lexer::Token, tokenize};
 
 pub mod ast;
+pub mod diff;
 pub mod element;
 pub mod error;
 pub mod function;
@@ -9,15 +10,17 @@ pub mod pipeline;
 pub mod set;
 pub mod text;
 pub mod validate;
+pub mod version;
 pub mod basic;
 pub mod literal;
 
-use validate::SyntaxValidator;
-
 pub use ast::{
-    ElementNode, FunctionNode, IndexNode, Node, SelectorNode, SetOperationNode, TextNode,
-    Visitable, Visitor,
+    ElementNode, FunctionNode, IndexNode, Literal, Node, SelectorNode, SetOperationNode, Span,
+    SpanTable, SpannedNode, TextNode, Visitable, Visitor, walk,
 };
+pub use diff::{SelectorDiff, StageDiff, diff};
+pub use validate::{Diagnostic, Severity, ValidationPass, Validator};
+pub use version::SyntaxVersion;
 pub use error::ParseError;
 
 /// HTML selector parser
@@ -36,6 +39,9 @@ pub struct Parser {
 
     /// Maximum nesting depth
     max_nesting_level: usize,
+
+    /// Spans of the nodes built so far, in construction order
+    spans: Vec<Span>,
 }
 
 impl Parser {
@@ -47,6 +53,7 @@ impl Parser {
             current_token: None,
             max_nesting_level: 100,
             current_depth: 0,
+            spans: Vec::new(),
         };
         parser.read_token();
         parser
@@ -111,6 +118,24 @@ impl Parser {
         }
     }
 
+    /// Record the span of a node that starts at `start` and was just built
+    fn record_span(&mut self, start: (usize, usize)) {
+        let end = self.get_current_position();
+        self.spans.push(Span { start, end });
+    }
+
+    /// Start position of the most recently built node
+    fn last_span_start(&self) -> (usize, usize) {
+        self.spans.last().map_or_else(|| self.get_current_position(), |span| span.start)
+    }
+
+    /// Validate a regular expression pattern at the given position
+    fn validate_regex(&self, pattern: &str, line: usize, column: usize) -> Result<(), ParseError> {
+        crate::pattern::Pattern::new(pattern)
+            .map(|_| ())
+            .map_err(|e| ParseError::invalid_regex(pattern, &e.to_string(), line, column))
+    }
+
     /// Check nesting depth and increment counter
     fn check_depth(&mut self) -> Result<(), ParseError> {
         if self.current_depth >= self.max_nesting_level {
@@ -179,7 +204,7 @@ impl Parser {
                         return Ok(());
                     }
                 }
-                Token::Pipeline | Token::Union | Token::Intersection | Token::Difference => {
+                Token::Pipeline | Token::Union | Token::Intersection | Token::Difference | Token::Fallback => {
                     if depth == 0 {
                         // Found a top-level operator, is a good sync point
                         return Ok(());
@@ -199,6 +224,25 @@ impl Parser {
 
 /// Convert lexer token stream to AST
 pub fn parse(input: &str) -> Result<Node, ParseError> {
+    parse_with(input, &Validator::default()).map(|(node, _)| node)
+}
+
+/// Convert lexer token stream to AST and run the given validation passes over it.
+///
+/// Fails on the first error diagnostic; warnings are returned with the AST.
+pub fn parse_with(input: &str, validator: &Validator) -> Result<(Node, Vec<Diagnostic>), ParseError> {
+    parse_spanned_with(input, validator).map(|(node, warnings)| (node.into_node(), warnings))
+}
+
+/// Convert lexer token stream to AST, keeping the source span of every node.
+pub fn parse_spanned(input: &str) -> Result<SpannedNode, ParseError> {
+    parse_spanned_with(input, &Validator::default()).map(|(node, _)| node)
+}
+
+fn parse_spanned_with(
+    input: &str,
+    validator: &Validator,
+) -> Result<(SpannedNode, Vec<Diagnostic>), ParseError> {
     let tokens = tokenize(input);
 
     let mut parser = Parser::new(tokens);
@@ -209,6 +253,8 @@ pub fn parse(input: &str) -> Result<Node, ParseError> {
             if let Err(_) = parser.try_recover() {
                 return Err(parse_error);
             }
+            // Only nodes from the recovered parse end up in the tree
+            parser.spans.clear();
             match set::parse_set(&mut parser) {
                 Ok(recovered_node) => Ok(recovered_node),
                 Err(_second_error) => {
@@ -218,8 +264,8 @@ pub fn parse(input: &str) -> Result<Node, ParseError> {
         }
     }?;
 
-    let mut validator = SyntaxValidator::new();
-    validator.validate(&node)?;
+    let node = SpannedNode::new(Box::new(node), &parser.spans);
+    let warnings = validator.validate(node.root(), node.spans())?;
 
-    Ok(node)
+    Ok((node, warnings))
 }
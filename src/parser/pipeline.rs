@@ -6,6 +6,7 @@ use super::basic::parse_basic;
 
 /// Parsing pipeline operation expressions.
 pub fn parse_pipeline(it: &mut Parser) -> Result<Node, ParseError> {
+    let start = it.get_current_position();
 
     let mut left = parse_basic(it)?;
 
@@ -17,6 +18,7 @@ pub fn parse_pipeline(it: &mut Parser) -> Result<Node, ParseError> {
         let right = parse_basic(it)?;
 
         left = Node::Pipeline(Box::new(left), Box::new(right));
+        it.record_span(start);
 
         it.decrease_depth();
     }
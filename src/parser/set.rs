@@ -6,6 +6,7 @@ use super::Parser;
 
 /// Parsing set operation expressions.
 pub fn parse_set(it: &mut Parser) -> Result<Node, ParseError> {
+    let start = it.get_current_position();
     let mut left = parse_pipeline(it)?;
 
     loop {
@@ -20,6 +21,7 @@ pub fn parse_set(it: &mut Parser) -> Result<Node, ParseError> {
                     Box::new(left),
                     Box::new(right),
                 )));
+                it.record_span(start);
 
                 it.decrease_depth();
             }
@@ -36,6 +38,7 @@ pub fn parse_set(it: &mut Parser) -> Result<Node, ParseError> {
                     Box::new(left),
                     Box::new(right),
                 )));
+                it.record_span(start);
 
                 it.decrease_depth();
             }
@@ -52,6 +55,35 @@ pub fn parse_set(it: &mut Parser) -> Result<Node, ParseError> {
                     Box::new(left),
                     Box::new(right),
                 )));
+                it.record_span(start);
+
+                it.decrease_depth();
+            }
+
+            Some((Token::Fallback, _, _)) => {
+
+                it.consume_token(&Token::Fallback)?;
+
+                // Optional minimum count, `||` alone falls back on an empty result
+                let min = match &it.current_token {
+                    Some((Token::Number(n), _, _)) => {
+                        let n = *n;
+                        it.read_token();
+                        n
+                    }
+                    _ => 1,
+                };
+
+                it.check_depth()?;
+
+                let right = parse_pipeline(it)?;
+
+                left = Node::SetOperation(Box::new(SetOperationNode::Fallback(
+                    Box::new(left),
+                    Box::new(right),
+                    min,
+                )));
+                it.record_span(start);
 
                 it.decrease_depth();
             }
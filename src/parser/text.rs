@@ -3,6 +3,19 @@ use crate::parser::ast::{SelectorNode, TextNode};
 use crate::parser::error::ParseError;
 use super::Parser;
 
+/// Words that start a text selector when they begin a selector. They are not reserved,
+/// so `class value` still selects the class `value`.
+const TEXT_KEYWORDS: &[&str] = &["value", "data", "markdown", "links", "tagpath", "owntext"];
+
+/// Check whether a token starts a text selector
+pub fn is_text_start(token: &Token) -> bool {
+    match token {
+        Token::Text | Token::Href | Token::Src | Token::Pound | Token::Question => true,
+        Token::String(word) => TEXT_KEYWORDS.contains(&word.as_str()),
+        _ => false,
+    }
+}
+
 /// Parse Text Selector
 pub fn parse_text(it: &mut Parser) -> Result<SelectorNode, ParseError> {
     match &it.current_token {
@@ -18,15 +31,43 @@ pub fn parse_text(it: &mut Parser) -> Result<SelectorNode, ParseError> {
             let text_node = parse_src_selector(it)?;
             Ok(SelectorNode::TextSelector(text_node))
         }
+        Some((Token::String(word), _, _)) if word == "markdown" => {
+            let text_node = parse_markdown_selector(it)?;
+            Ok(SelectorNode::TextSelector(text_node))
+        }
+        Some((Token::String(word), _, _)) if word == "links" => {
+            let text_node = parse_links_selector(it)?;
+            Ok(SelectorNode::TextSelector(text_node))
+        }
+        Some((Token::String(word), _, _)) if word == "tagpath" => {
+            let text_node = parse_tag_path_selector(it)?;
+            Ok(SelectorNode::TextSelector(text_node))
+        }
+        Some((Token::String(word), _, _)) if word == "owntext" => {
+            let text_node = parse_own_text_selector(it)?;
+            Ok(SelectorNode::TextSelector(text_node))
+        }
+        Some((Token::String(word), _, _)) if word == "value" => {
+            let text_node = parse_value_selector(it)?;
+            Ok(SelectorNode::TextSelector(text_node))
+        }
+        Some((Token::String(word), _, _)) if word == "data" => {
+            let text_node = parse_data_selector(it)?;
+            Ok(SelectorNode::TextSelector(text_node))
+        }
         Some((Token::Pound, _, _)) => {
             let text_node = parse_attr_value_selector(it)?;
             Ok(SelectorNode::TextSelector(text_node))
         }
+        Some((Token::Question, _, _)) => {
+            let text_node = parse_attr_presence_selector(it)?;
+            Ok(SelectorNode::TextSelector(text_node))
+        }
         _ => {
             let (line, column) = it.get_current_position();
             let current = it.get_current_token_str();
             Err(ParseError::unexpected_token(
-                "text,href,src",
+                "text,owntext,href,src,value,data,markdown,links,tagpath,#,?",
                 &current,
                 line,
                 column,
@@ -57,9 +98,67 @@ fn parse_src_selector(it: &mut Parser) -> Result<TextNode, ParseError> {
     Ok(TextNode::Src)
 }
 
+/// Parsing the Markdown rendering selector (markdown)
+fn parse_markdown_selector(it: &mut Parser) -> Result<TextNode, ParseError> {
+    it.read_token(); // Consume keyword
+
+    Ok(TextNode::Markdown)
+}
+
+/// Parsing the anchor text and target selector (links)
+fn parse_links_selector(it: &mut Parser) -> Result<TextNode, ParseError> {
+    it.read_token(); // Consume keyword
+
+    Ok(TextNode::Links)
+}
+
+/// Parsing the tag path selector (tagpath)
+fn parse_tag_path_selector(it: &mut Parser) -> Result<TextNode, ParseError> {
+    it.read_token(); // Consume keyword
+
+    Ok(TextNode::TagPath)
+}
+
+/// Parsing the own text selector (owntext)
+fn parse_own_text_selector(it: &mut Parser) -> Result<TextNode, ParseError> {
+    it.read_token(); // Consume keyword
+
+    Ok(TextNode::OwnText)
+}
+
+/// Parsing the form control value selector (value)
+fn parse_value_selector(it: &mut Parser) -> Result<TextNode, ParseError> {
+    it.read_token(); // Consume keyword
+
+    Ok(TextNode::Value)
+}
+
+/// Parsing the data attributes selector (data)
+fn parse_data_selector(it: &mut Parser) -> Result<TextNode, ParseError> {
+    it.read_token(); // Consume keyword
+
+    Ok(TextNode::Data)
+}
+
 fn parse_attr_value_selector(it: &mut Parser) -> Result<TextNode, ParseError> {
     it.consume_token(&Token::Pound)?;
 
+    let (name, is_regex) = parse_attr_name(it)?;
+
+    Ok(TextNode::AttrValue(name, is_regex))
+}
+
+/// Parsing the attribute presence selector (?"attributeName")
+fn parse_attr_presence_selector(it: &mut Parser) -> Result<TextNode, ParseError> {
+    it.consume_token(&Token::Question)?;
+
+    let (name, is_regex) = parse_attr_name(it)?;
+
+    Ok(TextNode::AttrPresent(name, is_regex))
+}
+
+/// Parse the attribute name of `#` and `?`, optionally a regex introduced by `~`
+fn parse_attr_name(it: &mut Parser) -> Result<(String, bool), ParseError> {
     let is_regex = if it.check_token(&Token::Tilde) {
         it.consume_token(&Token::Tilde)?; // Consume regex token
         true
@@ -69,8 +168,11 @@ fn parse_attr_value_selector(it: &mut Parser) -> Result<TextNode, ParseError> {
 
     // Parse selector value (common argument or quoted argument)
     let name = match &it.current_token {
-        Some((Token::String(value), _, _)) => {
+        Some((Token::String(value), line, column)) => {
             let value = value.clone();
+            if is_regex {
+                it.validate_regex(&value, *line, *column)?;
+            }
             it.read_token(); // Consume argument
             value
         }
@@ -86,6 +188,5 @@ fn parse_attr_value_selector(it: &mut Parser) -> Result<TextNode, ParseError> {
         }
     };
 
-    Ok(TextNode::AttrValue(name, is_regex))
-
+    Ok((name, is_regex))
 }
@@ -1,8 +1,11 @@
+use std::fmt;
+use std::sync::Arc;
+
 use crate::parser::ast::{
-    ElementNode, FunctionNode, IndexNode, Node, SelectorNode, SetOperationNode,
-    TextNode, Visitor, Visitable,
+    ElementNode, FunctionNode, IndexNode, Node, SelectorNode, SetOperationNode, Span,
+    SpanTable, TextNode, Visitor, Visitable,
 };
-use crate::parser::error::ParseError;
+use crate::parser::error::{ParseError, ParseErrorKind};
 
 /// Syntax validator state
 #[derive(Debug, Clone)]
@@ -49,6 +52,9 @@ impl ValidationState {
 pub struct SyntaxValidator {
     /// Current validation state
     state: ValidationState,
+
+    /// Node spans used to report error positions
+    spans: Option<SpanTable>,
 }
 
 impl SyntaxValidator {
@@ -56,6 +62,15 @@ impl SyntaxValidator {
     pub fn new() -> Self {
         SyntaxValidator {
             state: ValidationState::new(),
+            spans: None,
+        }
+    }
+
+    /// Create a validator that reports errors at the position of the offending node
+    pub fn with_spans(spans: SpanTable) -> Self {
+        SyntaxValidator {
+            state: ValidationState::new(),
+            spans: Some(spans),
         }
     }
     
@@ -74,6 +89,10 @@ impl SyntaxValidator {
 
 impl Visitor<Result<(), ParseError>> for SyntaxValidator {
     fn visit_node(&mut self, node: &Node) -> Result<(), ParseError> {
+        if let Some(span) = self.spans.as_ref().and_then(|spans| spans.span(node)) {
+            self.state.set_position(span.start.0, span.start.1);
+        }
+
         match node {
             Node::Selector(selector) => self.visit_selector(selector),
             Node::Pipeline(left, right) => self.visit_pipeline(left, right),
@@ -131,7 +150,8 @@ impl Visitor<Result<(), ParseError>> for SyntaxValidator {
         match node {
             SetOperationNode::Union(left, right) 
             | SetOperationNode::Intersection(left, right) 
-            | SetOperationNode::Difference(left, right) => {
+            | SetOperationNode::Difference(left, right)
+            | SetOperationNode::Fallback(left, right, _) => {
 
                 let original_state = self.state.clone();
                 
@@ -154,8 +174,182 @@ impl Visitor<Result<(), ParseError>> for SyntaxValidator {
         Ok(())
     }
     
-    fn visit_function(&mut self, _node: &FunctionNode) -> Result<(), ParseError> {
-        // Function call does not change text selector state
+    fn visit_function(&mut self, node: &FunctionNode) -> Result<(), ParseError> {
+        // Function calls do not change text selector state, except `@as_html`, which
+        // parses the texts into elements again
+        if node.name == "as_html" {
+            self.state = ValidationState::new();
+        }
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// Diagnostic severity
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    /// The selector is rejected
+    Error,
+
+    /// The selector is accepted and the diagnostic is reported alongside it
+    Warning,
+}
+
+/// A problem found by a validation pass
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    /// Name of the pass that produced the diagnostic
+    pub pass: String,
+
+    /// Severity
+    pub severity: Severity,
+
+    /// Error type
+    pub kind: ParseErrorKind,
+
+    /// Message
+    pub message: String,
+
+    /// Source range of the offending node
+    pub span: Span,
+
+    /// Suggested fix
+    pub hint: Option<String>,
+}
+
+impl Diagnostic {
+    /// Create an error that rejects the selector
+    pub fn error(message: impl Into<String>, span: Span) -> Self {
+        Diagnostic {
+            pass: String::new(),
+            severity: Severity::Error,
+            kind: ParseErrorKind::RuleViolation,
+            message: message.into(),
+            span,
+            hint: None,
+        }
+    }
+
+    /// Create a warning that is reported without rejecting the selector
+    pub fn warning(message: impl Into<String>, span: Span) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            ..Diagnostic::error(message, span)
+        }
+    }
+
+    /// Attach a suggested fix
+    pub fn with_hint(mut self, hint: impl Into<String>) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+}
+
+impl From<ParseError> for Diagnostic {
+    fn from(err: ParseError) -> Self {
+        Diagnostic {
+            pass: String::new(),
+            severity: Severity::Error,
+            kind: err.kind,
+            message: err.message,
+            span: Span {
+                start: (err.line, err.column),
+                end: (err.line, err.column),
+            },
+            hint: err.recovery_hint,
+        }
+    }
+}
+
+impl From<Diagnostic> for ParseError {
+    fn from(diagnostic: Diagnostic) -> Self {
+        let (line, column) = diagnostic.span.start;
+        ParseError::new(diagnostic.kind, diagnostic.message, line, column, diagnostic.hint)
+    }
+}
+
+/// A validation rule run over every parsed selector
+pub trait ValidationPass: Send + Sync {
+    /// Name reported with the diagnostics of this pass
+    fn name(&self) -> &str;
+
+    /// Inspect the tree and report problems. `spans` gives the source range of each node;
+    /// use `walk` to visit every node.
+    fn check(&self, root: &Node, spans: &SpanTable, diagnostics: &mut Vec<Diagnostic>);
+}
+
+/// Built-in rules on where text selectors may appear
+pub struct TextSelectorRules;
+
+impl ValidationPass for TextSelectorRules {
+    fn name(&self) -> &str {
+        "text-selectors"
+    }
+
+    fn check(&self, root: &Node, spans: &SpanTable, diagnostics: &mut Vec<Diagnostic>) {
+        if let Err(err) = SyntaxValidator::with_spans(spans.clone()).validate(root) {
+            diagnostics.push(err.into());
+        }
+    }
+}
+
+/// Ordered list of validation passes
+#[derive(Clone)]
+pub struct Validator {
+    passes: Vec<Arc<dyn ValidationPass>>,
+}
+
+impl Default for Validator {
+    /// The built-in rules
+    fn default() -> Self {
+        Validator {
+            passes: vec![Arc::new(TextSelectorRules)],
+        }
+    }
+}
+
+impl fmt::Debug for Validator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.passes.iter().map(|pass| pass.name()))
+            .finish()
+    }
+}
+
+impl Validator {
+    /// Create a validator without any passes, not even the built-in rules
+    pub fn empty() -> Self {
+        Validator { passes: Vec::new() }
+    }
+
+    /// Append a pass, run after the existing ones
+    pub fn with_pass(mut self, pass: impl ValidationPass + 'static) -> Self {
+        self.passes.push(Arc::new(pass));
+        self
+    }
+
+    /// Run every pass and collect all diagnostics
+    pub fn run(&self, root: &Node, spans: &SpanTable) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+
+        for pass in &self.passes {
+            let first = diagnostics.len();
+            pass.check(root, spans, &mut diagnostics);
+            for diagnostic in &mut diagnostics[first..] {
+                diagnostic.pass = pass.name().to_string();
+            }
+        }
+
+        diagnostics
+    }
+
+    /// Run every pass, failing on the first error and returning the warnings otherwise
+    pub fn validate(&self, root: &Node, spans: &SpanTable) -> Result<Vec<Diagnostic>, ParseError> {
+        let diagnostics = self.run(root, spans);
+
+        if let Some(error) = diagnostics.iter().find(|d| d.severity == Severity::Error) {
+            return Err(error.clone().into());
+        }
+
+        Ok(diagnostics)
+    }
+}
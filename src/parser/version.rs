@@ -0,0 +1,141 @@
+// parser/version.rs - Selector syntax versions
+//
+// Grammar additions are tied to a syntax version. Selectors stored against an older
+// version can pin it, so they are rejected rather than silently reinterpreted when
+// they use constructs that did not exist in that version.
+//
+// Regex flags (`~i"nav"`) and globs (`*"nav-*"`) are not gated: the lexer rewrites
+// them into ordinary regex values (`~"(?i)nav"`, `~"(?s)^nav-.*$"`) that every
+// version accepts, so they cannot be reinterpreted.
+
+use super::ast::{ElementNode, Node, SelectorNode, SetOperationNode, SpanTable, TextNode, walk};
+use super::validate::{Diagnostic, ValidationPass};
+
+/// Version of the selector grammar
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum SyntaxVersion {
+    /// The original grammar: element and text selectors, indexes, set operations, and
+    /// functions on text selectors only
+    V1,
+
+    /// Adds `lang` and `role` selectors, functions on element selectors, the `markdown`,
+    /// `links` and `tagpath` selectors, attribute presence selectors such as `?href`, and
+    /// `||` fallbacks
+    V2,
+
+    /// Adds the relational selectors `not(...)` and `has(...)` and the navigation steps
+    /// `next`, `prev`, `child`, `parent`, `ancestors` and `closest`, and the structural
+    /// filters `first-child`, `last-child`, `nth-child`, `empty` and `leaf`, and depth
+    /// limits such as `tag div,2`, and the `owntext`, `textnode`, `value` and `data`
+    /// selectors
+    #[default]
+    V3,
+}
+
+impl SyntaxVersion {
+    /// The newest grammar
+    pub const LATEST: SyntaxVersion = SyntaxVersion::V3;
+}
+
+/// Rejects constructs introduced after the given syntax version
+pub struct VersionGate(pub SyntaxVersion);
+
+impl ValidationPass for VersionGate {
+    fn name(&self) -> &str {
+        "syntax-version"
+    }
+
+    fn check(&self, root: &Node, spans: &SpanTable, diagnostics: &mut Vec<Diagnostic>) {
+        if self.0 >= SyntaxVersion::LATEST {
+            return;
+        }
+
+        walk(root, &mut |node| {
+            let feature = match node {
+                Node::Selector(selector) => match selector.as_ref() {
+                    SelectorNode::ElementSelector(ElementNode::Lang(..)) => {
+                        Some((SyntaxVersion::V2, "`lang` selectors"))
+                    }
+                    SelectorNode::ElementSelector(ElementNode::Role(..)) => {
+                        Some((SyntaxVersion::V2, "`role` selectors"))
+                    }
+                    SelectorNode::ElementSelector(ElementNode::Not(..)) => {
+                        Some((SyntaxVersion::V3, "`not(...)` selectors"))
+                    }
+                    SelectorNode::ElementSelector(ElementNode::Has(..)) => {
+                        Some((SyntaxVersion::V3, "`has(...)` selectors"))
+                    }
+                    SelectorNode::ElementSelector(
+                        ElementNode::Next(..)
+                        | ElementNode::Prev(..)
+                        | ElementNode::Child(..)
+                        | ElementNode::Parent(..)
+                        | ElementNode::Ancestors(..)
+                        | ElementNode::Closest(..),
+                    ) => Some((SyntaxVersion::V3, "navigation steps")),
+                    SelectorNode::ElementSelector(
+                        ElementNode::FirstChild
+                        | ElementNode::LastChild
+                        | ElementNode::NthChild(_)
+                        | ElementNode::Empty
+                        | ElementNode::Leaf,
+                    ) => Some((SyntaxVersion::V3, "structural filters")),
+                    SelectorNode::ElementSelector(ElementNode::TextNodes) => {
+                        Some((SyntaxVersion::V3, "`textnode` selectors"))
+                    }
+                    SelectorNode::ElementSelector(ElementNode::Depth(..)) => {
+                        Some((SyntaxVersion::V3, "depth limits"))
+                    }
+                    SelectorNode::TextSelector(TextNode::Markdown) => {
+                        Some((SyntaxVersion::V2, "`markdown` selectors"))
+                    }
+                    SelectorNode::TextSelector(TextNode::Links) => {
+                        Some((SyntaxVersion::V2, "`links` selectors"))
+                    }
+                    SelectorNode::TextSelector(TextNode::TagPath) => {
+                        Some((SyntaxVersion::V2, "`tagpath` selectors"))
+                    }
+                    SelectorNode::TextSelector(TextNode::OwnText) => {
+                        Some((SyntaxVersion::V3, "`owntext` selectors"))
+                    }
+                    SelectorNode::TextSelector(TextNode::Value) => {
+                        Some((SyntaxVersion::V3, "`value` selectors"))
+                    }
+                    SelectorNode::TextSelector(TextNode::Data) => {
+                        Some((SyntaxVersion::V3, "`data` selectors"))
+                    }
+                    SelectorNode::TextSelector(TextNode::AttrPresent(..)) => {
+                        Some((SyntaxVersion::V2, "attribute presence selectors"))
+                    }
+                    _ => None,
+                },
+                Node::SetOperation(op) if matches!(op.as_ref(), SetOperationNode::Fallback(..)) => {
+                    Some((SyntaxVersion::V2, "`||` fallbacks"))
+                }
+                Node::FunctionCall(inner, _) if !applies_to_text(inner) => {
+                    Some((SyntaxVersion::V2, "functions on element selectors"))
+                }
+                _ => None,
+            };
+
+            if let Some((version, feature)) = feature
+                && version > self.0
+            {
+                let span = spans.span(node).unwrap_or_default();
+                diagnostics.push(
+                    Diagnostic::error(format!("{} require syntax version {:?}", feature, version), span)
+                        .with_hint(format!("The selector is pinned to {:?}", self.0)),
+                );
+            }
+        });
+    }
+}
+
+/// Whether a function call target is a (possibly indexed) text selector
+fn applies_to_text(node: &Node) -> bool {
+    match node {
+        Node::Selector(selector) => matches!(selector.as_ref(), SelectorNode::TextSelector(_)),
+        Node::IndexSelection(inner, _) | Node::FunctionCall(inner, _) => applies_to_text(inner),
+        _ => false,
+    }
+}
@@ -0,0 +1,146 @@
+// pattern.rs - Regular expression engine behind the `~` operator
+//
+// The engine is chosen at compile time. `regex` is the default; `fancy-regex` adds
+// lookarounds and backreferences, and `regex-lite` gives up Unicode classes and matching
+// speed for a smaller binary (build with `default-features = false` so that `regex` is
+// not linked as well). When several are enabled, `fancy-regex` wins over `regex-lite`,
+// and both over `regex`.
+
+#[cfg(not(any(feature = "regex", feature = "regex-lite", feature = "fancy-regex")))]
+compile_error!("htmls needs a regex engine: enable `regex`, `regex-lite` or `fancy-regex`");
+
+#[cfg(feature = "fancy-regex")]
+use fancy_regex as engine;
+#[cfg(all(feature = "regex-lite", not(feature = "fancy-regex")))]
+use regex_lite as engine;
+#[cfg(all(feature = "regex", not(any(feature = "regex-lite", feature = "fancy-regex"))))]
+use regex as engine;
+
+use std::borrow::Cow;
+use std::fmt;
+
+/// A compiled regular expression
+#[derive(Debug, Clone)]
+pub struct Pattern(engine::Regex);
+
+/// Error for a pattern the engine rejects
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatternError(String);
+
+impl Pattern {
+    /// Compile a pattern with the selected engine
+    pub fn new(pattern: &str) -> Result<Self, PatternError> {
+        engine::Regex::new(pattern)
+            .map(Pattern)
+            .map_err(|e| PatternError(e.to_string()))
+    }
+
+    /// The source of the pattern
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+
+    /// Check whether the pattern matches anywhere in `text`. With `fancy-regex`, a
+    /// search that hits the backtracking limit counts as no match.
+    pub fn is_match(&self, text: &str) -> bool {
+        #[cfg(feature = "fancy-regex")]
+        return self.0.is_match(text).unwrap_or(false);
+
+        #[cfg(not(feature = "fancy-regex"))]
+        return self.0.is_match(text);
+    }
+
+    /// The first match in `text`
+    pub fn find<'t>(&self, text: &'t str) -> Option<&'t str> {
+        #[cfg(feature = "fancy-regex")]
+        return self.0.find(text).ok().flatten().map(|m| m.as_str());
+
+        #[cfg(not(feature = "fancy-regex"))]
+        return self.0.find(text).map(|m| m.as_str());
+    }
+
+    /// All non-overlapping matches in `text`. With `fancy-regex`, the search stops at
+    /// a match that hits the backtracking limit.
+    pub fn find_all<'t>(&self, text: &'t str) -> Vec<&'t str> {
+        #[cfg(feature = "fancy-regex")]
+        return self.0.find_iter(text).map_while(Result::ok).map(|m| m.as_str()).collect();
+
+        #[cfg(not(feature = "fancy-regex"))]
+        return self.0.find_iter(text).map(|m| m.as_str()).collect();
+    }
+
+    /// Number of groups in the pattern, including the implicit group 0 for the whole
+    /// match
+    pub fn captures_len(&self) -> usize {
+        self.0.captures_len()
+    }
+
+    /// Text of group `index` in the first match in `text`, if there is a match and the
+    /// group took part in it
+    pub fn capture<'t>(&self, text: &'t str, index: usize) -> Option<&'t str> {
+        #[cfg(feature = "fancy-regex")]
+        let captures = self.0.captures(text).ok().flatten()?;
+
+        #[cfg(not(feature = "fancy-regex"))]
+        let captures = self.0.captures(text)?;
+
+        captures.get(index).map(|m| m.as_str())
+    }
+
+    /// Replace every match in `text`, expanding `$1` and `${name}` in `replacement` to
+    /// the groups of the match. With `fancy-regex`, a text whose search hits the
+    /// backtracking limit is returned unchanged.
+    pub fn replace_all<'t>(&self, text: &'t str, replacement: &str) -> Cow<'t, str> {
+        #[cfg(feature = "fancy-regex")]
+        return self
+            .0
+            .try_replacen(text, 0, replacement)
+            .unwrap_or(Cow::Borrowed(text));
+
+        #[cfg(not(feature = "fancy-regex"))]
+        return self.0.replace_all(text, replacement);
+    }
+}
+
+/// Translate a glob, where `*` matches any text and `?` any one character, to a
+/// pattern matching whole values, line breaks included. A backslash makes the next
+/// character literal.
+pub fn glob_to_regex(glob: &str) -> String {
+    let mut pattern = String::with_capacity(glob.len() + 6);
+    pattern.push_str("(?s)^");
+
+    let mut chars = glob.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => pattern.push_str(".*"),
+            '?' => pattern.push('.'),
+            '\\' => {
+                if let Some(c) = chars.next() {
+                    push_literal(&mut pattern, c);
+                }
+            }
+            c => push_literal(&mut pattern, c),
+        }
+    }
+
+    pattern.push('$');
+    pattern
+}
+
+fn push_literal(pattern: &mut String, c: char) {
+    if matches!(
+        c,
+        '\\' | '.' | '+' | '*' | '?' | '(' | ')' | '|' | '[' | ']' | '{' | '}' | '^' | '$'
+    ) {
+        pattern.push('\\');
+    }
+    pattern.push(c);
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for PatternError {}
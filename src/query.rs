@@ -1,6 +1,23 @@
+use std::any::Any;
 use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
 
-use super::{Interpreter, InterpreterError, InterpreterResult, SelectionResult};
+use html5ever::driver::ParseOpts;
+
+use crate::parser::{Node, SyntaxVersion, ValidationPass};
+
+use super::extract::page_kind::PageClass;
+use super::{Document, Metadata, charset, html};
+#[cfg(any(feature = "gzip", feature = "brotli"))]
+use super::compressed;
+use super::{
+    EmptyReason, HtmlWarning, Interpreter, InterpreterError, InterpreterOptions, InterpreterResult,
+    SelectionResult, SerializeOptions,
+};
 /// Provides fluent API interfaces and result caching to simplify queries and operations.
 #[derive(Clone)]
 pub struct Query {
@@ -10,8 +27,53 @@ pub struct Query {
     /// current query result
     current_result: Option<Result<SelectionResult, InterpreterError>>,
 
-    /// query cache
-    cache: HashMap<String, Result<SelectionResult, InterpreterError>>,
+    /// query cache, shared with the views handed out by `for_each`
+    cache: Cache,
+
+    /// selectors run so far, when the audit log is enabled; shared like the cache
+    audit: Option<AuditLog>,
+}
+
+type Cache = Arc<Mutex<HashMap<String, Result<SelectionResult, InterpreterError>>>>;
+
+type AuditLog = Arc<Mutex<Vec<AuditEntry>>>;
+
+/// A selector run recorded by the audit log of a `Query`
+#[derive(Debug, Clone)]
+pub struct AuditEntry {
+    /// Selector as passed to `query`, `from` or `then`
+    pub selector: String,
+
+    /// When evaluation started
+    pub started: SystemTime,
+
+    /// Time taken, including parsing the selector and looking up the cache
+    pub duration: Duration,
+
+    /// Number of nodes or texts produced, 0 on error
+    pub count: usize,
+
+    /// Whether the result came from the cache
+    pub cache_hit: bool,
+
+    /// Why the selector failed, if it did
+    pub error: Option<InterpreterError>,
+}
+
+/// A hyperlink extracted by `Query::links`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+    /// Value of the `href` attribute, as written
+    pub href: String,
+
+    /// Link text with whitespace collapsed
+    pub text: String,
+
+    /// Value of the `rel` attribute
+    pub rel: Option<String>,
+
+    /// Value of the `title` attribute
+    pub title: Option<String>,
 }
 
 impl Query {
@@ -20,46 +82,298 @@ impl Query {
             Ok(it) => Query {
                 interpreter: it,
                 current_result: None,
-                cache: HashMap::new(),
+                cache: Cache::default(),
+                audit: None,
             },
             Err(e) => panic!("{}", e.to_string()),
         }
     }
 
+    /// Create a query over an XML document (sitemaps, RSS, XHTML) parsed without
+    /// HTML error recovery.
+    #[cfg(feature = "xml")]
+    pub fn new_xml(xml: &str) -> Self {
+        match Interpreter::new_xml(xml) {
+            Ok(it) => Query {
+                interpreter: it,
+                current_result: None,
+                cache: Cache::default(),
+                audit: None,
+            },
+            Err(e) => panic!("{}", e.to_string()),
+        }
+    }
 
-    /// Query nodes using a selector.
-    pub fn query(mut self, selector: &str) -> Self {
-        let cache_key = selector.to_string();
-        if !self.cache.contains_key(&cache_key) {
-            let result = self.interpreter.select(selector);
-            self.cache.insert(cache_key.clone(), result);
+    /// Create a query from HTML read incrementally from a reader. The input is decoded
+    /// as UTF-8; use `from_bytes` for documents in other encodings.
+    pub fn from_reader<R: Read>(mut reader: R) -> InterpreterResult<Self> {
+        Ok(Query {
+            interpreter: Interpreter::from_reader(&mut reader)?,
+            current_result: None,
+            cache: Cache::default(),
+            audit: None,
+        })
+    }
+
+    /// Create a query from an HTML file.
+    pub fn from_file(path: impl AsRef<Path>) -> InterpreterResult<Self> {
+        Self::from_reader(File::open(path)?)
+    }
+
+    /// Create a query in strict mode: malformed markup is an error instead of being
+    /// silently repaired.
+    pub fn new_strict(html: &str) -> InterpreterResult<Self> {
+        Ok(Query {
+            interpreter: Interpreter::new_strict(html)?,
+            current_result: None,
+            cache: Cache::default(),
+            audit: None,
+        })
+    }
+
+    /// Create a query over a parsed document with default options and an empty cache.
+    ///
+    /// The document is shared, not copied, so any number of queries can be created
+    /// over a single parse.
+    pub fn on(document: &Document) -> Self {
+        let mut interpreter = Interpreter::with_document(document.root().clone());
+        if !document.ext().is_empty() {
+            Arc::make_mut(&mut interpreter.options).ext = document.ext().clone();
+        }
+
+        Query {
+            interpreter,
+            current_result: None,
+            cache: Cache::default(),
+            audit: None,
         }
+    }
+
+    /// The document this query runs against, with the values attached to the query.
+    pub fn document(&self) -> Document {
+        Document::from_root(self.interpreter.document.clone())
+            .with_ext(self.interpreter.options.ext.clone())
+    }
+
+    /// Create a builder to configure parsing before building a query.
+    pub fn builder() -> QueryBuilder {
+        QueryBuilder::default()
+    }
+
+    /// Create a query from raw bytes, detecting the character encoding from a byte
+    /// order mark or `<meta charset>` declaration.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self::new(&charset::decode(bytes))
+    }
+
+    /// Create a query from gzip compressed HTML, detecting the character encoding like
+    /// `from_bytes`.
+    #[cfg(feature = "gzip")]
+    pub fn from_gzip(bytes: &[u8]) -> InterpreterResult<Self> {
+        Ok(Self::from_bytes(&compressed::gunzip(bytes)?))
+    }
+
+    /// Create a query from brotli compressed HTML, detecting the character encoding like
+    /// `from_bytes`.
+    #[cfg(feature = "brotli")]
+    pub fn from_brotli(bytes: &[u8]) -> InterpreterResult<Self> {
+        Ok(Self::from_bytes(&compressed::unbrotli(bytes)?))
+    }
+
+    /// Create a query from an HTML file, decompressed first if its name ends in `.gz`
+    /// or `.br`.
+    #[cfg(any(feature = "gzip", feature = "brotli"))]
+    pub fn from_compressed_file(path: impl AsRef<Path>) -> InterpreterResult<Self> {
+        Ok(Self::from_bytes(&compressed::read_file(path.as_ref())?))
+    }
+
+
+    /// Errors the HTML parser recovered from while building the document.
+    pub fn warnings(&self) -> &[HtmlWarning] {
+        self.interpreter.warnings()
+    }
+
+    /// Render `alt` text of images and the value of inputs when extracting element text.
+    pub fn void_element_text(mut self, enabled: bool) -> Self {
+        Arc::make_mut(&mut self.interpreter.options).void_element_text = enabled;
+        // Cached text results depend on this option, so stop sharing the cache
+        self.cache = Cache::default();
+        self
+    }
+
+    /// Lay out extracted element text by block: paragraphs, headings, list items and
+    /// `<br>` end up on separate lines, so `<p>a</p><p>b</p>` gives `"a\nb"`, not `"ab"`.
+    pub fn block_text(mut self, enabled: bool) -> Self {
+        Arc::make_mut(&mut self.interpreter.options).block_text = enabled;
+        // Cached text results depend on this option, so stop sharing the cache
+        self.cache = Cache::default();
+        self
+    }
+
+    /// Fail with `MemoryLimitExceeded` when the results produced by the stages of one
+    /// selector add up to more than `bytes`, e.g. `tag ~.*` on a huge page.
+    pub fn memory_budget(mut self, bytes: usize) -> Self {
+        Arc::make_mut(&mut self.interpreter.options).memory_budget = Some(bytes);
+        // Cached results were computed without the budget, so stop sharing the cache
+        self.cache = Cache::default();
+        self
+    }
+
+    /// Cut texts and attribute values produced by text selectors to at most `bytes`,
+    /// including the `…[+N bytes]` marker they end with, so attributes embedding
+    /// megabytes of data do not bloat the results.
+    pub fn max_value_len(mut self, bytes: usize) -> Self {
+        Arc::make_mut(&mut self.interpreter.options).max_value_len = Some(bytes);
+        // Cached text results depend on this option, so stop sharing the cache
+        self.cache = Cache::default();
+        self
+    }
 
-        self.current_result = Some(self.cache.get(&cache_key).unwrap().clone());
+    /// Fail with `InvalidStringIndex` when a text function such as `@slice` gets indices
+    /// out of range or inside a multi-byte character, instead of adjusting them.
+    pub fn strict_functions(mut self, enabled: bool) -> Self {
+        Arc::make_mut(&mut self.interpreter.options).strict_functions = enabled;
+        // Cached results were computed in the other mode, so stop sharing the cache
+        self.cache = Cache::default();
+        self
+    }
+
+    /// Set the URL the document was retrieved from, so `@absolute` can turn relative
+    /// `href` and `src` values into absolute URLs. A `<base href>` in the document is
+    /// resolved against it and takes precedence.
+    pub fn with_base_url(mut self, url: &str) -> Self {
+        Arc::make_mut(&mut self.interpreter.options).base_url = Some(url.to_string());
+        // Cached text results depend on the base, so stop sharing the cache
+        self.cache = Cache::default();
+        self
+    }
+
+    /// Attach a value to the document, such as the URL it was fetched from, replacing
+    /// any earlier value of the same type.
+    pub fn set_ext<T: Any + Send + Sync>(mut self, value: T) -> Self {
+        Arc::make_mut(&mut self.interpreter.options).ext.insert(value);
+        self
+    }
+
+    /// The attached value of type `T`
+    pub fn get_ext<T: Any + Send + Sync>(&self) -> Option<&T> {
+        self.interpreter.options.ext.get()
+    }
+
+    /// Create a query over the same document, options and cache, without copying the
+    /// current selection.
+    fn view(&self) -> Self {
+        Query {
+            interpreter: self.interpreter.fork(),
+            current_result: None,
+            cache: Arc::clone(&self.cache),
+            audit: self.audit.clone(),
+        }
+    }
+
+    /// Query nodes using a selector.
+    pub fn query(mut self, selector: &str) -> Self {
+        let started = (SystemTime::now(), Instant::now());
+        let mut cache_hit = false;
+        let outcome = self.interpreter.parse_selector(selector).and_then(|ast| {
+            cached(&self.cache, cache_key(&ast), &mut cache_hit, || {
+                self.interpreter.select_parsed(&ast)
+            })
+        });
+        self.audit(selector, started, cache_hit, &outcome);
+        self.current_result = Some(outcome);
         self
     }
 
     /// Query nodes from the specified context.
     pub fn from(mut self, context: SelectionResult, selector: &str) -> Self {
-        let cache_key = format!("ctx:{}:{}", context_hash(&context), selector);
+        let started = (SystemTime::now(), Instant::now());
+        let mut cache_hit = false;
+        let outcome = self.interpreter.parse_selector(selector).and_then(|ast| {
+            let key = format!("ctx:{}:{}", context_hash(&context), cache_key(&ast));
+            cached(&self.cache, key, &mut cache_hit, || {
+                self.interpreter.evaluate(context.clone(), &ast)
+            })
+        });
+        self.audit(selector, started, cache_hit, &outcome);
+        self.current_result = Some(outcome);
+        self
+    }
 
-        if !self.cache.contains_key(&cache_key) {
-            let result = self.interpreter.select_from(&context, selector);
-            self.cache.insert(cache_key.clone(), result);
-        }
+    /// Record a selector run in the audit log, if it is enabled
+    fn audit(
+        &self,
+        selector: &str,
+        (started, timer): (SystemTime, Instant),
+        cache_hit: bool,
+        outcome: &Result<SelectionResult, InterpreterError>,
+    ) {
+        let Some(log) = &self.audit else {
+            return;
+        };
+
+        let (count, error) = match outcome {
+            Ok(result) => (result.count(), None),
+            Err(e) => (0, Some(e.clone())),
+        };
+        log.lock().unwrap_or_else(|e| e.into_inner()).push(AuditEntry {
+            selector: selector.to_string(),
+            started,
+            duration: timer.elapsed(),
+            count,
+            cache_hit,
+            error,
+        });
+    }
 
-        self.current_result = Some(self.cache.get(&cache_key).unwrap().clone());
+    /// Record every selector run from now on, with its start time, duration, result
+    /// count and whether it was answered from the cache. The log is shared with the
+    /// views handed out by `for_each` and with clones of this query.
+    pub fn with_audit_log(mut self) -> Self {
+        self.audit.get_or_insert_with(AuditLog::default);
         self
     }
 
+    /// Entries of the audit log in the order the selectors ran; empty when it is not
+    /// enabled.
+    pub fn audit_log(&self) -> Vec<AuditEntry> {
+        match &self.audit {
+            Some(log) => log.lock().unwrap_or_else(|e| e.into_inner()).clone(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Remove and return the entries of the audit log, so long-running sessions can
+    /// process it in batches without it growing without bound.
+    pub fn take_audit_log(&self) -> Vec<AuditEntry> {
+        match &self.audit {
+            Some(log) => std::mem::take(&mut *log.lock().unwrap_or_else(|e| e.into_inner())),
+            None => Vec::new(),
+        }
+    }
+
+    /// Why the current result is empty: the first stage of the selector that received
+    /// nodes or texts but produced none. Distinguishes, for example, a page without
+    /// anchors (`tag a`) from anchors that all lack an href (`href`). Shorthand for
+    /// [`SelectionResult::empty_reason`] on the current result.
+    pub fn empty_reason(&self) -> Option<&EmptyReason> {
+        match &self.current_result {
+            Some(Ok(result)) => result.empty_reason(),
+            _ => None,
+        }
+    }
+
     /// Execute a function for each selection result.
+    ///
+    /// Each call receives a lightweight view of this query that shares its document and
+    /// cache, so nested queries inside the loop copy neither.
     pub fn for_each<F>(self, mut f: F)
     where
         F: FnMut(Query, SelectionResult),
     {
-        if let Some(Ok(result)) = self.current_result.clone() {
+        if let Some(Ok(result)) = &self.current_result {
             for item in result.iter() {
-                f(self.clone(), item)
+                f(self.view(), item)
             }
         }
     }
@@ -128,30 +442,165 @@ impl Query {
         }
     }
 
-    /// Use the query results as the context for another query.
-    pub fn then(self, selector: &str) -> Self {
-        match self.current_result {
-            Some(Ok(ref result)) => {
-                let result_clone = result.clone();
-                self.from(result_clone, selector)
+    /// Title, description, canonical URL, OpenGraph and Twitter card properties of the
+    /// document.
+    pub fn metadata(&self) -> Metadata {
+        self.document().metadata()
+    }
+
+    /// Classify the document as regular content or a page not worth extracting from,
+    /// see `Document::page_class`.
+    pub fn page_class(&self) -> PageClass {
+        self.document().page_class()
+    }
+
+    /// Serialize the whole document back to HTML, independent of the executed queries.
+    pub fn to_html(&self, options: &SerializeOptions) -> String {
+        self.document().to_html(options)
+    }
+
+    /// Rewrite the URLs in `href`, `src` and `srcset` attributes and in `url()`
+    /// references of `style` attributes, e.g. to point a mirrored page at local copies.
+    ///
+    /// The document is shared with other queries, so the rewrite produces a new
+    /// document; the returned query runs against it with the same options, an empty
+    /// cache and no current result.
+    pub fn rewrite_urls<F: FnMut(&str) -> String>(self, rewrite: F) -> Self {
+        let document = self.document().rewrite_urls(rewrite);
+        let mut interpreter = Interpreter::with_document(document.root().clone());
+        interpreter.options = self.interpreter.options;
+
+        Query {
+            interpreter,
+            current_result: None,
+            cache: Cache::default(),
+            audit: self.audit,
+        }
+    }
+
+    /// Extract the links (`a` and `area` elements with an `href`) within the selected
+    /// nodes, or within the whole document if no query was executed, in document order.
+    pub fn links(self) -> InterpreterResult<Vec<Link>> {
+        let context = match self.current_result {
+            Some(result) => result?,
+            None => SelectionResult::with_nodes(vec![self.interpreter.document.clone()]),
+        };
+
+        let mut links = Vec::new();
+        for node in html::find_links(context.nodes()?)? {
+            let Some(href) = html::get_href(&node)? else {
+                continue;
+            };
+            let text = html::extract_text_with(&node, &self.interpreter.options)?;
+
+            links.push(Link {
+                href,
+                text: text.split_whitespace().collect::<Vec<_>>().join(" "),
+                rel: html::get_attribute(&node, "rel"),
+                title: html::get_attribute(&node, "title"),
+            });
+        }
+
+        Ok(links)
+    }
+
+    /// Format the query results as JSON.
+    pub fn json(self) -> InterpreterResult<String> {
+        self.result().map(|result| result.to_json())
+    }
+
+    /// Format the query results as CSV.
+    pub fn csv(self) -> InterpreterResult<String> {
+        self.result().map(|result| result.to_csv())
+    }
+
+    /// Run a selector and write its results to `writer` as newline-delimited JSON, one
+    /// object per item, returning the number of lines written. Like `stream`, a
+    /// trailing text selector (`... > text`) runs one node at a time and its texts are
+    /// written as they are extracted, so they are never all held in memory. Results are
+    /// not cached. Wrap unbuffered writers such as files in a `BufWriter`.
+    pub fn write_ndjson<W: Write>(&self, selector: &str, mut writer: W) -> InterpreterResult<usize> {
+        let mut interpreter = self.interpreter.fork();
+        let ast = interpreter.parse_selector(selector)?;
+
+        let lines = match split_trailing_text(&ast) {
+            Some((nodes, text)) => {
+                let mut lines = 0;
+                for node in interpreter.select_parsed(nodes)?.nodes()? {
+                    let context = SelectionResult::with_nodes(vec![node.clone()]);
+                    lines += interpreter.evaluate(context, text)?.write_ndjson(&mut writer)?;
+                }
+                lines
             }
-            Some(Err(ref e)) => {
-                let mut builder = self.clone();
-                builder.current_result = Some(Err(e.clone()));
-                builder
+            None => interpreter.select_parsed(&ast)?.write_ndjson(&mut writer)?,
+        };
+        writer.flush()?;
+
+        Ok(lines)
+    }
+
+    /// Use the query results as the context for another query.
+    pub fn then(mut self, selector: &str) -> Self {
+        match self.current_result.take() {
+            Some(Ok(result)) => self.from(result, selector),
+            Some(Err(e)) => {
+                self.current_result = Some(Err(e));
+                self
             }
             None => {
-                let mut builder = self.clone();
-                builder.current_result =
+                self.current_result =
                     Some(Err(InterpreterError::execution_error("No queries were executed.")));
-                builder
+                self
             }
         }
     }
 
+    /// Run one selector per field and pair the extracted texts by position into records.
+    ///
+    /// Every selector must yield texts, and the same number of them.
+    pub fn records(
+        mut self,
+        fields: &[(&str, &str)],
+    ) -> InterpreterResult<Vec<HashMap<String, String>>> {
+        let mut columns = Vec::with_capacity(fields.len());
+
+        for (name, selector) in fields {
+            self = self.query(selector);
+            let texts = match &self.current_result {
+                Some(Ok(result)) => result.texts()?.clone(),
+                Some(Err(e)) => return Err(e.clone()),
+                None => Vec::new(),
+            };
+            columns.push((*name, texts));
+        }
+
+        zip_records(columns)
+    }
+
+    /// Run several selectors against the document and return their results in order.
+    ///
+    /// The selectors share this query's document, options and cache, so one that
+    /// repeats an earlier selector is not evaluated again. A failing selector does not
+    /// stop the others.
+    pub fn select_all(&self, selectors: &[&str]) -> Vec<InterpreterResult<SelectionResult>> {
+        selectors
+            .iter()
+            .map(|selector| self.view().query(selector).result())
+            .collect()
+    }
+
+    /// Run a selector that yields texts and pair each text with the element it came
+    /// from, see `Interpreter::select_with_nodes`. Results are not cached.
+    pub fn texts_with_nodes(
+        &self,
+        selector: &str,
+    ) -> InterpreterResult<Vec<(String, super::NodeHandle)>> {
+        self.interpreter.select_with_nodes(selector)
+    }
+
     /// Clear cache.
     pub fn clear_cache(mut self) -> Self {
-        self.cache.clear();
+        self.cache = Cache::default();
         self
     }
 
@@ -170,12 +619,332 @@ impl Query {
             _ => true,
         }
     }
+
+    /// Evaluate a selector lazily, yielding one node or text at a time as a
+    /// `futures_core::Stream` (the returned value is also an `Iterator`).
+    ///
+    /// When the selector ends in a text selector (`... > text`), texts are extracted
+    /// one node at a time as the consumer polls, so a slow consumer such as a database
+    /// writer holds back extraction instead of letting results pile up. Other selectors
+    /// are evaluated in full on the first poll. Results are not cached.
+    #[cfg(feature = "async")]
+    pub fn stream(self, selector: &str) -> QueryStream {
+        let interpreter = self.interpreter.fork();
+        let state = match interpreter.parse_selector(selector) {
+            Ok(ast) => StreamState::Pending(ast),
+            Err(e) => StreamState::Failed(Some(e)),
+        };
+
+        QueryStream { interpreter, state }
+    }
+}
+
+/// Lazily evaluated query results, returned by `Query::stream`. Each item holds a
+/// single node or text, like the items passed to `Query::for_each`.
+#[cfg(feature = "async")]
+pub struct QueryStream {
+    interpreter: Interpreter,
+    state: StreamState,
+}
+
+#[cfg(feature = "async")]
+enum StreamState {
+    /// Not evaluated yet
+    Pending(Node),
+
+    /// Yielding the items of a fully evaluated result
+    Items(super::interpreter::result::SelectionResultIter),
+
+    /// Applying a trailing text selector to the remaining nodes one at a time
+    PerNode {
+        nodes: std::vec::IntoIter<super::NodeHandle>,
+        text: Node,
+        current: Option<super::interpreter::result::SelectionResultIter>,
+    },
+
+    /// Evaluation failed; the error is yielded once
+    Failed(Option<InterpreterError>),
+}
+
+#[cfg(feature = "async")]
+impl Iterator for QueryStream {
+    type Item = InterpreterResult<SelectionResult>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match &mut self.state {
+                StreamState::Pending(ast) => {
+                    let ast = ast.clone();
+                    self.state = match self.start(ast) {
+                        Ok(state) => state,
+                        Err(e) => StreamState::Failed(Some(e)),
+                    };
+                }
+                StreamState::Items(items) => return items.next().map(Ok),
+                StreamState::PerNode { nodes, text, current } => {
+                    if let Some(item) = current.as_mut().and_then(|items| items.next()) {
+                        return Some(Ok(item));
+                    }
+                    let node = nodes.next()?;
+                    let context = SelectionResult::with_nodes(vec![node]);
+                    match self.interpreter.evaluate(context, text) {
+                        Ok(result) => *current = Some(result.iter()),
+                        Err(e) => {
+                            self.state = StreamState::Failed(None);
+                            return Some(Err(e));
+                        }
+                    }
+                }
+                StreamState::Failed(error) => return error.take().map(Err),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl QueryStream {
+    fn start(&mut self, ast: Node) -> InterpreterResult<StreamState> {
+        if let Some((nodes, text)) = split_trailing_text(&ast) {
+            let nodes = self.interpreter.select_parsed(nodes)?.nodes()?.clone();
+            return Ok(StreamState::PerNode {
+                nodes: nodes.into_iter(),
+                text: text.clone(),
+                current: None,
+            });
+        }
+
+        Ok(StreamState::Items(self.interpreter.select_parsed(&ast)?.iter()))
+    }
+}
+
+/// Split a pipeline ending in a text selector into the part selecting the nodes and the
+/// text selector, which can then run on one node at a time. Anything applied after the
+/// text selector (indexes, functions) may look at all texts at once, so such pipelines
+/// are not split.
+fn split_trailing_text(ast: &Node) -> Option<(&Node, &Node)> {
+    use crate::parser::SelectorNode;
+
+    if let Node::Pipeline(left, right) = ast
+        && let Node::Selector(selector) = right.as_ref()
+        && let SelectorNode::TextSelector(_) = selector.as_ref()
+    {
+        return Some((left, right));
+    }
+    None
+}
+
+#[cfg(feature = "async")]
+impl futures_core::Stream for QueryStream {
+    type Item = InterpreterResult<SelectionResult>;
+
+    fn poll_next(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        std::task::Poll::Ready(self.get_mut().next())
+    }
+}
+
+/// Configures how a document is parsed and evaluated before building a `Query`.
+#[derive(Clone)]
+pub struct QueryBuilder {
+    /// html5ever parser options
+    parse_opts: ParseOpts,
+
+    /// Fail on malformed markup
+    strict: bool,
+
+    /// Record attributes dropped as duplicates
+    keep_duplicate_attrs: bool,
+
+    /// Evaluation options
+    options: InterpreterOptions,
+}
+
+impl Default for QueryBuilder {
+    fn default() -> Self {
+        QueryBuilder {
+            parse_opts: html::default_parse_opts(),
+            strict: false,
+            keep_duplicate_attrs: false,
+            options: InterpreterOptions::default(),
+        }
+    }
+}
+
+impl QueryBuilder {
+    /// Keep the doctype node in the document tree (dropped by default).
+    pub fn keep_doctype(mut self, keep: bool) -> Self {
+        self.parse_opts.tree_builder.drop_doctype = !keep;
+        self
+    }
+
+    /// Parse as if scripting were enabled (the default), which keeps `<noscript>` content
+    /// as raw text instead of elements.
+    pub fn scripting_enabled(mut self, enabled: bool) -> Self {
+        self.parse_opts.tree_builder.scripting_enabled = enabled;
+        self
+    }
+
+    /// Report detailed parser messages in `Query::warnings`, at some cost in speed.
+    pub fn exact_errors(mut self, exact: bool) -> Self {
+        self.parse_opts.tokenizer.exact_errors = exact;
+        self.parse_opts.tree_builder.exact_errors = exact;
+        self
+    }
+
+    /// Replace the html5ever parser options entirely.
+    pub fn parse_opts(mut self, opts: ParseOpts) -> Self {
+        self.parse_opts = opts;
+        self
+    }
+
+    /// Fail instead of repairing malformed markup.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Record the attributes the parser drops when an element repeats an attribute name
+    /// (only the first occurrence is kept), available through
+    /// `NodeHandle::duplicate_attrs`. The whole input is buffered to rescan it.
+    pub fn keep_duplicate_attrs(mut self, keep: bool) -> Self {
+        self.keep_duplicate_attrs = keep;
+        self
+    }
+
+    /// Render `alt` text of images and the value of inputs when extracting element text.
+    pub fn void_element_text(mut self, enabled: bool) -> Self {
+        self.options.void_element_text = enabled;
+        self
+    }
+
+    /// Put block elements and `<br>` on separate lines when extracting element text.
+    pub fn block_text(mut self, enabled: bool) -> Self {
+        self.options.block_text = enabled;
+        self
+    }
+
+    /// Limit the bytes of intermediate results one selector may produce.
+    pub fn memory_budget(mut self, bytes: usize) -> Self {
+        self.options.memory_budget = Some(bytes);
+        self
+    }
+
+    /// Cut values produced by text selectors to at most `bytes`.
+    pub fn max_value_len(mut self, bytes: usize) -> Self {
+        self.options.max_value_len = Some(bytes);
+        self
+    }
+
+    /// Report out-of-range and mid-character indices in text functions as errors.
+    pub fn strict_functions(mut self, enabled: bool) -> Self {
+        self.options.strict_functions = enabled;
+        self
+    }
+
+    /// Set the URL the document was retrieved from, for `@absolute`.
+    pub fn base_url(mut self, url: &str) -> Self {
+        self.options.base_url = Some(url.to_string());
+        self
+    }
+
+    /// Decode an additional named entity, given without `&` and `;`, in extracted text.
+    #[cfg(feature = "entities")]
+    pub fn entity(mut self, name: &str, value: &str) -> Self {
+        self.options.entities.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// Run an extra validation pass over every selector, after the built-in rules.
+    pub fn validation_pass(mut self, pass: impl ValidationPass + 'static) -> Self {
+        let validator = self.options.validator.take().unwrap_or_default();
+        self.options.validator = Some(validator.with_pass(pass));
+        self
+    }
+
+    /// Pin selectors to a grammar version, rejecting constructs added after it.
+    pub fn syntax_version(mut self, version: SyntaxVersion) -> Self {
+        self.options.syntax_version = version;
+        self
+    }
+
+    /// Parse an HTML string and build the query.
+    pub fn build(&self, html: &str) -> InterpreterResult<Query> {
+        self.build_from_reader(html.as_bytes())
+    }
+
+    /// Parse HTML read incrementally from a reader and build the query.
+    pub fn build_from_reader<R: Read>(&self, reader: R) -> InterpreterResult<Query> {
+        Ok(self.on(&self.parse_from_reader(reader)?))
+    }
+
+    /// Parse HTML read incrementally from a reader with the configured parser options,
+    /// so the document can be shared by several queries.
+    pub fn parse_from_reader<R: Read>(&self, mut reader: R) -> InterpreterResult<Document> {
+        let root = if self.keep_duplicate_attrs {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes)?;
+            let html = String::from_utf8_lossy(&bytes);
+            html::parse_html_keeping_duplicates(&html, self.parse_opts.clone())?
+        } else {
+            html::parse_html_with(&mut reader, self.parse_opts.clone())?
+        };
+        let document = Document::from_root(root);
+        if self.strict {
+            document.ensure_well_formed()?;
+        }
+
+        Ok(document)
+    }
+
+    /// Create a query with the configured evaluation options over a parsed document.
+    pub fn on(&self, document: &Document) -> Query {
+        let mut query = Query::on(document);
+        query.interpreter.options = Arc::new(InterpreterOptions {
+            ext: document.ext().clone(),
+            ..self.options.clone()
+        });
+        query
+    }
+}
+
+/// Cache key of a parsed selector. Keys are derived from the syntax tree rather than the
+/// selector string, so selectors differing only in whitespace or redundant parentheses
+/// share an entry.
+fn cache_key(ast: &Node) -> String {
+    format!("{:?}", ast)
+}
+
+/// Look up a cached result, computing and storing it on a miss. `hit` is set when the
+/// result came from the cache.
+fn cached<F>(
+    cache: &Cache,
+    key: String,
+    hit: &mut bool,
+    compute: F,
+) -> Result<SelectionResult, InterpreterError>
+where
+    F: FnOnce() -> Result<SelectionResult, InterpreterError>,
+{
+    if let Some(result) = cache.lock().unwrap_or_else(|e| e.into_inner()).get(&key) {
+        *hit = true;
+        return result.clone();
+    }
+
+    // Evaluate without holding the lock; concurrent misses only compute twice
+    let result = compute();
+    cache
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(key, result.clone());
+    result
 }
 
 /// Generate the hash value of the context for use as a cache key.
 fn context_hash(context: &SelectionResult) -> String {
     match context {
-        SelectionResult::Nodes(nodes) => {
+        SelectionResult::Nodes(nodes, _) => {
             if nodes.is_empty() {
                 "empty_nodes".to_string()
             } else if nodes.len() <= 3 {
@@ -185,7 +954,7 @@ fn context_hash(context: &SelectionResult) -> String {
                 format!("{}+{}", nodes[0].id(), nodes.len())
             }
         }
-        SelectionResult::Texts(texts) => {
+        SelectionResult::Texts(texts, _) => {
             if texts.is_empty() {
                 "empty_texts".to_string()
             } else if texts.len() <= 3 {
@@ -211,3 +980,31 @@ fn context_hash(context: &SelectionResult) -> String {
         }
     }
 }
+
+/// Pair parallel columns of texts by position into records keyed by column name.
+///
+/// Fails with `ColumnLengthMismatch` unless all columns have the same length.
+pub fn zip_records<K: Into<String>>(
+    columns: Vec<(K, Vec<String>)>,
+) -> InterpreterResult<Vec<HashMap<String, String>>> {
+    let columns: Vec<(String, Vec<String>)> =
+        columns.into_iter().map(|(name, texts)| (name.into(), texts)).collect();
+
+    let Some((_, first)) = columns.first() else {
+        return Ok(Vec::new());
+    };
+    let len = first.len();
+
+    if let Some((name, texts)) = columns.iter().find(|(_, texts)| texts.len() != len) {
+        return Err(InterpreterError::ColumnLengthMismatch(name.clone(), texts.len(), len));
+    }
+
+    let mut records = vec![HashMap::with_capacity(columns.len()); len];
+    for (name, texts) in columns {
+        for (record, text) in records.iter_mut().zip(texts) {
+            record.insert(name.clone(), text);
+        }
+    }
+
+    Ok(records)
+}
@@ -0,0 +1,250 @@
+// report.rs - Standalone HTML pages of query results
+//
+// An `HtmlReport` runs selectors against queries and renders what they produced as a
+// single HTML page with inline styles, for sharing extraction results with people who
+// do not run the code: a summary table of counts, then each selector's texts or the
+// source of its nodes with highlighted tags and attributes. Empty selections show why
+// they came out empty, and failing selectors show their error.
+
+use std::fmt::Write;
+
+use crate::Query;
+use crate::interpreter::serialize::{self, Layout, SerializeOptions};
+use crate::interpreter::{EmptyReason, InterpreterResult, SelectionResult};
+
+/// Longest node source shown, in characters
+const MAX_SNIPPET_CHARS: usize = 4000;
+
+const STYLE: &str = "\
+body{font-family:system-ui,sans-serif;margin:2em;color:#222}\
+table{border-collapse:collapse}\
+th,td{border:1px solid #ccc;padding:.3em .6em;text-align:left}\
+code,pre{font-family:ui-monospace,monospace}\
+pre{background:#f6f8fa;padding:.6em;overflow-x:auto;white-space:pre-wrap}\
+li{margin:.3em 0}\
+.ok{color:#1a7f37}.empty{color:#9a6700}.error{color:#cf222e}\
+.t{color:#116329}.a{color:#0550ae}.v{color:#0a3069}.c{color:#6e7781}";
+
+/// Results of several selectors, rendered as one page
+#[derive(Debug, Clone, Default)]
+pub struct HtmlReport {
+    title: String,
+    sections: Vec<Section>,
+}
+
+#[derive(Debug, Clone)]
+struct Section {
+    selector: String,
+    outcome: InterpreterResult<SelectionResult>,
+    empty_reason: Option<EmptyReason>,
+}
+
+impl HtmlReport {
+    pub fn new(title: &str) -> Self {
+        HtmlReport {
+            title: title.to_string(),
+            sections: Vec::new(),
+        }
+    }
+
+    /// Run a selector on a query and add its results.
+    pub fn query(mut self, query: &Query, selector: &str) -> Self {
+        let query = query.clone().query(selector);
+        let empty_reason = query.empty_reason().cloned();
+
+        self.sections.push(Section {
+            selector: selector.to_string(),
+            outcome: query.result(),
+            empty_reason,
+        });
+        self
+    }
+
+    /// Render the page.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let title = escape(&self.title);
+
+        let _ = write!(
+            out,
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>{}</style>\n</head>\n<body>\n<h1>{}</h1>\n",
+            title, STYLE, title
+        );
+
+        out.push_str(
+            "<table>\n<tr><th>#</th><th>Selector</th><th>Results</th><th>Status</th></tr>\n",
+        );
+        for (i, section) in self.sections.iter().enumerate() {
+            let count = section.outcome.as_ref().map_or(0, SelectionResult::count);
+            let _ = writeln!(
+                out,
+                "<tr><td><a href=\"#s{}\">{}</a></td><td><code>{}</code></td><td>{}</td><td>{}</td></tr>",
+                i + 1,
+                i + 1,
+                escape(&section.selector),
+                count,
+                section.status()
+            );
+        }
+        out.push_str("</table>\n");
+
+        for (i, section) in self.sections.iter().enumerate() {
+            let _ = writeln!(
+                out,
+                "<h2 id=\"s{}\">{}. <code>{}</code></h2>\n<p>{}</p>",
+                i + 1,
+                i + 1,
+                escape(&section.selector),
+                section.status()
+            );
+            match &section.outcome {
+                Ok(SelectionResult::Texts(texts, _)) if !texts.is_empty() => {
+                    out.push_str("<ol>\n");
+                    for text in texts {
+                        if text.is_empty() {
+                            out.push_str("<li><em>(empty)</em></li>\n");
+                        } else {
+                            let _ = writeln!(out, "<li><pre>{}</pre></li>", escape(text));
+                        }
+                    }
+                    out.push_str("</ol>\n");
+                }
+                Ok(SelectionResult::Nodes(nodes, _)) if !nodes.is_empty() => {
+                    let options = SerializeOptions {
+                        layout: Layout::Pretty,
+                        ..Default::default()
+                    };
+                    out.push_str("<ol>\n");
+                    for node in nodes {
+                        out.push_str("<li><pre>");
+                        highlight(&snippet(serialize::to_html(node, &options)), &mut out);
+                        out.push_str("</pre></li>\n");
+                    }
+                    out.push_str("</ol>\n");
+                }
+                _ => {}
+            }
+        }
+
+        out.push_str("</body>\n</html>\n");
+        out
+    }
+}
+
+impl Section {
+    fn status(&self) -> String {
+        match &self.outcome {
+            Err(e) => format!(
+                "<span class=\"error\">Error: {}</span>",
+                escape(&e.to_string())
+            ),
+            Ok(result) if result.count() == 0 => match &self.empty_reason {
+                Some(reason) => format!(
+                    "<span class=\"empty\">Empty: {}</span>",
+                    escape(&reason.to_string())
+                ),
+                None => "<span class=\"empty\">Empty</span>".to_string(),
+            },
+            Ok(result) => format!("<span class=\"ok\">{} result(s)</span>", result.count()),
+        }
+    }
+}
+
+/// Shorten node source to `MAX_SNIPPET_CHARS`
+fn snippet(source: String) -> String {
+    match source.char_indices().nth(MAX_SNIPPET_CHARS) {
+        Some((end, _)) => format!("{}\n…", &source[..end]),
+        None => source,
+    }
+}
+
+/// Write serialized HTML escaped for display, with tags, attribute names, attribute
+/// values and comments wrapped in spans for colouring
+fn highlight(source: &str, out: &mut String) {
+    let mut rest = source;
+
+    while !rest.is_empty() {
+        if rest.starts_with("<!--") {
+            let end = rest.find("-->").map_or(rest.len(), |i| i + 3);
+            let _ = write!(out, "<span class=\"c\">{}</span>", escape(&rest[..end]));
+            rest = &rest[end..];
+        } else if rest.starts_with('<')
+            && rest[1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '/' || c == '!')
+        {
+            let end = rest.find('>').map_or(rest.len(), |i| i + 1);
+            highlight_tag(&rest[..end], out);
+            let name = rest[1..end]
+                .split(|c: char| c.is_ascii_whitespace() || c == '>' || c == '/')
+                .next()
+                .unwrap_or_default();
+            rest = &rest[end..];
+
+            // Script and style contents are not markup
+            if matches!(name, "script" | "style") {
+                let close = rest.find(&format!("</{}", name)).unwrap_or(rest.len());
+                out.push_str(&escape(&rest[..close]));
+                rest = &rest[close..];
+            }
+        } else {
+            // Skip the first character, which may be a `<` that does not start a tag
+            let first = rest.chars().next().map_or(0, char::len_utf8);
+            let end = rest[first..].find('<').map_or(rest.len(), |i| i + first);
+            out.push_str(&escape(&rest[..end]));
+            rest = &rest[end..];
+        }
+    }
+}
+
+/// Highlight one tag as written by the serializer: `<name attr="value" ...>`
+fn highlight_tag(tag: &str, out: &mut String) {
+    let name_end = tag
+        .find(|c: char| c.is_ascii_whitespace() || c == '>')
+        .unwrap_or(tag.len());
+    let _ = write!(out, "<span class=\"t\">{}</span>", escape(&tag[..name_end]));
+    let mut rest = &tag[name_end..];
+
+    loop {
+        let trimmed = rest.trim_start();
+        out.push_str(&rest[..rest.len() - trimmed.len()]);
+        rest = trimmed;
+
+        if rest.is_empty() || rest.starts_with(['>', '/']) {
+            let _ = write!(out, "<span class=\"t\">{}</span>", escape(rest));
+            return;
+        }
+
+        // At least one character, so text mistaken for a tag cannot stall the loop
+        let name_end = rest
+            .find(|c: char| c == '=' || c.is_ascii_whitespace() || c == '>')
+            .unwrap_or(rest.len())
+            .max(1);
+        let _ = write!(
+            out,
+            "<span class=\"a\">{}</span>",
+            escape(&rest[..name_end])
+        );
+        rest = &rest[name_end..];
+
+        // Values are always double quoted, with quotes inside escaped
+        if let Some(value) = rest.strip_prefix("=\"") {
+            let end = value.find('"').map_or(value.len(), |i| i + 1);
+            let _ = write!(out, "=<span class=\"v\">\"{}</span>", escape(&value[..end]));
+            rest = &value[end..];
+        }
+    }
+}
+
+/// Escape text for HTML content and attribute values
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
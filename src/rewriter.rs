@@ -0,0 +1,326 @@
+// rewriter.rs - Streaming extraction and rewriting on lol_html
+//
+// Like `stream`, evaluates selectors without building a DOM, but on lol_html, which can
+// also write the document back out with matched elements modified. Selectors use the
+// same syntax and are compiled to CSS for lol_html: pipelines of non-regex `class`,
+// `id`, `tag` and `attr` selectors, each matching descendants of the previous one,
+// optionally ending with `text`, `href`, `src`, `#name` or `?name`. The text of an
+// element includes the text of matched elements nested within it; those are not
+// reported separately.
+
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::rc::Rc;
+
+use lol_html::html_content::{TextChunk, TextType};
+use lol_html::{DocumentContentHandlers, ElementContentHandlers, HtmlRewriter, Selector, Settings};
+
+pub use lol_html::html_content::Element;
+
+use crate::interpreter::attrs::decode_entities;
+use crate::interpreter::dom::Attribute;
+use crate::interpreter::{InterpreterError, InterpreterResult};
+use crate::parse;
+use crate::parser::{ElementNode, SelectorNode, TextNode};
+use crate::stream::{StreamItem, flatten_pipeline};
+
+/// Size of the chunks read from the input
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Text being collected for a matched element
+#[derive(Default)]
+struct Capture {
+    /// Number of open matched elements
+    depth: usize,
+
+    /// Raw text of the outermost one, with entities still encoded
+    raw: String,
+}
+
+/// Stream the document from a reader and call `callback` for every match
+pub fn select<R, F>(mut reader: R, selector: &str, mut callback: F) -> InterpreterResult<()>
+where
+    R: Read,
+    F: FnMut(StreamItem),
+{
+    let (css, text) = compile(selector)?;
+
+    // End tag handlers must be 'static, so matches are queued and handed to the
+    // callback after each chunk
+    let items: Rc<RefCell<VecDeque<StreamItem>>> = Rc::default();
+    let capture: Rc<RefCell<Capture>> = Rc::default();
+
+    let element_handler = {
+        let items = Rc::clone(&items);
+        let capture = Rc::clone(&capture);
+        ElementContentHandlers::default().element(move |el: &mut Element| {
+            let attr = |name: &str| el.get_attribute(name).map(|v| decode_entities(&v));
+            let item = match &text {
+                None => Some(StreamItem::Element {
+                    name: el.tag_name(),
+                    attrs: el
+                        .attributes()
+                        .iter()
+                        .map(|a| Attribute {
+                            name: a.name(),
+                            value: decode_entities(&a.value()),
+                        })
+                        .collect(),
+                }),
+                Some(TextNode::Href) => attr("href").map(StreamItem::Text),
+                Some(TextNode::Src) => attr("src").map(StreamItem::Text),
+                Some(TextNode::AttrValue(name, _)) => attr(name).map(StreamItem::Text),
+                Some(TextNode::AttrPresent(name, _)) => {
+                    Some(StreamItem::Text(el.has_attribute(name).to_string()))
+                }
+                Some(TextNode::Text) => {
+                    start_capture(el, &capture, &items);
+                    None
+                }
+                // Rejected when compiling the selector
+                Some(TextNode::Markdown)
+                | Some(TextNode::Links)
+                | Some(TextNode::TagPath)
+                | Some(TextNode::OwnText)
+                | Some(TextNode::Value)
+                | Some(TextNode::Data) => None,
+            };
+
+            if let Some(item) = item {
+                items.borrow_mut().push_back(item);
+            }
+            Ok(())
+        })
+    };
+
+    let text_handler = {
+        let capture = Rc::clone(&capture);
+        DocumentContentHandlers::default().text(move |chunk: &mut TextChunk| {
+            let mut capture = capture.borrow_mut();
+            if capture.depth > 0 {
+                match chunk.text_type() {
+                    TextType::Data | TextType::RCData => capture.raw.push_str(chunk.as_str()),
+                    // Raw text is literal, so protect it from being decoded
+                    _ => capture.raw.push_str(&chunk.as_str().replace('&', "&amp;")),
+                }
+            }
+            Ok(())
+        })
+    };
+
+    let settings = Settings {
+        element_content_handlers: vec![(Cow::Owned(css), element_handler)],
+        document_content_handlers: vec![text_handler],
+        ..Settings::new()
+    };
+    let mut rewriter = HtmlRewriter::new(settings, |_: &[u8]| {});
+
+    let mut buffer = vec![0; CHUNK_SIZE];
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        rewriter.write(&buffer[..read]).map_err(rewriting_error)?;
+        items.borrow_mut().drain(..).for_each(&mut callback);
+    }
+
+    rewriter.end().map_err(rewriting_error)?;
+    items.borrow_mut().drain(..).for_each(&mut callback);
+
+    Ok(())
+}
+
+/// Stream an in-memory document and call `callback` for every match
+pub fn select_str<F>(html: &str, selector: &str, callback: F) -> InterpreterResult<()>
+where
+    F: FnMut(StreamItem),
+{
+    select(html.as_bytes(), selector, callback)
+}
+
+/// Copy the document from `reader` to `writer`, letting `edit` modify every element the
+/// selector matches: attributes, content, or the element itself.
+pub fn rewrite<R, W, F>(mut reader: R, mut writer: W, selector: &str, mut edit: F) -> InterpreterResult<()>
+where
+    R: Read,
+    W: Write,
+    F: FnMut(&mut Element),
+{
+    let (css, text) = compile(selector)?;
+    if text.is_some() {
+        return Err(InterpreterError::execution_error(
+            "A rewrite selector must select elements, not text",
+        ));
+    }
+
+    // The output sink cannot fail, so the first write error is kept and reported
+    let mut write_error = None;
+    let settings = Settings {
+        element_content_handlers: vec![(
+            Cow::Owned(css),
+            ElementContentHandlers::default().element(|el: &mut Element| {
+                edit(el);
+                Ok(())
+            }),
+        )],
+        ..Settings::new()
+    };
+    let mut rewriter = HtmlRewriter::new(settings, |chunk: &[u8]| {
+        if write_error.is_none()
+            && let Err(e) = writer.write_all(chunk)
+        {
+            write_error = Some(e);
+        }
+    });
+
+    let mut buffer = vec![0; CHUNK_SIZE];
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        rewriter.write(&buffer[..read]).map_err(rewriting_error)?;
+    }
+    rewriter.end().map_err(rewriting_error)?;
+
+    match write_error {
+        Some(e) => Err(e.into()),
+        None => Ok(()),
+    }
+}
+
+/// Rewrite an in-memory document
+pub fn rewrite_str<F>(html: &str, selector: &str, edit: F) -> InterpreterResult<String>
+where
+    F: FnMut(&mut Element),
+{
+    let mut output = Vec::with_capacity(html.len());
+    rewrite(html.as_bytes(), &mut output, selector, edit)?;
+    Ok(String::from_utf8_lossy(&output).into_owned())
+}
+
+/// Start collecting the text of a matched element, unless it is nested in another one
+fn start_capture(
+    el: &mut Element,
+    capture: &Rc<RefCell<Capture>>,
+    items: &Rc<RefCell<VecDeque<StreamItem>>>,
+) {
+    let Some(handlers) = el.end_tag_handlers() else {
+        // Void elements have no text
+        if capture.borrow().depth == 0 {
+            items.borrow_mut().push_back(StreamItem::Text(String::new()));
+        }
+        return;
+    };
+
+    capture.borrow_mut().depth += 1;
+
+    let capture = Rc::clone(capture);
+    let items = Rc::clone(items);
+    handlers.push(Box::new(move |_| {
+        let mut capture = capture.borrow_mut();
+        capture.depth -= 1;
+        if capture.depth == 0 {
+            let raw = std::mem::take(&mut capture.raw);
+            items.borrow_mut().push_back(StreamItem::Text(decode_entities(&raw)));
+        }
+        Ok(())
+    }));
+}
+
+/// Compile a selector to a CSS selector for lol_html and the optional text selector
+fn compile(selector: &str) -> InterpreterResult<(Selector, Option<TextNode>)> {
+    let ast = parse(selector).map_err(|e| InterpreterError::ParserError(e.to_string()))?;
+
+    let mut steps = Vec::new();
+    flatten_pipeline(&ast, &mut steps)?;
+
+    let mut css = Vec::new();
+    let mut text = None;
+
+    for (i, step) in steps.iter().enumerate() {
+        match step {
+            SelectorNode::ElementSelector(elem) => css.push(css_step(elem)?),
+            SelectorNode::TextSelector(
+                node @ (TextNode::Markdown
+                | TextNode::Links
+                | TextNode::TagPath
+                | TextNode::OwnText
+                | TextNode::Value
+                | TextNode::Data),
+            ) => {
+                return Err(InterpreterError::execution_error(format!(
+                    "`{}` needs the element tree and is not supported by the lol_html backend",
+                    node
+                )));
+            }
+            SelectorNode::TextSelector(TextNode::AttrValue(_, true) | TextNode::AttrPresent(_, true)) => {
+                return Err(InterpreterError::execution_error(
+                    "The lol_html backend does not support regex attribute names",
+                ));
+            }
+            SelectorNode::TextSelector(node) if i == steps.len() - 1 => text = Some(node.clone()),
+            SelectorNode::TextSelector(_) => {
+                return Err(InterpreterError::execution_error(
+                    "Text selectors must come last in a streaming selector",
+                ));
+            }
+        }
+    }
+
+    if css.is_empty() {
+        return Err(InterpreterError::execution_error(
+            "A streaming selector needs at least one element selector",
+        ));
+    }
+
+    let css = css.join(" ");
+    let compiled = css.parse().map_err(|e| {
+        InterpreterError::execution_error(format!("Cannot compile `{}` for lol_html: {}", css, e))
+    })?;
+
+    Ok((compiled, text))
+}
+
+/// CSS for one element selector
+fn css_step(elem: &ElementNode) -> InterpreterResult<String> {
+    match elem {
+        ElementNode::Tag(name, false) => Ok(css_identifier(name)),
+        ElementNode::Class(name, false) => Ok(format!(".{}", css_identifier(name))),
+        ElementNode::Id(name, false) => Ok(format!("#{}", css_identifier(name))),
+        ElementNode::Attr(name, None, false) => Ok(format!("[{}]", css_identifier(name))),
+        ElementNode::Attr(name, Some(value), false) => Ok(format!(
+            "[{}=\"{}\"]",
+            css_identifier(name),
+            value.replace('\\', "\\\\").replace('"', "\\\"")
+        )),
+        _ => Err(InterpreterError::execution_error(format!(
+            "The lol_html backend does not support the {} selector",
+            elem
+        ))),
+    }
+}
+
+/// Escape a name for use as a CSS identifier
+fn css_identifier(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for (i, c) in name.chars().enumerate() {
+        if i == 0 && c.is_ascii_digit() {
+            // A leading digit can only be written as a code point escape
+            escaped.push_str(&format!("\\{:x} ", c as u32));
+        } else if c.is_ascii_alphanumeric() || c == '-' || c == '_' || !c.is_ascii() {
+            escaped.push(c);
+        } else {
+            escaped.push('\\');
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+fn rewriting_error(e: lol_html::errors::RewritingError) -> InterpreterError {
+    InterpreterError::execution_error(format!("lol_html failed: {}", e))
+}
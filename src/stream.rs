@@ -0,0 +1,427 @@
+// stream.rs - Streaming selection without building a DOM
+//
+// Evaluates simple selectors directly against html5ever's tokenizer output, so huge
+// documents can be scanned in constant memory (apart from the open-element stack).
+//
+// Supported selectors are pipelines of `class`, `id`, `tag` and `attr` selectors,
+// optionally ending with one of the text selectors `text`, `owntext`, `href`, `src`,
+// `data`, `#name`, `?name` or `tagpath`. Tag paths list the open elements as written, since the
+// tokenizer does not add the implied `html` and `body` elements.
+// Unlike the DOM interpreter, each element is reported at most once, even when it is
+// reachable through several matching ancestors.
+
+use std::io::Read;
+
+use html5ever::tendril::StrTendril;
+use html5ever::tokenizer::{
+    BufferQueue, EndTag, StartTag, Tag, Token, TokenSink, TokenSinkResult, Tokenizer,
+    TokenizerOpts, states::RawKind,
+};
+use crate::pattern::Pattern;
+
+use crate::interpreter::dom::Attribute;
+use crate::interpreter::{InterpreterError, InterpreterResult};
+use crate::parse;
+use crate::parser::{ElementNode, Node, SelectorNode, TextNode};
+
+/// Item emitted for each match
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamItem {
+    /// A matched element (selector without a text selector)
+    Element { name: String, attrs: Vec<Attribute> },
+
+    /// An extracted text value
+    Text(String),
+}
+
+/// Size of the chunks read from the input
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Elements that never have content or an end tag
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param",
+    "source", "track", "wbr",
+];
+
+/// Stream the document from a reader and call `callback` for every match
+pub fn select<R, F>(mut reader: R, selector: &str, callback: F) -> InterpreterResult<()>
+where
+    R: Read,
+    F: FnMut(StreamItem),
+{
+    let sink = MatchSink::new(StreamSelector::compile(selector)?, callback);
+    let mut tokenizer = Tokenizer::new(sink, TokenizerOpts::default());
+    let mut queue = BufferQueue::default();
+
+    let mut buffer = vec![0; CHUNK_SIZE];
+    // Bytes of a UTF-8 sequence split across two chunks
+    let mut pending: Vec<u8> = Vec::new();
+
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+
+        pending.extend_from_slice(&buffer[..read]);
+        let valid = match std::str::from_utf8(&pending) {
+            Ok(_) => pending.len(),
+            // An incomplete sequence at the end is kept for the next chunk
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(_) => pending.len(),
+        };
+
+        let chunk = String::from_utf8_lossy(&pending[..valid]).into_owned();
+        pending.drain(..valid);
+
+        queue.push_back(StrTendril::from_slice(&chunk));
+        let _ = tokenizer.feed(&mut queue);
+    }
+
+    if !pending.is_empty() {
+        queue.push_back(StrTendril::from_slice(&String::from_utf8_lossy(&pending)));
+        let _ = tokenizer.feed(&mut queue);
+    }
+
+    tokenizer.end();
+    Ok(())
+}
+
+/// Stream an in-memory document and call `callback` for every match
+pub fn select_str<F>(html: &str, selector: &str, callback: F) -> InterpreterResult<()>
+where
+    F: FnMut(StreamItem),
+{
+    select(html.as_bytes(), selector, callback)
+}
+
+/// Element selector with its regular expressions compiled
+enum Stage {
+    Class(String, Option<Pattern>),
+    Id(String, Option<Pattern>),
+    Tag(String, Option<Pattern>),
+    Attr(String, Option<String>, Option<(Pattern, Option<Pattern>)>),
+}
+
+/// Selector compiled for streaming evaluation
+struct StreamSelector {
+    /// Element selectors, each searching the descendants of the previous one
+    stages: Vec<Stage>,
+
+    /// Optional text selector applied to the final matches
+    text: Option<TextNode>,
+
+    /// Pattern over the attribute name of a `#~name` or `?~name` text selector
+    text_attr: Option<Pattern>,
+}
+
+impl StreamSelector {
+    fn compile(selector: &str) -> InterpreterResult<Self> {
+        let ast = parse(selector).map_err(|e| InterpreterError::ParserError(e.to_string()))?;
+
+        let mut steps = Vec::new();
+        flatten_pipeline(&ast, &mut steps)?;
+
+        let mut stages = Vec::new();
+        let mut text = None;
+
+        for (i, step) in steps.iter().enumerate() {
+            match step {
+                SelectorNode::ElementSelector(elem) => stages.push(compile_stage(elem)?),
+                SelectorNode::TextSelector(TextNode::Markdown) => {
+                    return Err(InterpreterError::execution_error(
+                        "`markdown` needs the element tree and is not supported in streaming mode",
+                    ));
+                }
+                SelectorNode::TextSelector(TextNode::Links) => {
+                    return Err(InterpreterError::execution_error(
+                        "`links` needs the element tree and is not supported in streaming mode",
+                    ));
+                }
+                SelectorNode::TextSelector(TextNode::Value) => {
+                    return Err(InterpreterError::execution_error(
+                        "`value` needs the element tree and is not supported in streaming mode",
+                    ));
+                }
+                SelectorNode::TextSelector(node) if i == steps.len() - 1 => {
+                    text = Some(node.clone())
+                }
+                SelectorNode::TextSelector(_) => {
+                    return Err(InterpreterError::execution_error(
+                        "Text selectors must come last in a streaming selector",
+                    ));
+                }
+            }
+        }
+
+        if stages.is_empty() {
+            return Err(InterpreterError::execution_error(
+                "A streaming selector needs at least one element selector",
+            ));
+        }
+
+        let text_attr = match &text {
+            Some(TextNode::AttrValue(name, true)) | Some(TextNode::AttrPresent(name, true)) => {
+                Some(Pattern::new(name)?)
+            }
+            _ => None,
+        };
+
+        Ok(StreamSelector { stages, text, text_attr })
+    }
+}
+
+/// Collect the selectors of a pipeline in order
+pub(crate) fn flatten_pipeline(node: &Node, steps: &mut Vec<SelectorNode>) -> InterpreterResult<()> {
+    match node {
+        Node::Selector(selector) => {
+            steps.push(selector.as_ref().clone());
+            Ok(())
+        }
+        Node::Pipeline(left, right) => {
+            flatten_pipeline(left, steps)?;
+            flatten_pipeline(right, steps)
+        }
+        _ => Err(InterpreterError::execution_error(format!(
+            "Streaming mode only supports pipelines of class, id, tag, attr and text selectors, found {}",
+            node
+        ))),
+    }
+}
+
+fn compile_stage(elem: &ElementNode) -> InterpreterResult<Stage> {
+    let compile = |pattern: &str, is_regex: bool| -> InterpreterResult<Option<Pattern>> {
+        if is_regex {
+            Ok(Some(Pattern::new(pattern)?))
+        } else {
+            Ok(None)
+        }
+    };
+
+    match elem {
+        ElementNode::Class(value, is_regex) => Ok(Stage::Class(value.clone(), compile(value, *is_regex)?)),
+        ElementNode::Id(value, is_regex) => Ok(Stage::Id(value.clone(), compile(value, *is_regex)?)),
+        ElementNode::Tag(value, is_regex) => Ok(Stage::Tag(value.clone(), compile(value, *is_regex)?)),
+        ElementNode::Attr(name, value, is_regex) => {
+            let regexes = if *is_regex {
+                let value_re = match value {
+                    Some(value) => Some(Pattern::new(value)?),
+                    None => None,
+                };
+                Some((Pattern::new(name)?, value_re))
+            } else {
+                None
+            };
+            Ok(Stage::Attr(name.clone(), value.clone(), regexes))
+        }
+        _ => Err(InterpreterError::execution_error(format!(
+            "Streaming mode does not support the {} selector",
+            elem
+        ))),
+    }
+}
+
+/// Find an attribute by name, or by a regex over the name
+fn find_attr<'a>(attrs: &'a [Attribute], name: &str, re: Option<&Pattern>) -> Option<&'a Attribute> {
+    match re {
+        Some(re) => attrs.iter().find(|a| re.is_match(&a.name)),
+        None => attrs.iter().find(|a| a.name == name),
+    }
+}
+
+impl Stage {
+    fn matches(&self, name: &str, attrs: &[Attribute]) -> bool {
+        let attr = |attr_name: &str| attrs.iter().find(|a| a.name == attr_name);
+
+        match self {
+            Stage::Tag(tag, re) => match re {
+                Some(re) => re.is_match(name),
+                None => name == tag,
+            },
+            Stage::Class(class, re) => attr("class").is_some_and(|a| {
+                let mut classes = a.value.split_whitespace();
+                match re {
+                    Some(re) => classes.any(|c| re.is_match(c)),
+                    None => classes.any(|c| c == class),
+                }
+            }),
+            Stage::Id(id, re) => attr("id").is_some_and(|a| match re {
+                Some(re) => re.is_match(&a.value),
+                None => &a.value == id,
+            }),
+            Stage::Attr(attr_name, attr_value, regexes) => attrs.iter().any(|a| match regexes {
+                Some((name_re, value_re)) => {
+                    name_re.is_match(&a.name)
+                        && value_re.as_ref().is_none_or(|re| re.is_match(&a.value))
+                }
+                None => {
+                    &a.name == attr_name && attr_value.as_ref().is_none_or(|v| &a.value == v)
+                }
+            }),
+        }
+    }
+}
+
+/// An element on the open-element stack
+struct OpenElement {
+    name: String,
+
+    /// Number of leading stages (excluding the last) satisfied by this element or its ancestors
+    state: usize,
+
+    /// Text collected for a matched element when extracting `text` or `owntext`
+    text: Option<String>,
+}
+
+/// Token sink that tracks open elements and reports matches
+struct MatchSink<F: FnMut(StreamItem)> {
+    selector: StreamSelector,
+    stack: Vec<OpenElement>,
+    callback: F,
+}
+
+impl<F: FnMut(StreamItem)> MatchSink<F> {
+    fn new(selector: StreamSelector, callback: F) -> Self {
+        MatchSink {
+            selector,
+            stack: Vec::new(),
+            callback,
+        }
+    }
+
+    fn start_tag(&mut self, tag: Tag) -> TokenSinkResult<()> {
+        let name = tag.name.to_string();
+        let attrs: Vec<Attribute> = tag
+            .attrs
+            .iter()
+            .map(|a| Attribute {
+                name: a.name.local.to_string(),
+                value: a.value.to_string(),
+            })
+            .collect();
+
+        let stages = &self.selector.stages;
+        let last = stages.len() - 1;
+
+        // An element may satisfy several consecutive stages, mirroring the
+        // descendant-or-self search of the DOM interpreter
+        let mut state = self.stack.last().map_or(0, |e| e.state);
+        while state < last && stages[state].matches(&name, &attrs) {
+            state += 1;
+        }
+        let is_match = state == last && stages[last].matches(&name, &attrs);
+
+        let is_void = tag.self_closing || VOID_ELEMENTS.contains(&name.as_str());
+        let mut text = None;
+
+        if is_match {
+            match &self.selector.text {
+                None => (self.callback)(StreamItem::Element {
+                    name: name.clone(),
+                    attrs: attrs.clone(),
+                }),
+                Some(TextNode::Text) | Some(TextNode::OwnText) => text = Some(String::new()),
+                Some(TextNode::Href) => self.emit_attr(&attrs, "href"),
+                Some(TextNode::Src) => self.emit_attr(&attrs, "src"),
+                Some(TextNode::Data) => {
+                    for a in &attrs {
+                        if let Some(data_name) = a.name.strip_prefix("data-") {
+                            (self.callback)(StreamItem::Text(format!("{}={}", data_name, a.value)));
+                        }
+                    }
+                }
+                // Rejected when compiling the selector
+                Some(TextNode::Markdown) | Some(TextNode::Links) | Some(TextNode::Value) => {}
+                Some(TextNode::TagPath) => {
+                    let mut path: Vec<&str> = self.stack.iter().map(|e| e.name.as_str()).collect();
+                    path.push(&name);
+                    (self.callback)(StreamItem::Text(path.join("/")));
+                }
+                Some(TextNode::AttrValue(attr_name, _)) => {
+                    if let Some(a) = find_attr(&attrs, attr_name, self.selector.text_attr.as_ref()) {
+                        (self.callback)(StreamItem::Text(a.value.clone()));
+                    }
+                }
+                Some(TextNode::AttrPresent(attr_name, _)) => {
+                    let present = find_attr(&attrs, attr_name, self.selector.text_attr.as_ref()).is_some();
+                    (self.callback)(StreamItem::Text(present.to_string()));
+                }
+            }
+        }
+
+        if is_void {
+            if let Some(text) = text {
+                (self.callback)(StreamItem::Text(text));
+            }
+        } else {
+            self.stack.push(OpenElement { name: name.clone(), state, text });
+        }
+
+        // Switch the tokenizer into the right state for raw text elements
+        match name.as_str() {
+            "script" if !is_void => TokenSinkResult::RawData(RawKind::ScriptData),
+            "style" | "xmp" | "iframe" | "noembed" | "noframes" if !is_void => {
+                TokenSinkResult::RawData(RawKind::Rawtext)
+            }
+            "textarea" | "title" if !is_void => TokenSinkResult::RawData(RawKind::Rcdata),
+            "plaintext" => TokenSinkResult::Plaintext,
+            _ => TokenSinkResult::Continue,
+        }
+    }
+
+    fn end_tag(&mut self, name: &str) {
+        // Elements closed implicitly by this end tag are popped as well
+        if let Some(pos) = self.stack.iter().rposition(|e| e.name == name) {
+            while self.stack.len() > pos {
+                self.pop();
+            }
+        }
+    }
+
+    fn pop(&mut self) {
+        if let Some(OpenElement { text: Some(text), .. }) = self.stack.pop() {
+            (self.callback)(StreamItem::Text(text));
+        }
+    }
+
+    fn emit_attr(&mut self, attrs: &[Attribute], attr_name: &str) {
+        if let Some(a) = attrs.iter().find(|a| a.name == attr_name) {
+            (self.callback)(StreamItem::Text(a.value.clone()));
+        }
+    }
+}
+
+impl<F: FnMut(StreamItem)> TokenSink for MatchSink<F> {
+    type Handle = ();
+
+    fn process_token(&mut self, token: Token, _line_number: u64) -> TokenSinkResult<()> {
+        match token {
+            Token::TagToken(tag) => match tag.kind {
+                StartTag => return self.start_tag(tag),
+                EndTag => self.end_tag(&tag.name),
+            },
+            Token::CharacterTokens(chars) => {
+                if self.selector.text == Some(TextNode::OwnText) {
+                    // Only the innermost open element owns the text
+                    if let Some(OpenElement { text: Some(text), .. }) = self.stack.last_mut() {
+                        text.push_str(&chars);
+                    }
+                } else {
+                    for element in self.stack.iter_mut() {
+                        if let Some(text) = &mut element.text {
+                            text.push_str(&chars);
+                        }
+                    }
+                }
+            }
+            Token::EOFToken => {
+                while !self.stack.is_empty() {
+                    self.pop();
+                }
+            }
+            _ => {}
+        }
+
+        TokenSinkResult::Continue
+    }
+}